@@ -1,12 +1,42 @@
 use std::path::PathBuf;
 
-pub use clap::{Parser, Subcommand};
+pub use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[clap(about, long_about = None, version, author)]
 pub struct Cli {
     #[clap(subcommand)]
     pub command: CliCommand,
+
+    /// How to report the progress of long operations
+    #[clap(long, global = true, value_enum, default_value_t)]
+    pub progress: ProgressMode,
+
+    /// How to print the results and errors of commands
+    #[clap(long, global = true, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+/// How commands print their results on stdout and their errors on stderr
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// For humans
+    #[default]
+    Text,
+    /// One JSON object, for scripts
+    Json,
+}
+
+/// How long operations report their progress, on stderr
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// A progress bar
+    #[default]
+    Bar,
+    /// Nothing at all
+    Quiet,
+    /// One JSON object per event and line
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
@@ -17,18 +47,105 @@ pub enum CliCommand {
         value: Option<String>,
     },
 
+    /// Create a new vault protected by a passphrase and a recovery key
+    Init,
+
+    /// Regain access to the vault when the passphrase is lost
+    #[clap(group(ArgGroup::new("method").required(true)))]
+    Recover {
+        /// Use the recovery key words
+        #[clap(long, group = "method")]
+        mnemonic: bool,
+
+        /// Use the key shares
+        #[clap(long, group = "method")]
+        shares: bool,
+    },
+
+    /// Manage the key shares of the vault
+    Shares {
+        #[clap(subcommand)]
+        command: SharesCommand,
+    },
+
+    /// Generate and display a new recovery key, invalidating the old one
+    RecoveryKey,
+
+    /// Manage the public keys that can unlock the vault
+    Recipients {
+        #[clap(subcommand)]
+        command: RecipientsCommand,
+    },
+
     /// Get the status of the current database
     Status,
 
     /// Find something based on file name, path or tag name
     Find {
         query: String,
+
+        /// Only files modified since this date, as `2022-08-01` or RFC 3339
+        #[clap(long)]
+        modified_after: Option<String>,
+
+        /// Only files modified before this date, as `2022-08-01` or RFC 3339
+        #[clap(long)]
+        modified_before: Option<String>,
     },
 
-    /// Directly add a path without FUSE
+    /// Directly add files, directories and glob patterns without FUSE. Files are added by
+    /// name under prefix, directories with their contents under prefix
     Add {
-        target_path: PathBuf,
+        #[clap(required = true)]
+        target_paths: Vec<PathBuf>,
+
+        #[clap(long)]
+        prefix: Option<PathBuf>,
+
+        /// Only add the paths matching this glob, can be repeated
+        #[clap(long)]
+        include: Vec<String>,
+
+        /// Skip the paths matching this glob, can be repeated. `.kryptaignore` files are
+        /// always honored
+        #[clap(long)]
+        exclude: Vec<String>,
+
+        /// Add what can be read even if some paths cannot, which are listed anyway
+        #[clap(long)]
+        allow_partial: bool,
+
+        /// How many files are read at once, twice the CPUs by default
+        #[clap(long)]
+        io_concurrency: Option<usize>,
+
+        /// How many files are hashed and encrypted at once, one per CPU by default
+        #[clap(long)]
+        cpu_concurrency: Option<usize>,
+    },
+
+    /// Decrypt the files under prefix into destination, with their original times,
+    /// permissions and ownership
+    Extract {
+        destination: PathBuf,
+
+        #[clap(long)]
         prefix: Option<PathBuf>,
+
+        /// Rename the paths that would collide on case-insensitive filesystems, instead of
+        /// only warning about them
+        #[clap(long)]
+        rename_collisions: bool,
+    },
+
+    /// Encrypt stdin into path, like `pg_dump | krypta put backups/db.sql`
+    Put {
+        path: PathBuf,
+    },
+
+    /// Decrypt the file in path to stdout
+    Cat {
+        path: PathBuf,
     },
 
     /// Display files tree
@@ -40,9 +157,74 @@ pub enum CliCommand {
     /// Check that database and locked_path are in sync
     Check,
 
+    /// Keep the unlocked master key in memory for the other commands, until `krypta lock`
+    /// or until idle for `timeout` seconds
+    Agent {
+        #[clap(long, default_value_t = 900)]
+        timeout: u64,
+    },
+
+    /// Wipe the master key from the running agent
+    Lock,
+
+    /// Rotate the keys of every file, or only of the files under prefix
+    Rekey {
+        #[clap(long)]
+        prefix: Option<PathBuf>,
+
+        /// Rotate the master key too, which rotates the keys of every file
+        #[clap(long)]
+        master: bool,
+    },
+
+    /// Manage the database
+    Db {
+        #[clap(subcommand)]
+        command: DbCommand,
+    },
+
     #[cfg(debug_assertions)]
     /// Prune everything (debug mode only)
     Prune,
 
     Debug,
 }
+
+#[derive(Subcommand, Debug)]
+pub enum SharesCommand {
+    /// Split the vault key into shares, any `threshold` of which can unlock the vault
+    Split {
+        #[clap(long)]
+        threshold: u8,
+        #[clap(long)]
+        shares: u8,
+        /// Remove the passphrase so that no single person can unlock the vault
+        #[clap(long)]
+        remove_passphrase: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RecipientsCommand {
+    /// Allow a public key to unlock the vault
+    Add { recipient: String },
+
+    /// Remove a public key from the vault
+    Rm { recipient: String },
+
+    /// List the public keys that can unlock the vault
+    Ls,
+
+    /// Generate a new identity file and print its public key
+    Keygen { output: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Apply the pending schema migrations, which every command also does when needed
+    Migrate {
+        /// Only show the applied and the pending migrations
+        #[clap(long)]
+        status: bool,
+    },
+}
@@ -0,0 +1,260 @@
+use std::io::{Read, Write};
+
+use chacha20poly1305::{aead::Aead, KeyInit, XChaCha20Poly1305};
+use rayon::{
+    iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    slice::ParallelSlice,
+};
+
+use crate::errors::{CipherOperationError, CryptoError};
+
+use super::{stream::StreamSummary, NonceArray, SecretKey, AEAD_TAG_SIZE};
+
+/// Size of a plaintext chunk, all but the last one
+pub(crate) const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Size of an encrypted chunk, all but the last one
+const CIPHERTEXT_CHUNK_SIZE: usize = CHUNK_SIZE + AEAD_TAG_SIZE;
+
+/// Size of the encrypted chunk count which ends the ciphertext
+const TRAILER_SIZE: usize = 8 + AEAD_TAG_SIZE;
+
+/// The chunk count is sealed under an index that no chunk can have
+const TRAILER_INDEX: u64 = u64::MAX;
+
+/// Chunks held in memory at once, per file
+fn batch_chunks() -> usize {
+    rayon::current_num_threads().clamp(1, 16)
+}
+
+/// The nonce of the chunk in `index`: the file nonce with the index xored into its last
+/// bytes, so that every chunk of a file gets a distinct one
+fn chunk_nonce(nonce: &NonceArray, index: u64) -> NonceArray {
+    let mut chunk_nonce = *nonce;
+    let counter_start = chunk_nonce.len() - 8;
+
+    for (byte, index_byte) in chunk_nonce[counter_start..]
+        .iter_mut()
+        .zip(index.to_be_bytes())
+    {
+        *byte ^= index_byte;
+    }
+
+    chunk_nonce
+}
+
+fn stream_error(operation: CipherOperationError) -> CryptoError {
+    CryptoError::StreamCipherOperation(operation)
+}
+
+/// Seal `plaintexts`, the chunks that start at index `first`, in parallel
+fn encrypt_chunks(
+    aead: &XChaCha20Poly1305,
+    nonce: &NonceArray,
+    first: u64,
+    plaintexts: &[impl AsRef<[u8]> + Sync],
+) -> Result<Vec<Vec<u8>>, CryptoError> {
+    plaintexts
+        .par_iter()
+        .enumerate()
+        .map(|(offset, chunk)| {
+            aead.encrypt(&chunk_nonce(nonce, first + offset as u64), chunk.as_ref())
+                .map_err(|_| stream_error(CipherOperationError::EncryptChunk))
+        })
+        .collect()
+}
+
+/// Seal the count of chunks, which ends the ciphertext
+fn encrypt_trailer(
+    aead: &XChaCha20Poly1305,
+    nonce: &NonceArray,
+    chunks: u64,
+) -> Result<Vec<u8>, CryptoError> {
+    aead.encrypt(
+        &chunk_nonce(nonce, TRAILER_INDEX),
+        chunks.to_le_bytes().as_slice(),
+    )
+    .map_err(|_| stream_error(CipherOperationError::EncryptChunkCount))
+}
+
+/// Encrypt everything from `reader` into `writer` in krypta's chunked format, hashing the
+/// plaintext on the fly. Chunks of 1 MiB are sealed on their own with XChaCha20Poly1305
+/// under nonces derived from `nonce` and their index, so that the chunks of a batch are
+/// encrypted in parallel. The chunk count is sealed at the end, which makes a truncated
+/// ciphertext fail to decrypt
+pub fn encrypt_chunked(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    key: &SecretKey,
+    nonce: &NonceArray,
+) -> Result<StreamSummary, CryptoError> {
+    let aead = XChaCha20Poly1305::new(key.expose());
+    let batch_size = batch_chunks() * CHUNK_SIZE;
+
+    let mut hasher = blake3::Hasher::new();
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut size = 0;
+    let mut chunks = 0;
+
+    loop {
+        batch.clear();
+        reader
+            .by_ref()
+            .take(batch_size as u64)
+            .read_to_end(&mut batch)?;
+
+        hasher.update_rayon(&batch);
+        size += batch.len() as u64;
+
+        let plaintexts = batch.chunks(CHUNK_SIZE).collect::<Vec<_>>();
+        let ciphertexts = encrypt_chunks(&aead, nonce, chunks, &plaintexts)?;
+
+        for ciphertext in &ciphertexts {
+            writer.write_all(ciphertext)?;
+        }
+
+        chunks += ciphertexts.len() as u64;
+
+        if batch.len() < batch_size {
+            break;
+        }
+    }
+
+    writer.write_all(&encrypt_trailer(&aead, nonce, chunks)?)?;
+    writer.flush()?;
+
+    Ok(StreamSummary {
+        size,
+        hash: hasher.finalize(),
+    })
+}
+
+/// Decrypt everything from `reader` into `writer`, as written by `encrypt_chunked`, with the
+/// chunks of a batch decrypted in parallel. Returns the plaintext size. `writer` may have
+/// received some plaintext when the end turns out to be corrupted or missing
+pub fn decrypt_chunked(
+    reader: impl Read,
+    mut writer: impl Write,
+    key: &SecretKey,
+    nonce: &NonceArray,
+) -> Result<u64, CryptoError> {
+    let mut size = 0;
+
+    decrypt_batches(reader, key, nonce, |plaintexts| {
+        for plaintext in plaintexts {
+            writer.write_all(plaintext)?;
+            size += plaintext.len() as u64;
+        }
+
+        Ok(())
+    })?;
+
+    writer.flush()?;
+
+    Ok(size)
+}
+
+/// Decrypt everything from `reader`, as written by `encrypt_chunked` with `key` and `nonce`,
+/// and encrypt it again into `writer` with `new_key` and `new_nonce`. Chunks keep their
+/// index, so each batch goes through memory only and is hashed on the fly. `writer` may
+/// have received some ciphertext when the end turns out to be corrupted or missing
+pub fn reencrypt_chunked(
+    reader: impl Read,
+    mut writer: impl Write,
+    (key, nonce): (&SecretKey, &NonceArray),
+    (new_key, new_nonce): (&SecretKey, &NonceArray),
+) -> Result<StreamSummary, CryptoError> {
+    let new_aead = XChaCha20Poly1305::new(new_key.expose());
+
+    let mut hasher = blake3::Hasher::new();
+    let mut size = 0;
+    let mut chunks = 0;
+
+    decrypt_batches(reader, key, nonce, |plaintexts| {
+        for plaintext in plaintexts {
+            hasher.update_rayon(plaintext);
+            size += plaintext.len() as u64;
+        }
+
+        for ciphertext in encrypt_chunks(&new_aead, new_nonce, chunks, plaintexts)? {
+            writer.write_all(&ciphertext)?;
+        }
+
+        chunks += plaintexts.len() as u64;
+
+        Ok(())
+    })?;
+
+    writer.write_all(&encrypt_trailer(&new_aead, new_nonce, chunks)?)?;
+    writer.flush()?;
+
+    Ok(StreamSummary {
+        size,
+        hash: hasher.finalize(),
+    })
+}
+
+/// Decrypt the chunks from `reader` a batch at a time, in parallel, and hand each batch of
+/// plaintext to `each_batch`. The chunk count is checked once every batch has been handed
+fn decrypt_batches(
+    mut reader: impl Read,
+    key: &SecretKey,
+    nonce: &NonceArray,
+    mut each_batch: impl FnMut(&[Vec<u8>]) -> Result<(), CryptoError>,
+) -> Result<(), CryptoError> {
+    let aead = XChaCha20Poly1305::new(key.expose());
+    let batch_size = batch_chunks() * CIPHERTEXT_CHUNK_SIZE;
+
+    // Ciphertext not decrypted yet. A full batch is only decrypted once more ciphertext
+    // follows it, since the end holds a short chunk and the trailer
+    let mut pending = Vec::with_capacity(batch_size + TRAILER_SIZE);
+    let mut chunks = 0;
+
+    loop {
+        let missing = batch_size + TRAILER_SIZE - pending.len();
+        reader
+            .by_ref()
+            .take(missing as u64)
+            .read_to_end(&mut pending)?;
+
+        let is_last = pending.len() < batch_size + TRAILER_SIZE;
+
+        let batch_end = if is_last {
+            pending
+                .len()
+                .checked_sub(TRAILER_SIZE)
+                .ok_or(stream_error(CipherOperationError::DecryptChunkCount))?
+        } else {
+            batch_size
+        };
+
+        let plaintexts = pending[..batch_end]
+            .par_chunks(CIPHERTEXT_CHUNK_SIZE)
+            .enumerate()
+            .map(|(offset, chunk)| {
+                aead.decrypt(&chunk_nonce(nonce, chunks + offset as u64), chunk)
+                    .map_err(|_| stream_error(CipherOperationError::DecryptChunk))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        each_batch(&plaintexts)?;
+
+        chunks += plaintexts.len() as u64;
+        pending.drain(..batch_end);
+
+        if is_last {
+            break;
+        }
+    }
+
+    let count = aead
+        .decrypt(&chunk_nonce(nonce, TRAILER_INDEX), pending.as_slice())
+        .map_err(|_| stream_error(CipherOperationError::DecryptChunkCount))?;
+
+    // Authentic, so exactly 8 bytes
+    if u64::from_le_bytes(count.try_into().unwrap()) != chunks {
+        return Err(stream_error(CipherOperationError::DecryptChunkCount));
+    }
+
+    Ok(())
+}
@@ -1,28 +1,33 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    fs::{metadata, File},
+    io::{copy, BufWriter, Write},
     path::{Path, PathBuf},
 };
 
-use chacha20poly1305::{aead::stream, KeyInit, XChaCha20Poly1305};
-use memmap2::MmapOptions;
-
 use crate::{
-    errors::{CipherOperationError, CryptoError},
+    errors::CryptoError,
+    progress::ProgressReader,
+    source::SourceFile,
     traits::{ComputeBulk, ComputeUnit},
-    BUFFER_SIZE,
+    ReadMode,
 };
 
-use super::{KeyArray, NonceArray, PathPair, AEAD_NONCE_SIZE, AEAD_TAG_SIZE};
+use super::{
+    chunked::decrypt_chunked,
+    stream::{unwrap_io_error, DecryptReader},
+    CipherFormat, NonceArray, PathPair, SecretKey,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct FileDecryptUnit {
     // The source file
     locked_path: PathBuf,
     // The destination file
     unlocked_path: PathBuf,
-    key: KeyArray,
+    key: SecretKey,
     nonce: NonceArray,
+    read_mode: ReadMode,
+    format: CipherFormat,
 }
 
 impl From<&FileDecryptUnit> for PathPair {
@@ -38,7 +43,7 @@ impl FileDecryptUnit {
     pub fn try_new<P: AsRef<Path>>(
         locked_path: P,
         unlocked_path: P,
-        key: KeyArray,
+        key: SecretKey,
         nonce: NonceArray,
     ) -> Result<FileDecryptUnit, CryptoError> {
         let locked_path = locked_path.as_ref().to_path_buf();
@@ -51,90 +56,89 @@ impl FileDecryptUnit {
             unlocked_path: unlocked_path.as_ref().to_path_buf(),
             key,
             nonce,
+            read_mode: ReadMode::default(),
+            format: CipherFormat::default(),
         })
     }
+
+    /// How to read the locked file, `ReadMode::Auto` by default
+    pub fn read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// How the ciphertext is laid out, `CipherFormat::Stream` by default
+    pub fn format(mut self, format: CipherFormat) -> Self {
+        self.format = format;
+        self
+    }
 }
 
 impl ComputeUnit for FileDecryptUnit {
     type Output = ();
 
-    /// Try to encrypt a file as specified in struct
+    /// Try to decrypt a file as specified in struct
     fn start(self) -> Result<Self::Output, CryptoError> {
-        let locked_file = File::open(&self.locked_path)?;
-        let unlocked_file = File::create(&self.unlocked_path)?;
-
-        let aead = XChaCha20Poly1305::new(&self.key);
-
-        let nonce: &[u8; AEAD_NONCE_SIZE - 4] =
-            self.nonce[0..AEAD_NONCE_SIZE - 4].try_into().unwrap();
-        let mut stream_decryptor = stream::DecryptorLE31::from_aead(aead, nonce.into());
-
-        // Zero-sized files cannot be mmapped into memory
-        if locked_file.metadata()?.len() == 0 {
-            let mut unlocked_file_buf = BufWriter::new(unlocked_file);
-            let empty: &[u8] = &[];
-
-            let plaintext = stream_decryptor.decrypt_next(empty).map_err(|_| {
-                CryptoError::CipherOperationError(
-                    CipherOperationError::DecryptNext,
-                    PathPair::from(&self),
-                )
-            })?;
-            unlocked_file_buf.write_all(&plaintext)?;
+        self.start_with_progress(&mut |_| ())
+    }
 
-            return Ok(());
-        }
+    /// The size of the locked file
+    fn size(&self) -> Option<u64> {
+        metadata(&self.locked_path)
+            .ok()
+            .map(|metadata| metadata.len())
+    }
 
-        // SAFETY: nobody else is accessing this file
-        let locked_file_map = unsafe { MmapOptions::new().map(&locked_file)? };
+    /// Decrypt, reporting the ciphertext bytes read
+    fn start_with_progress(
+        self,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<Self::Output, CryptoError> {
+        let mut locked_file = SourceFile::open(&self.locked_path, self.read_mode)?;
+        let unlocked_file = File::create(&self.unlocked_path)?;
         let mut unlocked_file_buf = BufWriter::new(unlocked_file);
+        let mut locked_reader = ProgressReader::new(&mut locked_file, progress);
 
-        let mut locked_chunks = locked_file_map
-            .chunks(BUFFER_SIZE + AEAD_TAG_SIZE)
-            .peekable();
-
-        // Encrypt and write loop
-        let last_chunk = loop {
-            // This should never be None since we look ahead with peek and break early if
-            // next is the last chunk
-            let chunk = locked_chunks.next().unwrap();
+        let decrypted = match self.format {
+            CipherFormat::Stream => {
+                let mut decrypt_reader =
+                    DecryptReader::new(&mut locked_reader, &self.key, &self.nonce);
 
-            if locked_chunks.peek().is_none() {
-                break chunk;
+                copy(&mut decrypt_reader, &mut unlocked_file_buf)
+                    .and_then(|_| unlocked_file_buf.flush())
+                    .map_err(unwrap_io_error)
+                    .map(|_| ())
             }
-
-            let plaintext = stream_decryptor.decrypt_next(chunk).map_err(|_| {
-                CryptoError::CipherOperationError(
-                    CipherOperationError::DecryptNext,
-                    PathPair::from(&self),
-                )
-            })?;
-            unlocked_file_buf.write_all(&plaintext)?;
-        };
-
-        // decrypt_last consume and must be called at the very end
-        let plaintext = stream_decryptor.decrypt_last(last_chunk).map_err(|_| {
-            CryptoError::CipherOperationError(
-                CipherOperationError::DecryptLast,
-                PathPair::from(&self),
+            CipherFormat::Chunked => decrypt_chunked(
+                &mut locked_reader,
+                &mut unlocked_file_buf,
+                &self.key,
+                &self.nonce,
             )
-        })?;
-        unlocked_file_buf.write_all(&plaintext)?;
+            .map(|_| ()),
+        };
 
-        Ok(())
+        decrypted
+            .and_then(|_| locked_file.check_unchanged())
+            .map_err(|error| match error {
+                CryptoError::StreamCipherOperation(operation) => {
+                    CryptoError::CipherOperationError(operation, PathPair::from(&self))
+                }
+                error => error,
+            })
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct FileDecryptBulk {
     decryptors: Vec<FileDecryptUnit>,
 }
 
 impl FileDecryptBulk {
-    pub fn new(decryptors: impl IntoIterator<Item = FileDecryptUnit>) -> Box<Self> {
-        Box::new(Self {
+    pub fn new(decryptors: impl IntoIterator<Item = FileDecryptUnit>) -> Self {
+        Self {
             decryptors: decryptors.into_iter().collect(),
-        })
+        }
     }
 }
 
@@ -143,8 +147,8 @@ impl ComputeBulk for FileDecryptBulk {
     type Output = Result<(), CryptoError>;
     type Key = PathBuf;
 
-    fn units(&self) -> Vec<Self::Compute> {
-        self.decryptors.clone()
+    fn units(self) -> Vec<Self::Compute> {
+        self.decryptors
     }
 
     fn map_key(unit: &<Self as ComputeBulk>::Compute) -> Self::Key {
@@ -1,28 +1,33 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    fs::{metadata, File},
+    io::BufWriter,
     path::{Path, PathBuf},
 };
 
-use chacha20poly1305::{aead::stream, KeyInit, XChaCha20Poly1305};
-use memmap2::MmapOptions;
-
 use crate::{
-    errors::{CipherOperationError, CryptoError},
+    errors::CryptoError,
+    progress::{ProgressReader, ProgressWriter},
+    source::SourceFile,
     traits::{ComputeBulk, ComputeUnit},
-    BUFFER_SIZE,
+    ReadMode,
 };
 
-use super::{KeyArray, NonceArray, PathPair, AEAD_NONCE_SIZE};
+use super::{
+    chunked::encrypt_chunked,
+    stream::{unwrap_io_error, EncryptWriter},
+    CipherFormat, NonceArray, PathPair, SecretKey,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct FileEncryptUnit {
     // The source file
     unlocked_path: PathBuf,
     // The destination file
     locked_path: PathBuf,
-    key: KeyArray,
+    key: SecretKey,
     nonce: NonceArray,
+    read_mode: ReadMode,
+    format: CipherFormat,
 }
 
 impl From<&FileEncryptUnit> for PathPair {
@@ -38,7 +43,7 @@ impl FileEncryptUnit {
     pub fn try_new<P: AsRef<Path>>(
         unlocked_path: P,
         locked_path: P,
-        key: KeyArray,
+        key: SecretKey,
         nonce: NonceArray,
     ) -> Result<FileEncryptUnit, CryptoError> {
         let unlocked_path = unlocked_path.as_ref().to_path_buf();
@@ -51,8 +56,22 @@ impl FileEncryptUnit {
             locked_path: locked_path.as_ref().to_path_buf(),
             key,
             nonce,
+            read_mode: ReadMode::default(),
+            format: CipherFormat::default(),
         })
     }
+
+    /// How to read the source file, `ReadMode::Auto` by default
+    pub fn read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// How to lay out the ciphertext, `CipherFormat::Stream` by default
+    pub fn format(mut self, format: CipherFormat) -> Self {
+        self.format = format;
+        self
+    }
 }
 
 impl ComputeUnit for FileEncryptUnit {
@@ -60,80 +79,64 @@ impl ComputeUnit for FileEncryptUnit {
 
     /// Try to encrypt a file as specified in struct
     fn start(self) -> Result<Self::Output, CryptoError> {
-        let unlocked_file = File::open(&self.unlocked_path)?;
-        let locked_file = File::create(&self.locked_path)?;
-
-        let aead = XChaCha20Poly1305::new(&self.key);
-
-        let nonce: &[u8; AEAD_NONCE_SIZE - 4] =
-            self.nonce[0..AEAD_NONCE_SIZE - 4].try_into().unwrap();
-        let mut stream_encryptor = stream::EncryptorLE31::from_aead(aead, nonce.into());
-
-        // Zero-sized files cannot be mmapped into memory
-        if unlocked_file.metadata()?.len() == 0 {
-            let mut locked_file_buf = BufWriter::new(locked_file);
-            let empty: &[u8] = &[];
-
-            let ciphertext = stream_encryptor.encrypt_last(empty).map_err(|_| {
-                CryptoError::CipherOperationError(
-                    CipherOperationError::EncryptLast,
-                    PathPair::from(&self),
-                )
-            })?;
-
-            locked_file_buf.write_all(&ciphertext)?;
-
-            return Ok(());
-        }
-
-        // SAFETY: nobody else is accessing this file
-        let unlocked_file_map = unsafe { MmapOptions::new().map(&unlocked_file)? };
-        let mut locked_file_buf = BufWriter::new(locked_file);
-
-        let mut unlocked_chunks = unlocked_file_map.chunks(BUFFER_SIZE).peekable();
+        self.start_with_progress(&mut |_| ())
+    }
 
-        // Encrypt and write loop
-        let last_chunk = loop {
-            // This should never be None since we look ahead with peek and break early if
-            // next is the last chunk
-            let chunk = unlocked_chunks.next().unwrap();
+    /// The size of the source file
+    fn size(&self) -> Option<u64> {
+        metadata(&self.unlocked_path)
+            .ok()
+            .map(|metadata| metadata.len())
+    }
 
-            if unlocked_chunks.peek().is_none() {
-                break chunk;
+    /// Encrypt, reporting the plaintext bytes read
+    fn start_with_progress(
+        self,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<Self::Output, CryptoError> {
+        let mut unlocked_file = SourceFile::open(&self.unlocked_path, self.read_mode)?;
+        let locked_file = BufWriter::new(File::create(&self.locked_path)?);
+
+        let encrypted = match self.format {
+            CipherFormat::Stream => {
+                let mut encrypt_writer = EncryptWriter::new(locked_file, &self.key, &self.nonce);
+
+                unlocked_file
+                    .copy_to(&mut ProgressWriter::new(&mut encrypt_writer, progress))
+                    .map_err(unwrap_io_error)
+                    .and_then(|_| encrypt_writer.finish())
+                    .map(|_| ())
             }
-
-            let ciphertext = stream_encryptor.encrypt_next(chunk).map_err(|_| {
-                CryptoError::CipherOperationError(
-                    CipherOperationError::EncryptNext,
-                    PathPair::from(&self),
-                )
-            })?;
-            locked_file_buf.write_all(&ciphertext)?;
-        };
-
-        // encrypt_last consume and must be called at the very end
-        let ciphertext = stream_encryptor.encrypt_last(last_chunk).map_err(|_| {
-            CryptoError::CipherOperationError(
-                CipherOperationError::EncryptLast,
-                PathPair::from(&self),
+            CipherFormat::Chunked => encrypt_chunked(
+                ProgressReader::new(&mut unlocked_file, progress),
+                locked_file,
+                &self.key,
+                &self.nonce,
             )
-        })?;
-        locked_file_buf.write_all(&ciphertext)?;
+            .map(|_| ()),
+        };
 
-        Ok(())
+        encrypted
+            .and_then(|_| unlocked_file.check_unchanged())
+            .map_err(|error| match error {
+                CryptoError::StreamCipherOperation(operation) => {
+                    CryptoError::CipherOperationError(operation, PathPair::from(&self))
+                }
+                error => error,
+            })
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct FileEncryptBulk {
     encryptors: Vec<FileEncryptUnit>,
 }
 
 impl FileEncryptBulk {
-    pub fn new(encryptors: impl IntoIterator<Item = FileEncryptUnit>) -> Box<Self> {
-        Box::new(Self {
+    pub fn new(encryptors: impl IntoIterator<Item = FileEncryptUnit>) -> Self {
+        Self {
             encryptors: encryptors.into_iter().collect(),
-        })
+        }
     }
 }
 
@@ -142,8 +145,8 @@ impl ComputeBulk for FileEncryptBulk {
     type Output = Result<(), CryptoError>;
     type Key = PathBuf;
 
-    fn units(&self) -> Vec<Self::Compute> {
-        self.encryptors.clone()
+    fn units(self) -> Vec<Self::Compute> {
+        self.encryptors
     }
 
     fn map_key(unit: &<Self as ComputeBulk>::Compute) -> Self::Key {
@@ -0,0 +1,64 @@
+use std::io::{Read, Write};
+
+use crate::errors::CryptoError;
+
+use super::{
+    chunked::{decrypt_chunked, encrypt_chunked},
+    stream::{decrypt_stream, encrypt_stream, StreamSummary},
+    NonceArray, SecretKey,
+};
+
+/// Files this large are encrypted in the chunked format by `CipherFormat::for_size`
+const CHUNKED_MIN_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How the contents of a file are laid out once encrypted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CipherFormat {
+    /// The STREAM construction over 32 KiB chunks, encrypted one after the other
+    #[default]
+    Stream,
+    /// Independently nonced 1 MiB chunks and their count, encrypted in parallel
+    Chunked,
+}
+
+impl CipherFormat {
+    /// The format for a file of `size` bytes: chunked when it is large enough to keep
+    /// several cores busy
+    pub fn for_size(size: u64) -> Self {
+        if size >= CHUNKED_MIN_SIZE {
+            CipherFormat::Chunked
+        } else {
+            CipherFormat::Stream
+        }
+    }
+
+    /// Encrypt everything from `reader` into `writer` in this format, with
+    /// `encrypt_stream` or `encrypt_chunked`
+    pub fn encrypt(
+        self,
+        reader: impl Read,
+        writer: impl Write,
+        key: &SecretKey,
+        nonce: &NonceArray,
+    ) -> Result<StreamSummary, CryptoError> {
+        match self {
+            CipherFormat::Stream => encrypt_stream(reader, writer, key, nonce),
+            CipherFormat::Chunked => encrypt_chunked(reader, writer, key, nonce),
+        }
+    }
+
+    /// Decrypt everything from `reader` into `writer` from this format, with
+    /// `decrypt_stream` or `decrypt_chunked`
+    pub fn decrypt(
+        self,
+        reader: impl Read,
+        writer: impl Write,
+        key: &SecretKey,
+        nonce: &NonceArray,
+    ) -> Result<u64, CryptoError> {
+        match self {
+            CipherFormat::Stream => decrypt_stream(reader, writer, key, nonce),
+            CipherFormat::Chunked => decrypt_chunked(reader, writer, key, nonce),
+        }
+    }
+}
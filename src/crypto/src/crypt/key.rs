@@ -2,32 +2,47 @@ use chacha20poly1305::{AeadCore, KeyInit, XChaCha20Poly1305};
 use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
 
-use super::{KeyArray, NonceArray};
+use super::{NonceArray, SecretKey};
 
-pub fn generate_random_secure_key_nonce_pair() -> (KeyArray, NonceArray) {
+pub fn generate_random_secure_key_nonce_pair() -> (SecretKey, NonceArray) {
     // TODO: make sure that this Rng is crypto safe
     let mut rng = ChaCha20Rng::from_entropy();
 
-    let key = XChaCha20Poly1305::generate_key(&mut rng);
+    let key = SecretKey::from(XChaCha20Poly1305::generate_key(&mut rng));
     let nonce = XChaCha20Poly1305::generate_nonce(&mut rng);
 
     (key, nonce)
 }
 
+/// Generate a random key which is not tied to any nonce, such as the vault master key
+pub fn generate_random_secure_key() -> SecretKey {
+    let mut rng = ChaCha20Rng::from_entropy();
+    SecretKey::from(XChaCha20Poly1305::generate_key(&mut rng))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::crypt::{AEAD_KEY_SIZE, AEAD_NONCE_SIZE};
 
-    use super::generate_random_secure_key_nonce_pair;
+    use super::{generate_random_secure_key, generate_random_secure_key_nonce_pair};
 
     #[test]
     fn test_random_key() {
         let (key, nonce) = generate_random_secure_key_nonce_pair();
 
-        let key = key.as_slice();
+        let key = key.as_bytes();
         let nonce = nonce.as_slice();
 
         assert_ne!(key, [0u8; AEAD_KEY_SIZE]);
         assert_ne!(nonce, [0u8; AEAD_NONCE_SIZE]);
     }
+
+    #[test]
+    fn test_random_keys_are_different() {
+        let first = generate_random_secure_key();
+        let second = generate_random_secure_key();
+
+        assert_ne!(first.as_bytes(), [0u8; AEAD_KEY_SIZE]);
+        assert_ne!(first, second);
+    }
 }
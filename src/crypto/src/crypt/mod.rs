@@ -1,13 +1,24 @@
+mod chunked;
 mod decrypt;
 mod encrypt;
+mod format;
 mod key;
+mod passphrase;
+mod recipient;
+mod recovery;
+mod reencrypt;
+mod seal;
+mod secret;
+mod shares;
+mod stream;
+mod wrap;
 
 const AEAD_TAG_SIZE: usize = 16;
 pub const AEAD_KEY_SIZE: usize = 32;
 pub const AEAD_NONCE_SIZE: usize = 24;
 
-type KeyArray = GenericArray<u8, U32>;
-type NonceArray = GenericArray<u8, U24>;
+pub type KeyArray = GenericArray<u8, U32>;
+pub type NonceArray = GenericArray<u8, U24>;
 
 use std::{fmt::Display, path::PathBuf};
 
@@ -15,9 +26,20 @@ use chacha20poly1305::{
     aead::generic_array::GenericArray,
     consts::{U24, U32},
 };
+pub use chunked::{decrypt_chunked, encrypt_chunked, reencrypt_chunked};
 pub use decrypt::{FileDecryptBulk, FileDecryptUnit};
 pub use encrypt::{FileEncryptBulk, FileEncryptUnit};
-pub use key::generate_random_secure_key_nonce_pair;
+pub use format::CipherFormat;
+pub use key::{generate_random_secure_key, generate_random_secure_key_nonce_pair};
+pub use passphrase::{derive_key_from_passphrase, generate_random_salt, SALT_SIZE};
+pub use recipient::{unwrap_key_with_identity, wrap_key_for_recipient, Identity, Recipient};
+pub use recovery::RecoveryKey;
+pub use reencrypt::{FileReencryptBulk, FileReencryptUnit};
+pub use seal::{open_sealed, seal, seal_into};
+pub use secret::SecretKey;
+pub use shares::{combine_shares, split_key};
+pub use stream::{decrypt_stream, encrypt_stream, DecryptReader, EncryptWriter, StreamSummary};
+pub use wrap::{unwrap_key, wrap_key, KeyWrapper};
 
 #[derive(Debug)]
 pub struct PathPair {
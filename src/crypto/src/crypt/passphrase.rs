@@ -0,0 +1,49 @@
+use argon2::Argon2;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::errors::CryptoError;
+
+use super::SecretKey;
+
+pub const SALT_SIZE: usize = 16;
+
+/// Generate a random salt to be used with `derive_key_from_passphrase`
+pub fn generate_random_salt() -> [u8; SALT_SIZE] {
+    let mut rng = ChaCha20Rng::from_entropy();
+    let mut salt = [0u8; SALT_SIZE];
+    rng.fill_bytes(&mut salt);
+
+    salt
+}
+
+/// Derive a key from an user-provided passphrase using Argon2id
+pub fn derive_key_from_passphrase(
+    passphrase: impl AsRef<[u8]>,
+    salt: &[u8],
+) -> Result<SecretKey, CryptoError> {
+    let mut key = SecretKey::zeroed();
+
+    Argon2::default()
+        .hash_password_into(passphrase.as_ref(), salt, key.as_mut_bytes())
+        .map_err(|_| CryptoError::KeyDerivation)?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{derive_key_from_passphrase, generate_random_salt};
+
+    #[test]
+    fn test_derive_key_is_deterministic() {
+        let salt = generate_random_salt();
+
+        let first = derive_key_from_passphrase("correct horse battery staple", &salt).unwrap();
+        let second = derive_key_from_passphrase("correct horse battery staple", &salt).unwrap();
+        let other = derive_key_from_passphrase("wrong horse battery staple", &salt).unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+}
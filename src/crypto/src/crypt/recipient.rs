@@ -0,0 +1,226 @@
+use std::{fmt::Display, str::FromStr};
+
+use bech32::{FromBase32, ToBase32, Variant};
+use hkdf::Hkdf;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::errors::CryptoError;
+
+use super::{unwrap_key, wrap_key, SecretKey};
+
+const RECIPIENT_HRP: &str = "krypta";
+const IDENTITY_HRP: &str = "krypta-secret-key-";
+const HKDF_INFO: &[u8] = b"krypta-x25519";
+const X25519_KEY_SIZE: usize = 32;
+
+/// The public part of an X25519 key pair, which keys can be wrapped for
+#[derive(Clone, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+/// The secret part of an X25519 key pair, which can unwrap keys wrapped for its `Recipient`
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// Generate a new random identity
+    pub fn generate() -> Self {
+        let rng = ChaCha20Rng::from_entropy();
+        Identity(StaticSecret::random_from_rng(rng))
+    }
+
+    /// Get the recipient matching this identity
+    pub fn to_recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    /// Parse an identity file: empty lines and lines starting with # are skipped, the first
+    /// remaining line is the identity
+    pub fn from_identity_file(contents: impl AsRef<str>) -> Result<Self, CryptoError> {
+        let line = contents
+            .as_ref()
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or(CryptoError::InvalidIdentity)?;
+
+        line.parse()
+    }
+
+    /// Build the contents of an identity file
+    pub fn to_identity_file(&self) -> String {
+        format!("# public key: {}\n{}\n", self.to_recipient(), self)
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = Zeroizing::new(self.0.to_bytes());
+        let encoded = bech32_encode(IDENTITY_HRP, bytes.as_slice());
+        write!(f, "{}", encoded.to_uppercase())
+    }
+}
+
+impl FromStr for Identity {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes =
+            bech32_decode(IDENTITY_HRP, &s.to_lowercase()).ok_or(CryptoError::InvalidIdentity)?;
+
+        let identity = Identity(StaticSecret::from(bytes));
+        bytes.zeroize();
+
+        Ok(identity)
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", bech32_encode(RECIPIENT_HRP, self.0.as_bytes()))
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bech32_decode(RECIPIENT_HRP, s).ok_or(CryptoError::InvalidRecipient)?;
+
+        Ok(Recipient(PublicKey::from(bytes)))
+    }
+}
+
+impl std::fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Recipient({self})")
+    }
+}
+
+/// Encrypt `key` for `recipient`. The output is in the form of
+/// ephemeral public key || nonce || ciphertext
+pub fn wrap_key_for_recipient(
+    recipient: &Recipient,
+    key: &SecretKey,
+) -> Result<Vec<u8>, CryptoError> {
+    let ephemeral = Identity::generate();
+    let ephemeral_public = ephemeral.to_recipient();
+
+    let wrapping_key = derive_wrapping_key(&ephemeral, recipient, &ephemeral_public, recipient)?;
+
+    let mut wrapped = Vec::from(ephemeral_public.0.as_bytes().as_slice());
+    wrapped.extend(wrap_key(&wrapping_key, key)?);
+
+    Ok(wrapped)
+}
+
+/// Decrypt a key previously wrapped with `wrap_key_for_recipient`
+pub fn unwrap_key_with_identity(
+    identity: &Identity,
+    wrapped: &[u8],
+) -> Result<SecretKey, CryptoError> {
+    if wrapped.len() < X25519_KEY_SIZE {
+        return Err(CryptoError::KeyUnwrap);
+    }
+
+    let (ephemeral_public, wrapped) = wrapped.split_at(X25519_KEY_SIZE);
+    // Should never fail since the length has been checked above
+    let ephemeral_public: [u8; X25519_KEY_SIZE] = ephemeral_public.try_into().unwrap();
+    let ephemeral_public = Recipient(PublicKey::from(ephemeral_public));
+
+    let wrapping_key = derive_wrapping_key(
+        identity,
+        &ephemeral_public,
+        &ephemeral_public,
+        &identity.to_recipient(),
+    )?;
+
+    unwrap_key(&wrapping_key, wrapped)
+}
+
+/// Derive the symmetric wrapping key from the X25519 shared secret between `secret` and
+/// `their_public`, salted with both the ephemeral and the recipient public keys
+fn derive_wrapping_key(
+    secret: &Identity,
+    their_public: &Recipient,
+    ephemeral_public: &Recipient,
+    recipient: &Recipient,
+) -> Result<SecretKey, CryptoError> {
+    let shared_secret = secret.0.diffie_hellman(&their_public.0);
+
+    if !shared_secret.was_contributory() {
+        return Err(CryptoError::InvalidRecipient);
+    }
+
+    let mut salt = Vec::from(ephemeral_public.0.as_bytes().as_slice());
+    salt.extend_from_slice(recipient.0.as_bytes());
+
+    let mut wrapping_key = SecretKey::zeroed();
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret.as_bytes())
+        .expand(HKDF_INFO, wrapping_key.as_mut_bytes())
+        .map_err(|_| CryptoError::KeyDerivation)?;
+
+    Ok(wrapping_key)
+}
+
+fn bech32_encode(hrp: &str, bytes: &[u8]) -> String {
+    // Should never fail as the hrps are constant and valid
+    bech32::encode(hrp, bytes.to_base32(), Variant::Bech32).unwrap()
+}
+
+fn bech32_decode(expected_hrp: &str, s: &str) -> Option<[u8; X25519_KEY_SIZE]> {
+    let (hrp, data, variant) = bech32::decode(s).ok()?;
+
+    if hrp != expected_hrp || variant != Variant::Bech32 {
+        return None;
+    }
+
+    Vec::<u8>::from_base32(&data).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{crypt::generate_random_secure_key, errors::CryptoError};
+
+    use super::{unwrap_key_with_identity, wrap_key_for_recipient, Identity, Recipient};
+
+    #[test]
+    fn test_wrap_for_many_recipients() {
+        let key = generate_random_secure_key();
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+
+        let for_alice = wrap_key_for_recipient(&alice.to_recipient(), &key).unwrap();
+        let for_bob = wrap_key_for_recipient(&bob.to_recipient(), &key).unwrap();
+
+        assert_eq!(unwrap_key_with_identity(&alice, &for_alice).unwrap(), key);
+        assert_eq!(unwrap_key_with_identity(&bob, &for_bob).unwrap(), key);
+
+        assert!(matches!(
+            unwrap_key_with_identity(&bob, &for_alice),
+            Err(CryptoError::KeyUnwrap)
+        ));
+    }
+
+    #[test]
+    fn test_encoding_roundtrip() {
+        let identity = Identity::generate();
+        let recipient = identity.to_recipient();
+
+        let encoded = recipient.to_string();
+        assert!(encoded.starts_with("krypta1"));
+        assert_eq!(encoded.parse::<Recipient>().unwrap(), recipient);
+
+        let file = identity.to_identity_file();
+        assert!(file.starts_with("# public key: krypta1"));
+
+        let parsed = Identity::from_identity_file(file).unwrap();
+        assert_eq!(parsed.to_recipient(), recipient);
+
+        assert!("krypta1invalid".parse::<Recipient>().is_err());
+        assert!(identity.to_string().parse::<Recipient>().is_err());
+        assert!(Identity::from_identity_file("# only comments\n\n").is_err());
+    }
+}
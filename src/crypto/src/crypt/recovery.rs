@@ -0,0 +1,85 @@
+use bip39::Mnemonic;
+use zeroize::Zeroizing;
+
+use crate::errors::CryptoError;
+
+use super::{generate_random_secure_key, SecretKey};
+
+/// A random key that can be written down as a BIP39 word list and used to regain
+/// access to the vault when the passphrase is lost
+pub struct RecoveryKey(SecretKey);
+
+impl RecoveryKey {
+    /// Generate a new random recovery key
+    pub fn generate() -> Self {
+        RecoveryKey(generate_random_secure_key())
+    }
+
+    /// Parse a recovery key from its mnemonic representation
+    pub fn from_mnemonic(words: impl AsRef<str>) -> Result<Self, CryptoError> {
+        let words = Zeroizing::new(
+            words
+                .as_ref()
+                .split_whitespace()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+
+        let mnemonic =
+            Mnemonic::parse_normalized(&words).map_err(|_| CryptoError::InvalidMnemonic)?;
+        let entropy = Zeroizing::new(mnemonic.to_entropy());
+
+        let key = SecretKey::try_from_slice(&entropy).map_err(|_| CryptoError::InvalidMnemonic)?;
+
+        Ok(RecoveryKey(key))
+    }
+
+    /// Get the mnemonic representation of the recovery key, 24 words long
+    pub fn to_mnemonic(&self) -> String {
+        // Should never fail since the key is 256 bits long
+        Mnemonic::from_entropy(self.0.as_bytes())
+            .unwrap()
+            .to_string()
+    }
+
+    /// The key used for wrapping the master key
+    pub fn key(&self) -> &SecretKey {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::CryptoError;
+
+    use super::RecoveryKey;
+
+    #[test]
+    fn test_mnemonic_roundtrip() {
+        let recovery = RecoveryKey::generate();
+        let mnemonic = recovery.to_mnemonic();
+
+        assert_eq!(mnemonic.split_whitespace().count(), 24);
+
+        let parsed = RecoveryKey::from_mnemonic(&mnemonic).unwrap();
+        assert_eq!(parsed.key(), recovery.key());
+
+        // Extra whitespace and uppercase letters are tolerated
+        let sloppy = format!("  {}\n", mnemonic.to_uppercase().replace(' ', "   "));
+        let parsed = RecoveryKey::from_mnemonic(sloppy).unwrap();
+        assert_eq!(parsed.key(), recovery.key());
+    }
+
+    #[test]
+    fn test_invalid_mnemonic() {
+        let result = RecoveryKey::from_mnemonic("not a valid mnemonic");
+        assert!(matches!(result, Err(CryptoError::InvalidMnemonic)));
+
+        // Valid 12 words mnemonic, but too short for a key
+        let result = RecoveryKey::from_mnemonic(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        );
+        assert!(matches!(result, Err(CryptoError::InvalidMnemonic)));
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::{metadata, remove_file, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::CryptoError,
+    progress::ProgressReader,
+    source::SourceFile,
+    traits::{ComputeBulk, ComputeUnit},
+    ReadMode,
+};
+
+use super::{
+    chunked::reencrypt_chunked,
+    stream::{encrypt_stream, unwrap_io_error, DecryptReader, StreamSummary},
+    CipherFormat, NonceArray, PathPair, SecretKey,
+};
+
+/// Decrypts a locked file with its old key and encrypts it with a new one into another locked
+/// file, in the same format. The plaintext is only ever in memory
+#[derive(Debug)]
+pub struct FileReencryptUnit {
+    // The source file
+    old_locked_path: PathBuf,
+    // The destination file
+    new_locked_path: PathBuf,
+    old_key: SecretKey,
+    old_nonce: NonceArray,
+    new_key: SecretKey,
+    new_nonce: NonceArray,
+    read_mode: ReadMode,
+    format: CipherFormat,
+}
+
+impl From<&FileReencryptUnit> for PathPair {
+    fn from(unit: &FileReencryptUnit) -> Self {
+        PathPair {
+            source: unit.old_locked_path.clone(),
+            destination: unit.new_locked_path.clone(),
+        }
+    }
+}
+
+impl FileReencryptUnit {
+    pub fn try_new<P: AsRef<Path>>(
+        old_locked_path: P,
+        new_locked_path: P,
+        (old_key, old_nonce): (SecretKey, NonceArray),
+        (new_key, new_nonce): (SecretKey, NonceArray),
+    ) -> Result<FileReencryptUnit, CryptoError> {
+        let old_locked_path = old_locked_path.as_ref().to_path_buf();
+
+        // Make sure that the ciphertext exists
+        File::open(&old_locked_path)?;
+
+        Ok(FileReencryptUnit {
+            old_locked_path,
+            new_locked_path: new_locked_path.as_ref().to_path_buf(),
+            old_key,
+            old_nonce,
+            new_key,
+            new_nonce,
+            read_mode: ReadMode::default(),
+            format: CipherFormat::default(),
+        })
+    }
+
+    /// How to read the old locked file, `ReadMode::Auto` by default
+    pub fn read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// How both ciphertexts are laid out, `CipherFormat::Stream` by default
+    pub fn format(mut self, format: CipherFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn reencrypt(&self, progress: &mut dyn FnMut(u64)) -> Result<StreamSummary, CryptoError> {
+        let mut old_locked_file = SourceFile::open(&self.old_locked_path, self.read_mode)?;
+        let new_locked_file = BufWriter::new(File::create(&self.new_locked_path)?);
+        let mut old_locked_reader = ProgressReader::new(&mut old_locked_file, progress);
+
+        let summary = match self.format {
+            CipherFormat::Stream => encrypt_stream(
+                DecryptReader::new(&mut old_locked_reader, &self.old_key, &self.old_nonce),
+                new_locked_file,
+                &self.new_key,
+                &self.new_nonce,
+            )
+            .map_err(|error| match error {
+                // Decryption errors come through `Read`
+                CryptoError::InputOutput(error) => unwrap_io_error(error),
+                error => error,
+            })?,
+            CipherFormat::Chunked => reencrypt_chunked(
+                &mut old_locked_reader,
+                new_locked_file,
+                (&self.old_key, &self.old_nonce),
+                (&self.new_key, &self.new_nonce),
+            )?,
+        };
+
+        old_locked_file.check_unchanged()?;
+
+        Ok(summary)
+    }
+}
+
+impl ComputeUnit for FileReencryptUnit {
+    type Output = StreamSummary;
+
+    /// Re-encrypt a file as specified in struct
+    fn start(self) -> Result<Self::Output, CryptoError> {
+        self.start_with_progress(&mut |_| ())
+    }
+
+    /// The size of the old locked file
+    fn size(&self) -> Option<u64> {
+        metadata(&self.old_locked_path)
+            .ok()
+            .map(|metadata| metadata.len())
+    }
+
+    /// Re-encrypt, reporting the ciphertext bytes read. The new locked file is removed when
+    /// anything fails, and the summary tells what the plaintext was so that it can be checked
+    fn start_with_progress(
+        self,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<Self::Output, CryptoError> {
+        self.reencrypt(progress).map_err(|error| {
+            let _ = remove_file(&self.new_locked_path);
+
+            match error {
+                CryptoError::StreamCipherOperation(operation) => {
+                    CryptoError::CipherOperationError(operation, PathPair::from(&self))
+                }
+                error => error,
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FileReencryptBulk {
+    reencryptors: Vec<FileReencryptUnit>,
+}
+
+impl FileReencryptBulk {
+    pub fn new(reencryptors: impl IntoIterator<Item = FileReencryptUnit>) -> Self {
+        Self {
+            reencryptors: reencryptors.into_iter().collect(),
+        }
+    }
+}
+
+impl ComputeBulk for FileReencryptBulk {
+    type Compute = FileReencryptUnit;
+    type Output = Result<StreamSummary, CryptoError>;
+    /// The new locked file, since the old one may be shared by several files
+    type Key = PathBuf;
+
+    fn units(self) -> Vec<Self::Compute> {
+        self.reencryptors
+    }
+
+    fn map_key(unit: &<Self as ComputeBulk>::Compute) -> Self::Key {
+        unit.new_locked_path.clone()
+    }
+
+    fn map_output(
+        result: Result<<<Self as ComputeBulk>::Compute as ComputeUnit>::Output, CryptoError>,
+    ) -> Self::Output {
+        result
+    }
+}
@@ -0,0 +1,122 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadInPlace, Payload},
+    AeadCore, KeyInit, XChaCha20Poly1305,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use zeroize::Zeroize;
+
+use crate::errors::CryptoError;
+
+use super::{NonceArray, SecretKey, AEAD_NONCE_SIZE, AEAD_TAG_SIZE};
+
+/// Encrypt some in-memory `plaintext` at once, authenticating `associated_data` along with
+/// it. The output is in the form of nonce || ciphertext
+pub fn seal(
+    key: &SecretKey,
+    plaintext: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let mut sealed = vec![];
+    seal_into(key, plaintext, associated_data, &mut sealed)?;
+
+    Ok(sealed)
+}
+
+/// Like `seal`, but append nonce || ciphertext to `out`. The plaintext is copied there once
+/// and encrypted in place, so that large inputs do not need twice their size
+pub fn seal_into(
+    key: &SecretKey,
+    plaintext: &[u8],
+    associated_data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), CryptoError> {
+    let mut rng = ChaCha20Rng::from_entropy();
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut rng);
+
+    out.reserve(AEAD_NONCE_SIZE + plaintext.len() + AEAD_TAG_SIZE);
+    out.extend_from_slice(nonce.as_slice());
+
+    let start = out.len();
+    out.extend_from_slice(plaintext);
+
+    let aead = XChaCha20Poly1305::new(key.expose());
+
+    match aead.encrypt_in_place_detached(&nonce, associated_data, &mut out[start..]) {
+        Ok(tag) => {
+            out.extend_from_slice(&tag);
+            Ok(())
+        }
+        Err(_) => {
+            out[start..].zeroize();
+            out.truncate(start - AEAD_NONCE_SIZE);
+            Err(CryptoError::Seal)
+        }
+    }
+}
+
+/// Decrypt something previously encrypted with `seal` and the same `associated_data`
+pub fn open_sealed(
+    key: &SecretKey,
+    sealed: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < AEAD_NONCE_SIZE {
+        return Err(CryptoError::Open);
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(AEAD_NONCE_SIZE);
+    let nonce = NonceArray::from_slice(nonce);
+
+    let aead = XChaCha20Poly1305::new(key.expose());
+    let payload = Payload {
+        msg: ciphertext,
+        aad: associated_data,
+    };
+
+    aead.decrypt(nonce, payload).map_err(|_| CryptoError::Open)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{crypt::generate_random_secure_key, errors::CryptoError};
+
+    use super::{open_sealed, seal, seal_into};
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = generate_random_secure_key();
+        let plaintext = b"some catalog contents";
+
+        let sealed = seal(&key, plaintext, b"header").unwrap();
+        assert_eq!(open_sealed(&key, &sealed, b"header").unwrap(), plaintext);
+
+        // Wrong key, wrong associated data and truncated input are all rejected
+        let other = generate_random_secure_key();
+        assert!(matches!(
+            open_sealed(&other, &sealed, b"header"),
+            Err(CryptoError::Open)
+        ));
+        assert!(matches!(
+            open_sealed(&key, &sealed, b"tampered"),
+            Err(CryptoError::Open)
+        ));
+        assert!(matches!(
+            open_sealed(&key, &sealed[..10], b"header"),
+            Err(CryptoError::Open)
+        ));
+    }
+
+    #[test]
+    fn test_seal_into() {
+        let key = generate_random_secure_key();
+        let plaintext = b"some catalog contents";
+
+        let mut out = b"header".to_vec();
+        seal_into(&key, plaintext, b"header", &mut out).unwrap();
+
+        let (header, sealed) = out.split_at(6);
+        assert_eq!(header, b"header");
+        assert_eq!(open_sealed(&key, sealed, header).unwrap(), plaintext);
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::Debug;
+
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+use crate::errors::CryptoError;
+
+use super::{KeyArray, AEAD_KEY_SIZE};
+
+/// A 256 bit symmetric key which is wiped from memory when dropped. It is boxed so that
+/// moving it around does not leave copies on the stack, it cannot be cloned and it is
+/// never printed by `Debug`
+pub struct SecretKey(Box<[u8; AEAD_KEY_SIZE]>);
+
+impl SecretKey {
+    /// A key made of zeros, to be filled in place by a key derivation function
+    pub(crate) fn zeroed() -> Self {
+        SecretKey(Box::new([0u8; AEAD_KEY_SIZE]))
+    }
+
+    /// Copy a key out of `bytes`, which the caller is responsible for wiping
+    pub fn try_from_slice(bytes: &[u8]) -> Result<Self, CryptoError> {
+        if bytes.len() != AEAD_KEY_SIZE {
+            return Err(CryptoError::InvalidKeyLength(bytes.len()));
+        }
+
+        let mut key = Self::zeroed();
+        key.0.copy_from_slice(bytes);
+
+        Ok(key)
+    }
+
+    /// Get the key in the form expected by the ciphers
+    pub fn expose(&self) -> &KeyArray {
+        KeyArray::from_slice(self.0.as_slice())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    pub(crate) fn as_mut_bytes(&mut self) -> &mut [u8] {
+        self.0.as_mut_slice()
+    }
+}
+
+/// Move a key generated by the ciphers into a `SecretKey`, wiping the original
+impl From<KeyArray> for SecretKey {
+    fn from(mut key: KeyArray) -> Self {
+        let mut secret = Self::zeroed();
+        secret.0.copy_from_slice(key.as_slice());
+        key.as_mut_slice().zeroize();
+
+        secret
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl PartialEq for SecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(other.0.as_ref()).into()
+    }
+}
+
+impl Eq for SecretKey {}
+
+impl Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretKey(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        crypt::{generate_random_secure_key, KeyArray, AEAD_KEY_SIZE},
+        errors::CryptoError,
+    };
+
+    use super::SecretKey;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let key = SecretKey::try_from_slice(&[0xab; AEAD_KEY_SIZE]).unwrap();
+        let debug = format!("{key:?}");
+
+        assert_eq!(debug, "SecretKey(<redacted>)");
+        assert!(!debug.contains("ab") && !debug.contains("171"));
+    }
+
+    #[test]
+    fn test_from_slice_and_array() {
+        let key = generate_random_secure_key();
+
+        let copy = SecretKey::try_from_slice(key.as_bytes()).unwrap();
+        assert_eq!(copy, key);
+        assert_ne!(copy, generate_random_secure_key());
+
+        let from_array = SecretKey::from(*KeyArray::from_slice(key.as_bytes()));
+        assert_eq!(from_array, key);
+
+        assert!(matches!(
+            SecretKey::try_from_slice(&[0u8; 4]),
+            Err(CryptoError::InvalidKeyLength(4))
+        ));
+    }
+}
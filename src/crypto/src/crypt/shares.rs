@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use sharks::{Share, Sharks};
+use zeroize::Zeroizing;
+
+use crate::errors::CryptoError;
+
+use super::SecretKey;
+
+/// Split `key` into `count` hex encoded shares, any `threshold` of which can rebuild it
+pub fn split_key(key: &SecretKey, threshold: u8, count: u8) -> Result<Vec<String>, CryptoError> {
+    if threshold < 2 || threshold > count {
+        return Err(CryptoError::InvalidShareThreshold(threshold, count));
+    }
+
+    let mut rng = ChaCha20Rng::from_entropy();
+
+    let shares = Sharks(threshold)
+        .dealer_rng(key.as_bytes(), &mut rng)
+        .take(count as usize)
+        .map(|share| hex::encode(Zeroizing::new(Vec::from(&share))))
+        .collect();
+
+    Ok(shares)
+}
+
+/// Rebuild a key from at least `threshold` shares generated by `split_key`
+pub fn combine_shares(threshold: u8, shares: &[impl AsRef<str>]) -> Result<SecretKey, CryptoError> {
+    // Shares with the same x coordinate would break the interpolation
+    let shares = shares
+        .iter()
+        .map(|share| {
+            let bytes = Zeroizing::new(
+                hex::decode(share.as_ref().trim()).map_err(|_| CryptoError::InvalidShare)?,
+            );
+            let share = Share::try_from(bytes.as_slice()).map_err(|_| CryptoError::InvalidShare)?;
+
+            Ok((bytes[0], share))
+        })
+        .collect::<Result<HashMap<_, _>, CryptoError>>()?;
+
+    let key = Zeroizing::new(
+        Sharks(threshold)
+            .recover(shares.values())
+            .map_err(|_| CryptoError::InvalidShare)?,
+    );
+
+    SecretKey::try_from_slice(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{crypt::generate_random_secure_key, errors::CryptoError};
+
+    use super::{combine_shares, split_key};
+
+    #[test]
+    fn test_split_and_combine() {
+        let key = generate_random_secure_key();
+        let shares = split_key(&key, 3, 5).unwrap();
+
+        assert_eq!(shares.len(), 5);
+
+        // Any 3 shares rebuild the key
+        assert_eq!(combine_shares(3, &shares[0..3]).unwrap(), key);
+        assert_eq!(combine_shares(3, &shares[2..5]).unwrap(), key);
+        assert_eq!(
+            combine_shares(3, &[&shares[4], &shares[0], &shares[2]]).unwrap(),
+            key
+        );
+
+        // Two shares, or the same share repeated, are not enough
+        assert!(matches!(
+            combine_shares(3, &shares[0..2]),
+            Err(CryptoError::InvalidShare)
+        ));
+        assert!(matches!(
+            combine_shares(3, &[&shares[0], &shares[0], &shares[1]]),
+            Err(CryptoError::InvalidShare)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_threshold_and_shares() {
+        let key = generate_random_secure_key();
+
+        assert!(split_key(&key, 1, 5).is_err());
+        assert!(split_key(&key, 6, 5).is_err());
+
+        assert!(matches!(
+            combine_shares(2, &["not hex", "00"]),
+            Err(CryptoError::InvalidShare)
+        ));
+    }
+}
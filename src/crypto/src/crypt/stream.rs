@@ -0,0 +1,266 @@
+use std::{
+    cmp::min,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use chacha20poly1305::{
+    aead::stream::{DecryptorLE31, EncryptorLE31},
+    KeyInit, XChaCha20Poly1305,
+};
+
+use crate::{
+    errors::{CipherOperationError, CryptoError},
+    BUFFER_SIZE,
+};
+
+use super::{NonceArray, SecretKey, AEAD_NONCE_SIZE, AEAD_TAG_SIZE};
+
+/// Size of an encrypted chunk, all but the last one
+const CIPHERTEXT_CHUNK_SIZE: usize = BUFFER_SIZE + AEAD_TAG_SIZE;
+
+/// The nonce prefix used by the STREAM construction, the last bytes are its counter
+fn stream_nonce(nonce: &NonceArray) -> &[u8; AEAD_NONCE_SIZE - 4] {
+    nonce[0..AEAD_NONCE_SIZE - 4].try_into().unwrap()
+}
+
+/// Wraps a `CryptoError` so that it can go through `Read` and `Write`
+fn cipher_error(operation: CipherOperationError) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        CryptoError::StreamCipherOperation(operation),
+    )
+}
+
+/// Take back a `CryptoError` that went through `Read` or `Write`
+pub(crate) fn unwrap_io_error(error: io::Error) -> CryptoError {
+    if error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<CryptoError>())
+    {
+        // Just checked the type
+        *error
+            .into_inner()
+            .unwrap()
+            .downcast::<CryptoError>()
+            .unwrap()
+    } else {
+        CryptoError::InputOutput(error)
+    }
+}
+
+/// Encrypts everything written to it into `writer`, in krypta's format: the STREAM
+/// construction (LE31) of XChaCha20Poly1305 over 32 KiB chunks, the last one sealed as such.
+/// `finish` must be called at the end, otherwise the ciphertext is truncated and will not
+/// decrypt
+pub struct EncryptWriter<W: Write> {
+    writer: W,
+    encryptor: EncryptorLE31<XChaCha20Poly1305>,
+    /// Plaintext waiting to be encrypted, since the last chunk can only be told apart when
+    /// `finish` is called
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn new(writer: W, key: &SecretKey, nonce: &NonceArray) -> Self {
+        let aead = XChaCha20Poly1305::new(key.expose());
+
+        EncryptWriter {
+            writer,
+            encryptor: EncryptorLE31::from_aead(aead, stream_nonce(nonce).into()),
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+        }
+    }
+
+    /// Encrypt the last chunk, flush and give back the inner writer
+    pub fn finish(self) -> Result<W, CryptoError> {
+        let EncryptWriter {
+            mut writer,
+            encryptor,
+            buffer,
+        } = self;
+
+        let ciphertext = encryptor
+            .encrypt_last(buffer.as_slice())
+            .map_err(|_| CryptoError::StreamCipherOperation(CipherOperationError::EncryptLast))?;
+
+        writer.write_all(&ciphertext)?;
+        writer.flush()?;
+
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // More plaintext is coming, so a full buffer is not the last chunk
+        if self.buffer.len() == BUFFER_SIZE {
+            let ciphertext = self
+                .encryptor
+                .encrypt_next(self.buffer.as_slice())
+                .map_err(|_| cipher_error(CipherOperationError::EncryptNext))?;
+
+            self.writer.write_all(&ciphertext)?;
+            self.buffer.clear();
+        }
+
+        let written = min(BUFFER_SIZE - self.buffer.len(), buf.len());
+        self.buffer.extend_from_slice(&buf[..written]);
+
+        Ok(written)
+    }
+
+    /// Flush the inner writer. Up to a chunk of plaintext stays buffered until `finish`
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Decrypts what `reader` gives, as written by `EncryptWriter`. Plaintext is only returned
+/// once its chunk is authenticated, and a missing or corrupted end is an error instead of
+/// a silent end of file
+pub struct DecryptReader<R: Read> {
+    reader: R,
+    /// `None` once the last chunk has been decrypted
+    decryptor: Option<DecryptorLE31<XChaCha20Poly1305>>,
+    /// The ciphertext chunk read ahead, which is the last one when nothing follows it
+    next_chunk: Option<Vec<u8>>,
+    plaintext: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn new(reader: R, key: &SecretKey, nonce: &NonceArray) -> Self {
+        let aead = XChaCha20Poly1305::new(key.expose());
+
+        DecryptReader {
+            reader,
+            decryptor: Some(DecryptorLE31::from_aead(aead, stream_nonce(nonce).into())),
+            next_chunk: None,
+            plaintext: vec![],
+            position: 0,
+        }
+    }
+
+    /// Give back the inner reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn decrypt_chunk(&mut self) -> io::Result<()> {
+        let chunk = match self.next_chunk.take() {
+            Some(chunk) => chunk,
+            None => read_chunk(&mut self.reader, CIPHERTEXT_CHUNK_SIZE)?,
+        };
+
+        if chunk.len() == CIPHERTEXT_CHUNK_SIZE {
+            self.next_chunk = Some(read_chunk(&mut self.reader, CIPHERTEXT_CHUNK_SIZE)?);
+        }
+
+        let is_last = self.next_chunk.as_ref().is_none_or(Vec::is_empty);
+        let mut decryptor = self.decryptor.take().unwrap();
+
+        self.plaintext = if is_last {
+            decryptor
+                .decrypt_last(chunk.as_slice())
+                .map_err(|_| cipher_error(CipherOperationError::DecryptLast))?
+        } else {
+            let plaintext = decryptor
+                .decrypt_next(chunk.as_slice())
+                .map_err(|_| cipher_error(CipherOperationError::DecryptNext))?;
+            self.decryptor = Some(decryptor);
+
+            plaintext
+        };
+
+        self.position = 0;
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+
+            self.decrypt_chunk()?;
+        }
+
+        let read = min(buf.len(), self.plaintext.len() - self.position);
+        buf[..read].copy_from_slice(&self.plaintext[self.position..self.position + read]);
+        self.position += read;
+
+        Ok(read)
+    }
+}
+
+/// What went through `encrypt_stream`, so that it can be stored along with the ciphertext
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamSummary {
+    /// Plaintext size in bytes
+    pub size: u64,
+    /// BLAKE3 hash of the plaintext
+    pub hash: blake3::Hash,
+}
+
+/// Encrypt everything from `reader` into `writer` with an `EncryptWriter`, hashing the
+/// plaintext on the fly
+pub fn encrypt_stream(
+    mut reader: impl Read,
+    writer: impl Write,
+    key: &SecretKey,
+    nonce: &NonceArray,
+) -> Result<StreamSummary, CryptoError> {
+    let mut encrypt_writer = EncryptWriter::new(writer, key, nonce);
+    let mut hasher = blake3::Hasher::new();
+    let mut size = 0;
+
+    loop {
+        let chunk = read_chunk(&mut reader, BUFFER_SIZE)?;
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+        encrypt_writer.write_all(&chunk).map_err(unwrap_io_error)?;
+    }
+
+    encrypt_writer.finish()?;
+
+    Ok(StreamSummary {
+        size,
+        hash: hasher.finalize(),
+    })
+}
+
+/// Decrypt everything from `reader` into `writer` with a `DecryptReader`. Returns the
+/// plaintext size. `writer` may have received some plaintext when the end turns out to be
+/// corrupted
+pub fn decrypt_stream(
+    reader: impl Read,
+    mut writer: impl Write,
+    key: &SecretKey,
+    nonce: &NonceArray,
+) -> Result<u64, CryptoError> {
+    let mut decrypt_reader = DecryptReader::new(reader, key, nonce);
+    let size = io::copy(&mut decrypt_reader, &mut writer).map_err(unwrap_io_error)?;
+    writer.flush()?;
+
+    Ok(size)
+}
+
+/// Read up to `size` bytes from `reader`, fewer only when the end comes first
+fn read_chunk(reader: &mut impl Read, size: usize) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(size);
+    reader.by_ref().take(size as u64).read_to_end(&mut chunk)?;
+
+    Ok(chunk)
+}
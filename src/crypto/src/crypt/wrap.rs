@@ -0,0 +1,68 @@
+use zeroize::Zeroizing;
+
+use crate::errors::CryptoError;
+
+use super::{open_sealed, seal, SecretKey};
+
+/// Something that can wrap and unwrap keys on behalf of a master key, which is not
+/// necessarily held by this process
+pub trait KeyWrapper {
+    fn wrap_key(&self, key: &SecretKey) -> Result<Vec<u8>, CryptoError>;
+
+    fn unwrap_key(&self, wrapped: &[u8]) -> Result<SecretKey, CryptoError>;
+}
+
+impl KeyWrapper for SecretKey {
+    fn wrap_key(&self, key: &SecretKey) -> Result<Vec<u8>, CryptoError> {
+        wrap_key(self, key)
+    }
+
+    fn unwrap_key(&self, wrapped: &[u8]) -> Result<SecretKey, CryptoError> {
+        unwrap_key(self, wrapped)
+    }
+}
+
+/// Encrypt `key` with `wrapping_key`. The output is in the form of nonce || ciphertext
+pub fn wrap_key(wrapping_key: &SecretKey, key: &SecretKey) -> Result<Vec<u8>, CryptoError> {
+    seal(wrapping_key, key.as_bytes(), &[]).map_err(|_| CryptoError::KeyWrap)
+}
+
+/// Decrypt a key previously wrapped with `wrap_key`
+pub fn unwrap_key(wrapping_key: &SecretKey, wrapped: &[u8]) -> Result<SecretKey, CryptoError> {
+    let key = Zeroizing::new(
+        open_sealed(wrapping_key, wrapped, &[]).map_err(|_| CryptoError::KeyUnwrap)?,
+    );
+
+    SecretKey::try_from_slice(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{crypt::generate_random_secure_key, errors::CryptoError};
+
+    use super::{unwrap_key, wrap_key};
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let wrapping_key = generate_random_secure_key();
+        let key = generate_random_secure_key();
+
+        let wrapped = wrap_key(&wrapping_key, &key).unwrap();
+        assert_ne!(wrapped.as_slice(), key.as_bytes());
+
+        let unwrapped = unwrap_key(&wrapping_key, &wrapped).unwrap();
+        assert_eq!(unwrapped, key);
+    }
+
+    #[test]
+    fn test_unwrap_with_wrong_key() {
+        let key = generate_random_secure_key();
+        let wrapped = wrap_key(&generate_random_secure_key(), &key).unwrap();
+
+        let result = unwrap_key(&generate_random_secure_key(), &wrapped);
+        assert!(matches!(result, Err(CryptoError::KeyUnwrap)));
+
+        let result = unwrap_key(&generate_random_secure_key(), &[0u8; 4]);
+        assert!(matches!(result, Err(CryptoError::KeyUnwrap)));
+    }
+}
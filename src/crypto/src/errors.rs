@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 
 use crate::crypt::PathPair;
@@ -12,6 +14,14 @@ pub enum CipherOperationError {
     DecryptNext,
     #[error("DecryptLast")]
     DecryptLast,
+    #[error("EncryptChunk")]
+    EncryptChunk,
+    #[error("EncryptChunkCount")]
+    EncryptChunkCount,
+    #[error("DecryptChunk")]
+    DecryptChunk,
+    #[error("DecryptChunkCount")]
+    DecryptChunkCount,
 }
 
 #[derive(Error, Debug)]
@@ -20,8 +30,34 @@ pub enum CryptoError {
     InputOutput(#[from] std::io::Error),
     #[error("Error while performing {0} cipher operation {:?} {:?}", .1.source, .1.destination)]
     CipherOperationError(CipherOperationError, PathPair),
+    #[error("Error while performing {0} cipher operation on a stream")]
+    StreamCipherOperation(CipherOperationError),
+    #[error("{0:?} changed while being read")]
+    SourceChanged(PathBuf),
+    #[error("Unknown read mode {0:?}, expected auto, mmap or buffered")]
+    InvalidReadMode(String),
     #[error("Key with length of {0} bytes is not valid")]
     InvalidKeyLength(usize),
     #[error("Nonce with length of {0} bytes is not valid")]
     InvalidNonceLength(usize),
+    #[error("Cannot wrap key")]
+    KeyWrap,
+    #[error("Cannot unwrap key: wrong key or corrupted data")]
+    KeyUnwrap,
+    #[error("Cannot encrypt data")]
+    Seal,
+    #[error("Cannot decrypt data: wrong key or corrupted data")]
+    Open,
+    #[error("Cannot derive key from passphrase")]
+    KeyDerivation,
+    #[error("Invalid recovery mnemonic")]
+    InvalidMnemonic,
+    #[error("Cannot split key with a threshold of {0} out of {1} shares")]
+    InvalidShareThreshold(u8, u8),
+    #[error("Invalid or insufficient key shares")]
+    InvalidShare,
+    #[error("Invalid recipient public key")]
+    InvalidRecipient,
+    #[error("Invalid identity")]
+    InvalidIdentity,
 }
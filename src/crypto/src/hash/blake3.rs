@@ -1,22 +1,45 @@
 use std::{
     fmt::Debug,
-    fs::File,
+    fs::{metadata, File},
+    io::{self, Write},
     path::{Path, PathBuf},
 };
 
-use memmap2::MmapOptions;
-
 use crate::{
-    errors::CryptoError,
-    traits::{ComputeBulk, ComputeUnit},
-    BUFFER_SIZE,
+    errors::CryptoError, progress::ProgressWriter, source::SourceFile, traits::ComputeUnit,
+    ReadMode,
 };
 
 pub type Blake3Hash = blake3::Hash;
 
+/// Writes at least this large are hashed across the rayon pool, below it splitting the work
+/// costs more than it saves
+const RAYON_MIN_WRITE: usize = 128 * 1024;
+
+/// Feeds a BLAKE3 hasher, spreading large writes, as a whole memory mapped file, across
+/// the rayon pool
+struct RayonHasher(blake3::Hasher);
+
+impl Write for RayonHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() >= RAYON_MIN_WRITE {
+            self.0.update_rayon(buf);
+        } else {
+            self.0.update(buf);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Blake3File {
     source_path: PathBuf,
+    read_mode: ReadMode,
 }
 
 impl Blake3File {
@@ -27,7 +50,16 @@ impl Blake3File {
         // Attempt to open file first
         File::open(&source_path)?;
 
-        Ok(Blake3File { source_path })
+        Ok(Blake3File {
+            source_path,
+            read_mode: ReadMode::default(),
+        })
+    }
+
+    /// How to read the file, `ReadMode::Auto` by default
+    pub fn read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
     }
 }
 
@@ -35,61 +67,26 @@ impl ComputeUnit for Blake3File {
     type Output = blake3::Hash;
 
     fn start(self) -> Result<Self::Output, CryptoError> {
-        let file_input = File::open(&self.source_path)?;
-
-        // Handle zero-sized files without mmapping them into memory
-        if file_input.metadata()?.len() == 0 {
-            let mut hasher = blake3::Hasher::new();
-            hasher.update(&[]);
-            return Ok(hasher.finalize());
-        }
-
-        let mmap = unsafe { MmapOptions::new().map(&file_input).unwrap() };
-
-        let mut hasher = blake3::Hasher::new();
-
-        // Hash loop
-        for chunk in mmap.chunks(BUFFER_SIZE) {
-            hasher.update(chunk);
-        }
-
-        Ok(hasher.finalize())
+        self.start_with_progress(&mut |_| ())
     }
-}
-
-#[derive(Debug, Clone)]
-pub struct Blake3Concurrent {
-    hashers: Vec<Blake3File>,
-}
-
-impl Blake3Concurrent {
-    pub fn try_new<P: AsRef<Path>>(source_paths: &[P]) -> Result<Box<Self>, CryptoError> {
-        let mut hashers = Vec::new();
 
-        for source_path in source_paths {
-            hashers.push(Blake3File::try_new(source_path)?);
-        }
-
-        Ok(Box::new(Self { hashers }))
+    fn size(&self) -> Option<u64> {
+        metadata(&self.source_path)
+            .ok()
+            .map(|metadata| metadata.len())
     }
-}
 
-impl ComputeBulk for Blake3Concurrent {
-    type Compute = Blake3File;
-    type Output = blake3::Hash;
-    type Key = PathBuf;
+    /// Hash, reporting the bytes read
+    fn start_with_progress(
+        self,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<Self::Output, CryptoError> {
+        let mut source_file = SourceFile::open(&self.source_path, self.read_mode)?;
+        let mut hasher = RayonHasher(blake3::Hasher::new());
 
-    fn units(&self) -> Vec<Self::Compute> {
-        self.hashers.clone()
-    }
-
-    fn map_key(unit: &<Self as ComputeBulk>::Compute) -> Self::Key {
-        unit.source_path.clone()
-    }
+        source_file.copy_to(&mut ProgressWriter::new(&mut hasher, progress))?;
+        source_file.check_unchanged()?;
 
-    fn map_output(
-        result: Result<<Self::Compute as ComputeUnit>::Output, CryptoError>,
-    ) -> Self::Output {
-        result.unwrap()
+        Ok(hasher.0.finalize())
     }
 }
@@ -1,2 +1,2 @@
 mod blake3;
-pub use self::blake3::{Blake3Concurrent, Blake3File, Blake3Hash};
+pub use self::blake3::{Blake3File, Blake3Hash};
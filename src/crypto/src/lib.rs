@@ -5,6 +5,10 @@ pub mod hash;
 pub use blake3;
 
 pub mod errors;
+pub mod progress;
 pub mod types;
 
 pub mod traits;
+
+mod source;
+pub use source::{ReadMode, SourceFile};
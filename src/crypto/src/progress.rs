@@ -0,0 +1,243 @@
+use std::{
+    cmp::min,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
+
+use crate::errors::CryptoError;
+
+/// Bytes reported at once: more go through a write in several steps, so that a memory
+/// mapped file does not jump from nothing to done, and fewer are added up first
+pub(crate) const PROGRESS_STEP: usize = 4 * 1024 * 1024;
+
+/// Receives what a `ComputeBulk` job is doing. Units run on several threads at once, so
+/// events of different units interleave. Every event is ignored by default
+pub trait ProgressSink: Send + Sync {
+    /// The job is about to start `units` units, which process `bytes` in total as far as
+    /// is known
+    fn job_started(&self, _units: usize, _bytes: u64) {}
+
+    /// `unit` starts, and will process `bytes` if known
+    fn unit_started(&self, _unit: &Path, _bytes: Option<u64>) {}
+
+    /// `unit` has processed `bytes` more
+    fn unit_bytes(&self, _unit: &Path, _bytes: u64) {}
+
+    /// `unit` is done, successfully unless there is an `error`
+    fn unit_finished(&self, _unit: &Path, _error: Option<&CryptoError>) {}
+
+    /// The job is over, with `cancelled` units never started
+    fn job_finished(&self, _completed: usize, _cancelled: usize) {}
+}
+
+/// Reports nothing, for scripts
+#[derive(Debug, Default)]
+pub struct QuietProgress;
+
+impl ProgressSink for QuietProgress {}
+
+const BYTES_TEMPLATE: &str = concat!(
+    "[{elapsed_precise}] {spinner} {bar:40.cyan/blue} ",
+    "{bytes:>10}/{total_bytes:10} {binary_bytes_per_sec}"
+);
+
+const UNITS_TEMPLATE: &str = "[{elapsed_precise}] {spinner} {bar:40.cyan/blue} {pos:>7}/{len:7}";
+
+/// Draws a progress bar on stderr, with the bytes processed and the throughput when the
+/// sizes are known, or the units done otherwise
+#[derive(Debug)]
+pub struct BarProgress {
+    bar: ProgressBar,
+    counts_bytes: AtomicBool,
+}
+
+impl BarProgress {
+    pub fn new() -> Self {
+        BarProgress {
+            bar: ProgressBar::new(0),
+            counts_bytes: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Default for BarProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressSink for BarProgress {
+    fn job_started(&self, units: usize, bytes: u64) {
+        let counts_bytes = bytes > 0;
+        self.counts_bytes.store(counts_bytes, Ordering::Relaxed);
+
+        let (template, length) = if counts_bytes {
+            (BYTES_TEMPLATE, bytes)
+        } else {
+            (UNITS_TEMPLATE, units as u64)
+        };
+
+        self.bar
+            .set_style(ProgressStyle::with_template(template).unwrap());
+        self.bar.set_length(length);
+        self.bar.reset();
+    }
+
+    fn unit_bytes(&self, _unit: &Path, bytes: u64) {
+        if self.counts_bytes.load(Ordering::Relaxed) {
+            self.bar.inc(bytes);
+        }
+    }
+
+    fn unit_finished(&self, _unit: &Path, _error: Option<&CryptoError>) {
+        if !self.counts_bytes.load(Ordering::Relaxed) {
+            self.bar.inc(1);
+        }
+    }
+
+    fn job_finished(&self, _completed: usize, _cancelled: usize) {
+        self.bar.finish();
+    }
+}
+
+/// Writes every event as a JSON object on its own line, for other programs to follow
+#[derive(Debug)]
+pub struct JsonLinesProgress<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesProgress<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesProgress {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Give back the inner writer
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner().unwrap()
+    }
+
+    fn write_event(&self, event: serde_json::Value) {
+        let mut writer = self.writer.lock().unwrap();
+
+        // Progress is best effort, a closed pipe must not fail the job
+        let _ = writeln!(writer, "{event}").and_then(|_| writer.flush());
+    }
+}
+
+impl<W: Write + Send> ProgressSink for JsonLinesProgress<W> {
+    fn job_started(&self, units: usize, bytes: u64) {
+        self.write_event(json!({ "event": "job_started", "units": units, "bytes": bytes }));
+    }
+
+    fn unit_started(&self, unit: &Path, bytes: Option<u64>) {
+        self.write_event(json!({
+            "event": "unit_started",
+            "unit": unit.to_string_lossy(),
+            "bytes": bytes,
+        }));
+    }
+
+    fn unit_bytes(&self, unit: &Path, bytes: u64) {
+        self.write_event(json!({
+            "event": "unit_bytes",
+            "unit": unit.to_string_lossy(),
+            "bytes": bytes,
+        }));
+    }
+
+    fn unit_finished(&self, unit: &Path, error: Option<&CryptoError>) {
+        self.write_event(json!({
+            "event": "unit_finished",
+            "unit": unit.to_string_lossy(),
+            "error": error.map(ToString::to_string),
+        }));
+    }
+
+    fn job_finished(&self, completed: usize, cancelled: usize) {
+        self.write_event(json!({
+            "event": "job_finished",
+            "completed": completed,
+            "cancelled": cancelled,
+        }));
+    }
+}
+
+/// Stops a `ComputeBulk` job from starting more units once cancelled. The units already
+/// running are completed. Clones share the same state
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Reports the bytes written through it
+pub(crate) struct ProgressWriter<'a, W: Write> {
+    writer: W,
+    progress: &'a mut dyn FnMut(u64),
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    pub fn new(writer: W, progress: &'a mut dyn FnMut(u64)) -> Self {
+        ProgressWriter { writer, progress }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(&buf[..min(buf.len(), PROGRESS_STEP)])?;
+
+        if written > 0 {
+            (self.progress)(written as u64);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reports the bytes read through it
+pub(crate) struct ProgressReader<'a, R: Read> {
+    reader: R,
+    progress: &'a mut dyn FnMut(u64),
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(reader: R, progress: &'a mut dyn FnMut(u64)) -> Self {
+        ProgressReader { reader, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+
+        if read > 0 {
+            (self.progress)(read as u64);
+        }
+
+        Ok(read)
+    }
+}
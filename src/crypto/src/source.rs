@@ -0,0 +1,293 @@
+use std::{
+    fmt::{self, Display},
+    fs::{File, Metadata},
+    io::{self, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use memmap2::{Mmap, MmapOptions};
+
+use crate::{errors::CryptoError, BUFFER_SIZE};
+
+/// Files smaller than this are read with plain reads when the mode is `Auto`, since setting
+/// up a memory map costs more than copying them
+const MMAP_MIN_SIZE: u64 = 4 * 1024 * 1024;
+
+/// How the files to hash, encrypt or decrypt are read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Memory map large files on local filesystems, read the others
+    #[default]
+    Auto,
+    /// Always memory map. Fastest, but the process is killed with SIGBUS when a file is
+    /// truncated while it is mapped
+    Mmap,
+    /// Always read through a buffer, which survives files changing under it
+    Buffered,
+}
+
+impl FromStr for ReadMode {
+    type Err = CryptoError;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "auto" => Ok(ReadMode::Auto),
+            "mmap" => Ok(ReadMode::Mmap),
+            "buffered" => Ok(ReadMode::Buffered),
+            _ => Err(CryptoError::InvalidReadMode(mode.to_owned())),
+        }
+    }
+}
+
+impl Display for ReadMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReadMode::Auto => "auto",
+            ReadMode::Mmap => "mmap",
+            ReadMode::Buffered => "buffered",
+        })
+    }
+}
+
+enum Contents {
+    Mapped(Cursor<Mmap>),
+    Buffered,
+}
+
+/// A file opened for reading in the chosen `ReadMode`, which can tell whether it changed
+/// since it was opened
+pub struct SourceFile {
+    path: PathBuf,
+    file: File,
+    contents: Contents,
+    /// Size and modification time when opened
+    size: u64,
+    modified: Option<SystemTime>,
+    /// Bytes handed out so far
+    read: u64,
+}
+
+impl SourceFile {
+    pub fn open(path: impl AsRef<Path>, mode: ReadMode) -> Result<Self, CryptoError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+
+        let use_mmap = match mode {
+            // Zero-sized files cannot be mmapped into memory
+            _ if size == 0 => false,
+            ReadMode::Auto => size >= MMAP_MIN_SIZE && !is_network_filesystem(&file),
+            ReadMode::Mmap => true,
+            ReadMode::Buffered => false,
+        };
+
+        let contents = if use_mmap {
+            // SAFETY: the map is only read, and changes to the file are detected afterwards.
+            // Truncation still raises SIGBUS, which `ReadMode::Buffered` avoids
+            Contents::Mapped(Cursor::new(unsafe { MmapOptions::new().map(&file)? }))
+        } else {
+            Contents::Buffered
+        };
+
+        log::trace!("reading {path:?} with mmap: {use_mmap}");
+
+        Ok(SourceFile {
+            path,
+            file,
+            contents,
+            size,
+            modified: modified(&metadata),
+            read: 0,
+        })
+    }
+
+    /// Write all the remaining contents to `writer`, without copying them when mapped
+    pub fn copy_to(&mut self, writer: &mut impl Write) -> io::Result<u64> {
+        let copied = match &mut self.contents {
+            Contents::Mapped(cursor) => {
+                let position = cursor.position();
+                let remaining = &cursor.get_ref()[position as usize..];
+                writer.write_all(remaining)?;
+
+                let end = cursor.get_ref().len() as u64;
+                cursor.set_position(end);
+
+                end - position
+            }
+            Contents::Buffered => {
+                let mut buffer = vec![0; BUFFER_SIZE];
+                let mut copied = 0;
+
+                loop {
+                    let read = match self.file.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(read) => read,
+                        Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(error) => return Err(error),
+                    };
+
+                    writer.write_all(&buffer[..read])?;
+                    copied += read as u64;
+                }
+
+                copied
+            }
+        };
+
+        self.read += copied;
+
+        Ok(copied)
+    }
+
+    /// Fail when the file has been modified since it was opened, so what was read from it
+    /// may be a mix of old and new contents. Meant to be called once everything is read
+    pub fn check_unchanged(&self) -> Result<(), CryptoError> {
+        let metadata = self.file.metadata()?;
+
+        if self.read != self.size
+            || metadata.len() != self.size
+            || modified(&metadata) != self.modified
+        {
+            return Err(CryptoError::SourceChanged(self.path.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for SourceFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = match &mut self.contents {
+            Contents::Mapped(cursor) => cursor.read(buf)?,
+            Contents::Buffered => self.file.read(buf)?,
+        };
+
+        self.read += read as u64;
+
+        Ok(read)
+    }
+}
+
+fn modified(metadata: &Metadata) -> Option<SystemTime> {
+    metadata.modified().ok()
+}
+
+/// Whether `file` is on a network filesystem, where memory maps are unreliable
+#[cfg(target_os = "linux")]
+fn is_network_filesystem(file: &File) -> bool {
+    use std::os::fd::AsRawFd;
+
+    // From statfs(2), `f_type` differs in width and sign across architectures
+    const NETWORK_MAGICS: [u32; 9] = [
+        0x6969,     // NFS
+        0x517b,     // SMB
+        0xff534d42, // CIFS
+        0xfe534d42, // SMB2
+        0x5346414f, // AFS
+        0x73757245, // Coda
+        0x01021997, // 9P
+        0x00c36400, // Ceph
+        0x65735546, // FUSE, as sshfs
+    ];
+
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+
+    // SAFETY: the descriptor is open for the lifetime of `file`, and `stat` is only read
+    // when the call succeeds
+    if unsafe { libc::fstatfs(file.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+        return false;
+    }
+
+    let stat = unsafe { stat.assume_init() };
+
+    NETWORK_MAGICS.contains(&(stat.f_type as u32))
+}
+
+/// Whether `file` is on a network filesystem, where memory maps are unreliable
+#[cfg(target_os = "macos")]
+fn is_network_filesystem(file: &File) -> bool {
+    use std::{ffi::CStr, os::fd::AsRawFd};
+
+    const NETWORK_TYPES: [&str; 5] = ["nfs", "smbfs", "afpfs", "webdav", "macfuse"];
+
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+
+    // SAFETY: as above
+    if unsafe { libc::fstatfs(file.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+        return false;
+    }
+
+    let stat = unsafe { stat.assume_init() };
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+
+    name.to_str()
+        .is_ok_and(|name| NETWORK_TYPES.contains(&name))
+}
+
+/// Whether `file` is on a network filesystem, which is not known here
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn is_network_filesystem(_file: &File) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::write, io::Read};
+
+    use tmp::Tmp;
+
+    use super::{ReadMode, SourceFile, MMAP_MIN_SIZE};
+
+    #[test]
+    fn test_read_modes_read_the_same() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("file");
+        let contents = (0..MMAP_MIN_SIZE + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        write(&path, &contents).unwrap();
+
+        for mode in [ReadMode::Auto, ReadMode::Mmap, ReadMode::Buffered] {
+            let mut copied = vec![];
+            let mut source = SourceFile::open(&path, mode).unwrap();
+            source.copy_to(&mut copied).unwrap();
+            source.check_unchanged().unwrap();
+            assert_eq!(copied, contents);
+
+            let mut read = vec![];
+            let mut source = SourceFile::open(&path, mode).unwrap();
+            source.read_to_end(&mut read).unwrap();
+            source.check_unchanged().unwrap();
+            assert_eq!(read, contents);
+        }
+    }
+
+    #[test]
+    fn test_detect_changes_while_reading() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("file");
+        write(&path, vec![1; 100_000]).unwrap();
+
+        let mut source = SourceFile::open(&path, ReadMode::Buffered).unwrap();
+        let mut start = [0; 1000];
+        source.read_exact(&mut start).unwrap();
+
+        // Truncated while being read
+        write(&path, vec![2; 50_000]).unwrap();
+        source.copy_to(&mut vec![]).unwrap();
+
+        assert!(source.check_unchanged().is_err());
+    }
+
+    #[test]
+    fn test_parse_read_mode() {
+        for mode in [ReadMode::Auto, ReadMode::Mmap, ReadMode::Buffered] {
+            assert_eq!(mode.to_string().parse::<ReadMode>().unwrap(), mode);
+        }
+
+        assert!("mapped".parse::<ReadMode>().is_err());
+    }
+}
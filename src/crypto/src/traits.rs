@@ -1,25 +1,48 @@
-use std::{any::type_name, collections::HashMap, hash::Hash, time::Instant};
+use std::{any::type_name, collections::HashMap, hash::Hash, path::Path, time::Instant};
 
-use indicatif::{ParallelProgressIterator, ProgressStyle};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::errors::CryptoError;
+use crate::{
+    errors::CryptoError,
+    progress::{BarProgress, CancellationToken, ProgressSink, PROGRESS_STEP},
+    types::BulkReport,
+};
 
 /// Something that can be computed
 pub trait ComputeUnit {
     type Output: Send;
 
     fn start(self) -> Result<Self::Output, CryptoError>;
+
+    /// How many bytes the computation goes through, if known before starting
+    fn size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Like `start`, calling `progress` with the bytes processed as they are. Nothing is
+    /// reported by default
+    fn start_with_progress(
+        self,
+        _progress: &mut dyn FnMut(u64),
+    ) -> Result<Self::Output, CryptoError>
+    where
+        Self: Sized,
+    {
+        self.start()
+    }
 }
 
 /// Provide the ability to execute multiple `Compute` objects at once
 pub trait ComputeBulk {
     type Compute: ComputeUnit + Send;
-    type Key: Hash + Eq + Send;
+    /// Names the unit in the progress events too
+    type Key: Hash + Eq + Send + AsRef<Path>;
     type Output: Send;
 
-    /// Get a Vec of `Compute`s
-    fn units(&self) -> Vec<Self::Compute>;
+    /// Take the `Compute`s out, without copying them since they may hold key material
+    fn units(self) -> Vec<Self::Compute>
+    where
+        Self: Sized;
 
     /// Map a `ComputeUnit` to its key
     fn map_key(unit: &<Self as ComputeBulk>::Compute) -> Self::Key;
@@ -29,8 +52,26 @@ pub trait ComputeBulk {
         result: Result<<<Self as ComputeBulk>::Compute as ComputeUnit>::Output, CryptoError>,
     ) -> Self::Output;
 
-    /// Start `ComputeUnit` action in a concurrent manner
-    fn start_all(self: Box<Self>) -> HashMap<Self::Key, Self::Output> {
+    /// Start `ComputeUnit` action in a concurrent manner, with a progress bar
+    fn start_all(self) -> HashMap<Self::Key, Self::Output>
+    where
+        Self: Sized,
+    {
+        self.start_all_with(&BarProgress::new(), &CancellationToken::new())
+            .outputs
+    }
+
+    /// Start `ComputeUnit` action in a concurrent manner, reporting to `progress`. Once
+    /// `cancel` is cancelled no more units are started, and the report tells them apart
+    /// from the completed ones
+    fn start_all_with(
+        self,
+        progress: &dyn ProgressSink,
+        cancel: &CancellationToken,
+    ) -> BulkReport<Self::Key, Self::Output>
+    where
+        Self: Sized,
+    {
         let computes = self.units();
 
         if computes.is_empty() {
@@ -39,7 +80,7 @@ pub trait ComputeBulk {
                 type_name::<Self::Compute>(),
             );
 
-            return HashMap::new();
+            return BulkReport::default();
         }
 
         log::trace!(
@@ -50,30 +91,63 @@ pub trait ComputeBulk {
 
         let start = Instant::now();
 
-        let progress_bar_style = ProgressStyle::with_template(
-            "[{elapsed_precise}] {spinner} {bar:40.cyan/blue} {pos:>7}/{len:7}",
-        )
-        .unwrap();
+        let total_bytes = computes.iter().filter_map(ComputeUnit::size).sum();
+        progress.job_started(computes.len(), total_bytes);
 
         let results = computes
             .into_par_iter()
-            .progress_with_style(progress_bar_style)
             .map(|compute| {
                 let key = Self::map_key(&compute);
-                let result = compute.start();
+                let unit = key.as_ref();
+
+                if cancel.is_cancelled() {
+                    return Err(key);
+                }
+
+                progress.unit_started(unit, compute.size());
+
+                let mut pending_bytes = 0;
+                let result = compute.start_with_progress(&mut |bytes| {
+                    pending_bytes += bytes;
+
+                    if pending_bytes >= PROGRESS_STEP as u64 {
+                        progress.unit_bytes(unit, pending_bytes);
+                        pending_bytes = 0;
+                    }
+                });
+
+                if pending_bytes > 0 {
+                    progress.unit_bytes(unit, pending_bytes);
+                }
+
+                progress.unit_finished(unit, result.as_ref().err());
                 let output = Self::map_output(result);
 
-                (key, output)
+                Ok((key, output))
             })
-            .collect::<HashMap<Self::Key, Self::Output>>();
+            .collect::<Vec<_>>();
+
+        let mut report = BulkReport::default();
+
+        for result in results {
+            match result {
+                Ok((key, output)) => {
+                    report.outputs.insert(key, output);
+                }
+                Err(key) => report.cancelled.push(key),
+            }
+        }
+
+        progress.job_finished(report.outputs.len(), report.cancelled.len());
 
         log::trace!(
-            "[{}] Took {:?} for processing {} items",
+            "[{}] Took {:?} for processing {} items, {} cancelled",
             type_name::<Self::Compute>(),
             start.elapsed(),
-            results.len()
+            report.outputs.len(),
+            report.cancelled.len()
         );
 
-        results
+        report
     }
 }
@@ -1,2 +1,2 @@
 mod reports;
-pub use reports::Report;
+pub use reports::{BulkReport, Report, ReportError};
@@ -1,5 +1,57 @@
-#[derive(Debug)]
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+use serde::Serialize;
+
+/// What an operation did to many files, to be shown to users or scripts
+#[derive(Debug, Default, Serialize)]
 pub struct Report {
     pub processed_file_count: usize,
+    /// Plaintext size of the files processed
+    pub processed_bytes: u64,
     pub errors_count: usize,
+    /// The files that failed, and why
+    pub errors: Vec<ReportError>,
+    /// Files left untouched because the operation was cancelled
+    pub cancelled_count: usize,
+}
+
+impl Report {
+    /// Count a file processed successfully
+    pub fn processed(&mut self, bytes: u64) {
+        self.processed_file_count += 1;
+        self.processed_bytes += bytes;
+    }
+
+    /// Count a file that failed with `error`
+    pub fn failed(&mut self, path: impl AsRef<Path>, error: impl Display) {
+        self.errors_count += 1;
+        self.errors.push(ReportError {
+            path: path.as_ref().to_string_lossy().into_owned(),
+            error: error.to_string(),
+        });
+    }
+}
+
+/// A file that could not be processed
+#[derive(Debug, Serialize)]
+pub struct ReportError {
+    pub path: String,
+    pub error: String,
+}
+
+/// What a `ComputeBulk` job did: the output of every completed unit, and the units that
+/// were never started because the job was cancelled
+#[derive(Debug)]
+pub struct BulkReport<K, O> {
+    pub outputs: HashMap<K, O>,
+    pub cancelled: Vec<K>,
+}
+
+impl<K, O> Default for BulkReport<K, O> {
+    fn default() -> Self {
+        BulkReport {
+            outputs: HashMap::new(),
+            cancelled: vec![],
+        }
+    }
 }
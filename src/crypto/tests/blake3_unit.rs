@@ -78,3 +78,17 @@ fn test_blake3_random_file() {
         assert_eq!(hash, expected_hash);
     }
 }
+
+#[test]
+fn test_blake3_failures() {
+    let tmp = Tmp::random();
+
+    let missing = tmp.base_path().join("missing.txt");
+    let directory = tmp.base_path().join("directory");
+    std::fs::create_dir(&directory).unwrap();
+
+    assert!(Blake3File::try_new(&missing).is_err());
+    assert!(Blake3File::try_new(&directory)
+        .and_then(|hasher| hasher.start())
+        .is_err());
+}
@@ -0,0 +1,185 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crypto::{
+    crypt::{CipherFormat, FileEncryptBulk, FileEncryptUnit, SecretKey},
+    errors::CryptoError,
+    progress::{CancellationToken, JsonLinesProgress, ProgressSink},
+    traits::ComputeBulk,
+};
+use rand::{prelude::SmallRng, SeedableRng};
+use tmp::Tmp;
+
+use common::{generate_random_plaintext_file_with_rng, generate_seeded_key};
+
+mod common;
+
+const SIZES: [usize; 5] = [0, 1, 1000, 1024 * 1024, 9 * 1024 * 1024 + 7];
+
+/// Keeps every event it receives
+#[derive(Default)]
+struct RecordingProgress {
+    jobs: Mutex<Vec<(usize, u64)>>,
+    started: Mutex<Vec<(PathBuf, Option<u64>)>>,
+    bytes: Mutex<u64>,
+    finished: Mutex<Vec<(PathBuf, bool)>>,
+    job_finished: Mutex<Option<(usize, usize)>>,
+}
+
+impl ProgressSink for RecordingProgress {
+    fn job_started(&self, units: usize, bytes: u64) {
+        self.jobs.lock().unwrap().push((units, bytes));
+    }
+
+    fn unit_started(&self, unit: &Path, bytes: Option<u64>) {
+        self.started.lock().unwrap().push((unit.to_owned(), bytes));
+    }
+
+    fn unit_bytes(&self, _unit: &Path, bytes: u64) {
+        *self.bytes.lock().unwrap() += bytes;
+    }
+
+    fn unit_finished(&self, unit: &Path, error: Option<&CryptoError>) {
+        self.finished
+            .lock()
+            .unwrap()
+            .push((unit.to_owned(), error.is_none()));
+    }
+
+    fn job_finished(&self, completed: usize, cancelled: usize) {
+        *self.job_finished.lock().unwrap() = Some((completed, cancelled));
+    }
+}
+
+fn generate_files(tmp: &Tmp) -> Vec<PathBuf> {
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    SIZES
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            let path = tmp.base_path().join(format!("{i}.bin"));
+            generate_random_plaintext_file_with_rng(&mut rng, &path, *size);
+            path
+        })
+        .collect()
+}
+
+/// Encrypt each of `paths` next to it, in `format`
+fn encrypt_all(paths: &[PathBuf], format: CipherFormat) -> FileEncryptBulk {
+    let (key, nonce) = generate_seeded_key();
+
+    FileEncryptBulk::new(paths.iter().map(|path| {
+        FileEncryptUnit::try_new(
+            path,
+            &path.with_extension(format!("{format:?}")),
+            SecretKey::try_from_slice(&key).unwrap(),
+            nonce.into(),
+        )
+        .unwrap()
+        .format(format)
+    }))
+}
+
+#[test]
+fn test_progress_of_job() {
+    let tmp = Tmp::random();
+    let paths = generate_files(&tmp);
+    let total: u64 = SIZES.iter().sum::<usize>() as u64;
+
+    let progress = RecordingProgress::default();
+    let report = encrypt_all(&paths, CipherFormat::Stream)
+        .start_all_with(&progress, &CancellationToken::new());
+
+    assert_eq!(report.outputs.len(), paths.len());
+    assert!(report.cancelled.is_empty());
+
+    assert_eq!(*progress.jobs.lock().unwrap(), [(paths.len(), total)]);
+    assert_eq!(progress.started.lock().unwrap().len(), paths.len());
+    assert_eq!(*progress.bytes.lock().unwrap(), total);
+    assert!(progress.finished.lock().unwrap().iter().all(|(_, ok)| *ok));
+    assert_eq!(
+        *progress.job_finished.lock().unwrap(),
+        Some((paths.len(), 0))
+    );
+}
+
+#[test]
+fn test_progress_of_encryption() {
+    let tmp = Tmp::random();
+    let paths = generate_files(&tmp);
+    let total: u64 = SIZES.iter().sum::<usize>() as u64;
+
+    for format in [CipherFormat::Stream, CipherFormat::Chunked] {
+        let progress = RecordingProgress::default();
+        let report =
+            encrypt_all(&paths, format).start_all_with(&progress, &CancellationToken::new());
+
+        assert!(report.outputs.values().all(Result::is_ok));
+        assert_eq!(*progress.bytes.lock().unwrap(), total);
+
+        let mut started = progress.started.lock().unwrap().clone();
+        started.sort();
+        let expected = paths
+            .iter()
+            .zip(SIZES)
+            .map(|(path, size)| (path.clone(), Some(size as u64)))
+            .collect::<Vec<_>>();
+        assert_eq!(started, expected);
+    }
+}
+
+#[test]
+fn test_cancelled_job_starts_nothing() {
+    let tmp = Tmp::random();
+    let paths = generate_files(&tmp);
+
+    let cancel = CancellationToken::new();
+    cancel.clone().cancel();
+    assert!(cancel.is_cancelled());
+
+    let progress = RecordingProgress::default();
+    let mut report = encrypt_all(&paths, CipherFormat::Stream).start_all_with(&progress, &cancel);
+
+    assert!(report.outputs.is_empty());
+    report.cancelled.sort();
+    assert_eq!(report.cancelled, paths);
+
+    assert!(progress.started.lock().unwrap().is_empty());
+    assert_eq!(
+        *progress.job_finished.lock().unwrap(),
+        Some((0, paths.len()))
+    );
+}
+
+#[test]
+fn test_json_lines_progress() {
+    let tmp = Tmp::random();
+    let paths = generate_files(&tmp);
+
+    let progress = JsonLinesProgress::new(vec![]);
+    encrypt_all(&paths, CipherFormat::Stream).start_all_with(&progress, &CancellationToken::new());
+
+    let output = String::from_utf8(progress.into_inner()).unwrap();
+    let events = output
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    let count = |name: &str| events.iter().filter(|e| e["event"] == name).count();
+
+    assert_eq!(events.first().unwrap()["event"], "job_started");
+    assert_eq!(events.last().unwrap()["event"], "job_finished");
+    assert_eq!(events.last().unwrap()["completed"], paths.len());
+    assert_eq!(count("unit_started"), paths.len());
+    assert_eq!(count("unit_finished"), paths.len());
+
+    let bytes: u64 = events
+        .iter()
+        .filter(|e| e["event"] == "unit_bytes")
+        .map(|e| e["bytes"].as_u64().unwrap())
+        .sum();
+    assert_eq!(bytes, SIZES.iter().sum::<usize>() as u64);
+}
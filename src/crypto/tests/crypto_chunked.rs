@@ -0,0 +1,188 @@
+use std::{
+    fs::{read, write, File},
+    io::Cursor,
+};
+
+use crypto::{
+    crypt::{
+        decrypt_chunked, encrypt_chunked, CipherFormat, FileDecryptUnit, FileEncryptUnit, SecretKey,
+    },
+    errors::CryptoError,
+    traits::ComputeUnit,
+};
+use rand::{prelude::SmallRng, RngCore, SeedableRng};
+use tmp::Tmp;
+
+use common::generate_seeded_key;
+
+mod common;
+
+const MIB: usize = 1024 * 1024;
+
+/// Size of an encrypted chunk
+const CIPHERTEXT_CHUNK_SIZE: usize = MIB + 16;
+
+/// Sizes around the 1 MiB chunk boundaries, and the 2 MiB batch boundaries of a pool with
+/// two threads
+const SIZES: [usize; 9] = [
+    0,
+    1,
+    1000,
+    MIB - 1,
+    MIB,
+    MIB + 1,
+    2 * MIB,
+    4 * MIB,
+    4 * MIB + 17,
+];
+
+fn random_bytes(size: usize, rng: &mut SmallRng) -> Vec<u8> {
+    let mut bytes = vec![0; size];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+#[test]
+fn test_chunked_roundtrip() {
+    let mut rng = SmallRng::seed_from_u64(42);
+    let (key, nonce) = generate_seeded_key();
+    let key = SecretKey::try_from_slice(&key).unwrap();
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .build()
+        .unwrap();
+
+    for size in SIZES {
+        let plaintext = random_bytes(size, &mut rng);
+
+        let mut ciphertext = vec![];
+        let summary = pool
+            .install(|| encrypt_chunked(plaintext.as_slice(), &mut ciphertext, &key, &nonce.into()))
+            .unwrap();
+
+        assert_eq!(summary.size, size as u64);
+        assert_eq!(summary.hash, crypto::blake3::hash(&plaintext));
+
+        // The layout does not depend on how many threads encrypted it
+        let mut other_ciphertext = vec![];
+        encrypt_chunked(
+            plaintext.as_slice(),
+            &mut other_ciphertext,
+            &key,
+            &nonce.into(),
+        )
+        .unwrap();
+        assert_eq!(ciphertext, other_ciphertext);
+
+        let mut recovered = vec![];
+        let recovered_size = pool
+            .install(|| decrypt_chunked(ciphertext.as_slice(), &mut recovered, &key, &nonce.into()))
+            .unwrap();
+
+        assert_eq!(recovered_size, size as u64);
+        assert_eq!(recovered, plaintext);
+    }
+}
+
+#[test]
+fn test_chunked_detects_tampering() {
+    let mut rng = SmallRng::seed_from_u64(44);
+    let (key, nonce) = generate_seeded_key();
+    let key = SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext = random_bytes(3 * MIB + 1000, &mut rng);
+    let mut ciphertext = vec![];
+    encrypt_chunked(plaintext.as_slice(), &mut ciphertext, &key, &nonce.into()).unwrap();
+
+    let decrypt =
+        |ciphertext: &[u8]| decrypt_chunked(Cursor::new(ciphertext), vec![], &key, &nonce.into());
+
+    let is_rejected = |ciphertext: &[u8]| {
+        matches!(
+            decrypt(ciphertext),
+            Err(CryptoError::StreamCipherOperation(_))
+        )
+    };
+
+    let mut flipped = ciphertext.clone();
+    flipped[MIB + 100] ^= 1;
+    assert!(is_rejected(&flipped));
+
+    // Chunks cannot be moved around
+    let mut swapped = ciphertext.clone();
+    let (first, rest) = swapped.split_at_mut(CIPHERTEXT_CHUNK_SIZE);
+    first.swap_with_slice(&mut rest[..CIPHERTEXT_CHUNK_SIZE]);
+    assert!(is_rejected(&swapped));
+
+    // Nor dropped, be it with or without the chunk count
+    let mut dropped = ciphertext[..2 * CIPHERTEXT_CHUNK_SIZE].to_vec();
+    dropped.extend_from_slice(&ciphertext[ciphertext.len() - 24..]);
+    assert!(is_rejected(&dropped));
+    assert!(is_rejected(&ciphertext[..3 * CIPHERTEXT_CHUNK_SIZE]));
+    assert!(is_rejected(&ciphertext[..10]));
+
+    let mut appended = ciphertext.clone();
+    appended.extend_from_slice(&[0; 100]);
+    assert!(is_rejected(&appended));
+
+    assert!(decrypt(&ciphertext).is_ok());
+}
+
+#[test]
+fn test_chunked_file_units() {
+    let tmp = Tmp::random();
+    let mut rng = SmallRng::seed_from_u64(45);
+    let (key, nonce) = generate_seeded_key();
+    let secret = || SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext_path = tmp.base_path().join("plaintext");
+    let locked_path = tmp.base_path().join("locked");
+    let recovered_path = tmp.base_path().join("recovered");
+
+    for size in [0, 1000, 2 * MIB + 1] {
+        let plaintext = random_bytes(size, &mut rng);
+        write(&plaintext_path, &plaintext).unwrap();
+
+        FileEncryptUnit::try_new(&plaintext_path, &locked_path, secret(), nonce.into())
+            .unwrap()
+            .format(CipherFormat::Chunked)
+            .start()
+            .unwrap();
+
+        let mut recovered = vec![];
+        CipherFormat::Chunked
+            .decrypt(
+                File::open(&locked_path).unwrap(),
+                &mut recovered,
+                &secret(),
+                &nonce.into(),
+            )
+            .unwrap();
+        assert_eq!(recovered, plaintext);
+
+        FileDecryptUnit::try_new(&locked_path, &recovered_path, secret(), nonce.into())
+            .unwrap()
+            .format(CipherFormat::Chunked)
+            .start()
+            .unwrap();
+        assert_eq!(read(&recovered_path).unwrap(), plaintext);
+
+        // The formats are not interchangeable
+        let decrypted =
+            FileDecryptUnit::try_new(&locked_path, &recovered_path, secret(), nonce.into())
+                .unwrap()
+                .start();
+        assert!(decrypted.is_err());
+    }
+}
+
+#[test]
+fn test_format_for_size() {
+    assert_eq!(CipherFormat::for_size(0), CipherFormat::Stream);
+    assert_eq!(CipherFormat::for_size(MIB as u64), CipherFormat::Stream);
+    assert_eq!(
+        CipherFormat::for_size(200 * 1024 * MIB as u64),
+        CipherFormat::Chunked
+    );
+}
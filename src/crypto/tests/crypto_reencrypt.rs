@@ -0,0 +1,108 @@
+use std::fs::{read, write};
+
+use crypto::{
+    crypt::{
+        generate_random_secure_key_nonce_pair, CipherFormat, FileDecryptUnit, FileEncryptUnit,
+        FileReencryptUnit, SecretKey,
+    },
+    traits::ComputeUnit,
+};
+use rand::{prelude::SmallRng, RngCore, SeedableRng};
+use tmp::Tmp;
+
+use common::generate_seeded_key;
+
+mod common;
+
+const MIB: usize = 1024 * 1024;
+
+#[test]
+fn test_reencrypt_file_units() {
+    let tmp = Tmp::random();
+    let mut rng = SmallRng::seed_from_u64(46);
+    let (key, nonce) = generate_seeded_key();
+    let secret = || SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext_path = tmp.base_path().join("plaintext");
+    let old_locked_path = tmp.base_path().join("old");
+    let new_locked_path = tmp.base_path().join("new");
+    let recovered_path = tmp.base_path().join("recovered");
+
+    for format in [CipherFormat::Stream, CipherFormat::Chunked] {
+        for size in [0, 1000, 2 * MIB + 1] {
+            let mut plaintext = vec![0; size];
+            rng.fill_bytes(&mut plaintext);
+            write(&plaintext_path, &plaintext).unwrap();
+
+            FileEncryptUnit::try_new(&plaintext_path, &old_locked_path, secret(), nonce.into())
+                .unwrap()
+                .format(format)
+                .start()
+                .unwrap();
+
+            let (new_key, new_nonce) = generate_random_secure_key_nonce_pair();
+            let new_secret = || SecretKey::try_from_slice(new_key.as_bytes()).unwrap();
+
+            let summary = FileReencryptUnit::try_new(
+                &old_locked_path,
+                &new_locked_path,
+                (secret(), nonce.into()),
+                (new_secret(), new_nonce),
+            )
+            .unwrap()
+            .format(format)
+            .start()
+            .unwrap();
+
+            assert_eq!(summary.size, size as u64);
+            assert_eq!(summary.hash, blake3::hash(&plaintext));
+
+            FileDecryptUnit::try_new(&new_locked_path, &recovered_path, new_secret(), new_nonce)
+                .unwrap()
+                .format(format)
+                .start()
+                .unwrap();
+            assert_eq!(read(&recovered_path).unwrap(), plaintext);
+        }
+    }
+}
+
+#[test]
+fn test_failed_reencrypt_leaves_nothing() {
+    let tmp = Tmp::random();
+    let (key, nonce) = generate_seeded_key();
+    let secret = || SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext_path = tmp.base_path().join("plaintext");
+    let old_locked_path = tmp.base_path().join("old");
+    let new_locked_path = tmp.base_path().join("new");
+
+    for format in [CipherFormat::Stream, CipherFormat::Chunked] {
+        write(&plaintext_path, vec![7; MIB + 1]).unwrap();
+
+        FileEncryptUnit::try_new(&plaintext_path, &old_locked_path, secret(), nonce.into())
+            .unwrap()
+            .format(format)
+            .start()
+            .unwrap();
+
+        let mut ciphertext = read(&old_locked_path).unwrap();
+        let last = ciphertext.len() - 1;
+        ciphertext[last] ^= 1;
+        write(&old_locked_path, ciphertext).unwrap();
+
+        let (new_key, new_nonce) = generate_random_secure_key_nonce_pair();
+        let reencrypted = FileReencryptUnit::try_new(
+            &old_locked_path,
+            &new_locked_path,
+            (secret(), nonce.into()),
+            (new_key, new_nonce),
+        )
+        .unwrap()
+        .format(format)
+        .start();
+
+        assert!(reencrypted.is_err());
+        assert!(!new_locked_path.exists());
+    }
+}
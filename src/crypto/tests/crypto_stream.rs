@@ -0,0 +1,210 @@
+use std::{
+    fs::{read, write, File},
+    io::{Cursor, Read, Write},
+};
+
+use crypto::{
+    crypt::{
+        decrypt_stream, encrypt_stream, DecryptReader, EncryptWriter, FileDecryptUnit,
+        FileEncryptUnit, SecretKey,
+    },
+    errors::CryptoError,
+    traits::ComputeUnit,
+};
+use rand::{prelude::SmallRng, RngCore, SeedableRng};
+use tmp::Tmp;
+
+use common::generate_seeded_key;
+
+mod common;
+
+/// Sizes around the 32 KiB chunk boundaries
+const SIZES: [usize; 9] = [0, 1, 1000, 32767, 32768, 32769, 65536, 100_000, 1_000_000];
+
+fn random_bytes(size: usize, rng: &mut SmallRng) -> Vec<u8> {
+    let mut bytes = vec![0; size];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+#[test]
+fn test_stream_roundtrip() {
+    let mut rng = SmallRng::seed_from_u64(42);
+    let (key, nonce) = generate_seeded_key();
+    let key = SecretKey::try_from_slice(&key).unwrap();
+
+    for size in SIZES {
+        let plaintext = random_bytes(size, &mut rng);
+
+        let mut ciphertext = vec![];
+        let summary =
+            encrypt_stream(plaintext.as_slice(), &mut ciphertext, &key, &nonce.into()).unwrap();
+
+        assert_eq!(summary.size, size as u64);
+        assert_eq!(summary.hash, crypto::blake3::hash(&plaintext));
+
+        let mut recovered = vec![];
+        let recovered_size =
+            decrypt_stream(ciphertext.as_slice(), &mut recovered, &key, &nonce.into()).unwrap();
+
+        assert_eq!(recovered_size, size as u64);
+        assert_eq!(recovered, plaintext);
+    }
+}
+
+#[test]
+fn test_stream_compatible_with_file_units() {
+    let tmp = Tmp::random();
+    let mut rng = SmallRng::seed_from_u64(43);
+    let (key, nonce) = generate_seeded_key();
+    let secret = || SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext_path = tmp.base_path().join("plaintext");
+    let locked_path = tmp.base_path().join("locked");
+    let recovered_path = tmp.base_path().join("recovered");
+
+    for size in SIZES {
+        let plaintext = random_bytes(size, &mut rng);
+        write(&plaintext_path, &plaintext).unwrap();
+
+        // Encrypted by the file unit, decrypted as a stream
+        FileEncryptUnit::try_new(&plaintext_path, &locked_path, secret(), nonce.into())
+            .unwrap()
+            .start()
+            .unwrap();
+
+        let mut streamed_ciphertext = vec![];
+        encrypt_stream(
+            plaintext.as_slice(),
+            &mut streamed_ciphertext,
+            &secret(),
+            &nonce.into(),
+        )
+        .unwrap();
+        assert_eq!(read(&locked_path).unwrap(), streamed_ciphertext);
+
+        let mut recovered = vec![];
+        decrypt_stream(
+            File::open(&locked_path).unwrap(),
+            &mut recovered,
+            &secret(),
+            &nonce.into(),
+        )
+        .unwrap();
+        assert_eq!(recovered, plaintext);
+
+        // Encrypted as a stream, decrypted by the file unit
+        write(&locked_path, &streamed_ciphertext).unwrap();
+        FileDecryptUnit::try_new(&locked_path, &recovered_path, secret(), nonce.into())
+            .unwrap()
+            .start()
+            .unwrap();
+        assert_eq!(read(&recovered_path).unwrap(), plaintext);
+    }
+}
+
+#[test]
+fn test_stream_detects_tampering() {
+    let mut rng = SmallRng::seed_from_u64(44);
+    let (key, nonce) = generate_seeded_key();
+    let key = SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext = random_bytes(100_000, &mut rng);
+    let mut ciphertext = vec![];
+    encrypt_stream(plaintext.as_slice(), &mut ciphertext, &key, &nonce.into()).unwrap();
+
+    let decrypt =
+        |ciphertext: &[u8]| decrypt_stream(Cursor::new(ciphertext), vec![], &key, &nonce.into());
+
+    let mut flipped = ciphertext.clone();
+    flipped[50_000] ^= 1;
+    assert!(matches!(
+        decrypt(&flipped),
+        Err(CryptoError::StreamCipherOperation(_))
+    ));
+
+    // Dropping the last chunk must not go unnoticed
+    let truncated = &ciphertext[..2 * (32768 + 16)];
+    assert!(matches!(
+        decrypt(truncated),
+        Err(CryptoError::StreamCipherOperation(_))
+    ));
+}
+
+#[test]
+fn test_adapters_with_uneven_writes_and_reads() {
+    let tmp = Tmp::random();
+    let mut rng = SmallRng::seed_from_u64(45);
+    let (key, nonce) = generate_seeded_key();
+    let secret = || SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext_path = tmp.base_path().join("plaintext");
+    let locked_path = tmp.base_path().join("locked");
+
+    for size in SIZES {
+        let plaintext = random_bytes(size, &mut rng);
+        write(&plaintext_path, &plaintext).unwrap();
+
+        FileEncryptUnit::try_new(&plaintext_path, &locked_path, secret(), nonce.into())
+            .unwrap()
+            .start()
+            .unwrap();
+
+        // Writes that do not line up with the chunks, a single byte one included
+        let mut encrypt_writer = EncryptWriter::new(vec![], &secret(), &nonce.into());
+        let mut remaining = plaintext.as_slice();
+
+        for write_size in [1, 7000, 40_000].into_iter().cycle() {
+            if remaining.is_empty() {
+                break;
+            }
+
+            let (head, tail) = remaining.split_at(write_size.min(remaining.len()));
+            encrypt_writer.write_all(head).unwrap();
+            remaining = tail;
+        }
+
+        let ciphertext = encrypt_writer.finish().unwrap();
+        assert_eq!(read(&locked_path).unwrap(), ciphertext);
+
+        // Reads smaller than a chunk
+        let mut decrypt_reader =
+            DecryptReader::new(ciphertext.as_slice(), &secret(), &nonce.into());
+        let mut recovered = vec![];
+        let mut buffer = [0; 1000];
+
+        loop {
+            let read = decrypt_reader.read(&mut buffer).unwrap();
+
+            if read == 0 {
+                break;
+            }
+
+            recovered.extend_from_slice(&buffer[..read]);
+        }
+
+        assert_eq!(recovered, plaintext);
+        assert!(decrypt_reader.into_inner().is_empty());
+    }
+}
+
+#[test]
+fn test_decrypt_reader_detects_tampering() {
+    let mut rng = SmallRng::seed_from_u64(46);
+    let (key, nonce) = generate_seeded_key();
+    let key = SecretKey::try_from_slice(&key).unwrap();
+
+    let plaintext = random_bytes(100_000, &mut rng);
+    let mut encrypt_writer = EncryptWriter::new(vec![], &key, &nonce.into());
+    encrypt_writer.write_all(&plaintext).unwrap();
+    let mut ciphertext = encrypt_writer.finish().unwrap();
+
+    // The first two chunks are still readable, the error comes with the corrupted one
+    ciphertext[80_000] ^= 1;
+    let mut decrypt_reader = DecryptReader::new(ciphertext.as_slice(), &key, &nonce.into());
+    let mut recovered = vec![];
+    let error = decrypt_reader.read_to_end(&mut recovered).unwrap_err();
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(recovered, plaintext[..2 * 32768]);
+}
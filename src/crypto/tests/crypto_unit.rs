@@ -7,8 +7,8 @@ use std::{
 use common::generate_plaintext_with_content;
 use crypto::{
     crypt::{
-        generate_random_secure_key_nonce_pair, FileDecryptUnit, FileEncryptUnit, AEAD_KEY_SIZE,
-        AEAD_NONCE_SIZE,
+        generate_random_secure_key_nonce_pair, FileDecryptUnit, FileEncryptUnit, SecretKey,
+        AEAD_KEY_SIZE, AEAD_NONCE_SIZE,
     },
     traits::ComputeUnit,
 };
@@ -40,12 +40,22 @@ fn encrypt_decrypt_with_key(
     let mut recovered_path = file_path;
     recovered_path.push(RECOVERED_FILE);
 
-    let encryptor =
-        FileEncryptUnit::try_new(&unlocked_path, &locked_path, key.into(), nonce.into()).unwrap();
+    let encryptor = FileEncryptUnit::try_new(
+        &unlocked_path,
+        &locked_path,
+        SecretKey::try_from_slice(&key).unwrap(),
+        nonce.into(),
+    )
+    .unwrap();
     encryptor.start().unwrap();
 
-    let decryptor =
-        FileDecryptUnit::try_new(&locked_path, &recovered_path, key.into(), nonce.into()).unwrap();
+    let decryptor = FileDecryptUnit::try_new(
+        &locked_path,
+        &recovered_path,
+        SecretKey::try_from_slice(&key).unwrap(),
+        nonce.into(),
+    )
+    .unwrap();
     decryptor.start().unwrap();
 
     // Make sure that plaintext and recovered files are the same
@@ -0,0 +1,47 @@
+-- A database created before schema migrations existed, `user_version` is 0
+
+BEGIN TRANSACTION;
+
+CREATE TABLE IF NOT EXISTS `file_tag` (
+	`file_id` INTEGER NOT NULL,
+	`tag_id` INTEGER NOT NULL
+) STRICT;
+
+CREATE TABLE IF NOT EXISTS `tag` (
+	`id` INTEGER NOT NULL UNIQUE,
+	`name` TEXT NOT NULL UNIQUE,
+	PRIMARY KEY(`id` AUTOINCREMENT)
+) STRICT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS `tag_name` ON `tag` (`name`);
+
+CREATE TABLE IF NOT EXISTS `file` (
+	`id` INTEGER NOT NULL UNIQUE,
+	`title` TEXT NOT NULL UNIQUE,
+	`path` TEXT NOT NULL UNIQUE,
+	`locked_hash` TEXT NOT NULL,
+	`contents_hash` TEXT NOT NULL,
+	`size` INTEGER NOT NULL,
+	`created_at` TEXT NOT NULL,
+	`updated_at` TEXT NOT NULL,
+	`key` BLOB NOT NULL,
+	`nonce` BLOB NOT NULL,
+	PRIMARY KEY(`id` AUTOINCREMENT)
+) STRICT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS `file_title` ON `file` (`title` ASC);
+CREATE INDEX IF NOT EXISTS `file_locked_hash` ON `file` (`locked_hash`);
+CREATE UNIQUE INDEX IF NOT EXISTS `file_time` ON `file` (`created_at`, `updated_at`);
+CREATE UNIQUE INDEX IF NOT EXISTS `file_path` ON `file` (`path`);
+
+INSERT INTO `tag` (`name`) VALUES ('holiday');
+
+INSERT INTO `file` (`title`, `path`, `locked_hash`, `contents_hash`, `size`, `created_at`, `updated_at`, `key`, `nonce`)
+VALUES ('beach.jpg', 'photos/beach.jpg', 'a3f1', 'b7c2', 1024, '2022-08-01 10:00:00', '2022-08-01 10:00:00', X'00', X'00');
+
+INSERT INTO `file` (`title`, `path`, `locked_hash`, `contents_hash`, `size`, `created_at`, `updated_at`, `key`, `nonce`)
+VALUES ('back\slash.txt', 'docs/back\slash.txt', 'c4d5', 'e6f7', 16, '2022-08-02 10:00:00', '2022-08-02 10:00:00', X'00', X'00');
+
+INSERT INTO `file_tag` (`file_id`, `tag_id`) VALUES (1, 1);
+
+COMMIT;
@@ -1,5 +1,3 @@
-BEGIN TRANSACTION;
-
 CREATE TABLE IF NOT EXISTS `file_tag` (
 	`file_id` INTEGER NOT NULL,
 	`tag_id` INTEGER NOT NULL
@@ -31,5 +29,3 @@ CREATE UNIQUE INDEX IF NOT EXISTS `file_title` ON `file` (`title` ASC);
 CREATE INDEX IF NOT EXISTS `file_locked_hash` ON `file` (`locked_hash`);
 CREATE UNIQUE INDEX IF NOT EXISTS `file_time` ON `file` (`created_at`, `updated_at`);
 CREATE UNIQUE INDEX IF NOT EXISTS `file_path` ON `file` (`path`);
-
-COMMIT;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS `pending_rekey` (
+	`id` INTEGER NOT NULL UNIQUE,
+	`file_id` INTEGER NOT NULL UNIQUE,
+	`old_locked_hash` TEXT NOT NULL,
+	`locked_hash` TEXT NOT NULL,
+	`key` BLOB NOT NULL,
+	`nonce` BLOB NOT NULL,
+	`encrypted` INTEGER NOT NULL,
+	PRIMARY KEY(`id` AUTOINCREMENT)
+) STRICT;
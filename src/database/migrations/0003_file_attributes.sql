@@ -0,0 +1,8 @@
+ALTER TABLE `file` ADD COLUMN `modified_at` TEXT;
+ALTER TABLE `file` ADD COLUMN `accessed_at` TEXT;
+ALTER TABLE `file` ADD COLUMN `mode` INTEGER;
+ALTER TABLE `file` ADD COLUMN `uid` INTEGER;
+ALTER TABLE `file` ADD COLUMN `gid` INTEGER;
+ALTER TABLE `file` ADD COLUMN `xattrs` BLOB;
+
+CREATE INDEX IF NOT EXISTS `file_modified_at` ON `file` (`modified_at`);
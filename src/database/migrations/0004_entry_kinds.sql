@@ -0,0 +1,2 @@
+ALTER TABLE `file` ADD COLUMN `kind` TEXT NOT NULL DEFAULT 'file';
+ALTER TABLE `file` ADD COLUMN `target` BLOB;
@@ -0,0 +1,2 @@
+-- Paths are stored encoded by `fs::encode_path`, where `\` starts an escape
+UPDATE `file` SET `path` = REPLACE(`path`, '\', '\\');
@@ -0,0 +1 @@
+ALTER TABLE `file` ADD COLUMN `format` TEXT NOT NULL DEFAULT 'stream';
@@ -0,0 +1,649 @@
+use std::{
+    cell::Cell,
+    ffi::OsString,
+    fs::{read, File, OpenOptions},
+    io,
+    ops::{Deref, DerefMut},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    ptr::copy_nonoverlapping,
+};
+
+use crypto::crypt::{generate_random_secure_key, open_sealed, seal_into, KeyWrapper, SecretKey};
+use rusqlite::{ffi, Connection};
+use utils::write_atomically;
+use zeroize::Zeroizing;
+
+use crate::{
+    errors::{DatabaseError, DatabaseResult},
+    migrations::{migrate, pending_migrations, schema_version, Migration},
+    models,
+    utils::load_schema,
+};
+
+const MAGIC: &[u8] = b"KRYPTADB";
+const VERSION: u8 = 1;
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+const MAIN_SCHEMA: &[u8] = b"main\0";
+
+/// The catalog, decrypted in memory when opened and encrypted back to disk by `persist` and
+/// `close`. It is encrypted with its own key, which is stored next to the ciphertext wrapped
+/// with the master key.
+///
+/// The whole catalog is held in memory, close to 1 KB per path, so memory grows with the
+/// paths in the vault. Every `persist` encrypts all of it into a copy of the same size and
+/// writes it out, which takes longer as the catalog grows.
+/// Since the whole catalog is rewritten, only one `EncryptedDatabase` can be open on a path
+/// at a time: `<path>.lock` is locked until it is dropped.
+///
+/// On disk: MAGIC || VERSION || wrapped key length (u16 BE) || wrapped key || nonce || ciphertext,
+/// everything before the nonce is authenticated along with the ciphertext
+pub struct EncryptedDatabase {
+    connection: Connection,
+    path: PathBuf,
+    key: SecretKey,
+    wrapped_key: Vec<u8>,
+    // Held for the lifetime of the catalog, closing it releases the lock
+    _lock: File,
+    // The state of the connection when it was last read from or written to disk, `None` when
+    // the disk is behind whatever the connection holds
+    persisted: Cell<Option<Revision>>,
+}
+
+/// How many rows have been changed and how many times the schema has, since the connection
+/// was opened
+type Revision = (i32, i64);
+
+impl EncryptedDatabase {
+    /// Open the catalog in `path`, or create an empty one. Plaintext catalogs made by older
+    /// versions are encrypted on the next `persist`
+    pub fn open_or_create(
+        path: impl AsRef<Path>,
+        master_key: &impl KeyWrapper,
+    ) -> DatabaseResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let lock = lock(&path)?;
+
+        if !path.exists() {
+            log::trace!("New database... loading schema");
+
+            let connection = Connection::open_in_memory()?;
+            load_schema(&connection)?;
+
+            // Moved to memory that `with_serialized` can hand out as it is
+            let connection = deserialize(&serialize(&connection)?)?;
+
+            return Self::with_new_key(connection, path, lock, master_key);
+        }
+
+        let contents = Zeroizing::new(read(&path)?);
+
+        if contents.starts_with(SQLITE_MAGIC) {
+            log::info!("Plaintext database found, it will be encrypted");
+
+            let connection = deserialize(&contents)?;
+            return Self::with_new_key(connection, path, lock, master_key);
+        }
+
+        let (header, wrapped_key, sealed) = split(&contents)?;
+
+        let key = master_key.unwrap_key(wrapped_key)?;
+        let plaintext = Zeroizing::new(open_sealed(&key, sealed, header)?);
+
+        let connection = deserialize(&plaintext)?;
+        let persisted = Cell::new(Some(revision(&connection)?));
+
+        Ok(EncryptedDatabase {
+            connection,
+            path,
+            key,
+            wrapped_key: wrapped_key.to_vec(),
+            _lock: lock,
+            persisted,
+        })
+    }
+
+    fn with_new_key(
+        connection: Connection,
+        path: PathBuf,
+        lock: File,
+        master_key: &impl KeyWrapper,
+    ) -> DatabaseResult<Self> {
+        let key = generate_random_secure_key();
+        let wrapped_key = master_key.wrap_key(&key)?;
+
+        Ok(EncryptedDatabase {
+            connection,
+            path,
+            key,
+            wrapped_key,
+            _lock: lock,
+            persisted: Cell::new(None),
+        })
+    }
+
+    /// Encrypt the current state of the catalog to disk, atomically
+    pub fn persist(&self) -> DatabaseResult<()> {
+        let revision = revision(&self.connection)?;
+
+        self.write_to(&self.path)?;
+        self.persisted.set(Some(revision));
+
+        Ok(())
+    }
+
+    /// Whether the catalog holds anything that has not been persisted yet
+    pub fn is_dirty(&self) -> DatabaseResult<bool> {
+        Ok(self.persisted.get() != Some(revision(&self.connection)?))
+    }
+
+    /// Apply the pending schema migrations and persist the result. The catalog on disk is
+    /// copied first to `<path>.v<version>.bak`, renaming the copy back restores it. Catalogs
+    /// made before migrations existed may hold bare per-file keys, which get wrapped with
+    /// `master_key`
+    pub fn migrate(&self, master_key: &impl KeyWrapper) -> DatabaseResult<Migrated> {
+        if pending_migrations(self)?.is_empty() {
+            return Ok(Migrated::default());
+        }
+
+        let version = schema_version(self)?;
+
+        let backup = if self.path.exists() {
+            let mut backup = OsString::from(&self.path);
+            backup.push(format!(".v{version}.bak"));
+
+            // The loaded catalog is still the one on disk, but written as a copy it gets
+            // encrypted if it was plaintext
+            let backup = PathBuf::from(backup);
+            self.write_to(&backup)?;
+
+            Some(backup)
+        } else {
+            None
+        };
+
+        let applied = migrate(self)?;
+
+        if version == 0 {
+            let wrapped = models::File::wrap_legacy_keys(self, master_key)?;
+            log::info!("Wrapped {wrapped} legacy per-file keys");
+        }
+
+        self.persist()?;
+
+        Ok(Migrated { applied, backup })
+    }
+
+    /// Encrypt the current state of the catalog to `path`, atomically
+    fn write_to(&self, path: &Path) -> DatabaseResult<()> {
+        let mut header = Vec::from(MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(&(self.wrapped_key.len() as u16).to_be_bytes());
+        header.extend_from_slice(&self.wrapped_key);
+
+        let mut contents = header.clone();
+        with_serialized(&self.connection, |plaintext| {
+            Ok(seal_into(&self.key, plaintext, &header, &mut contents)?)
+        })?;
+
+        write_atomically(path, &contents)?;
+
+        Ok(())
+    }
+
+    /// Wrap the catalog key with a new master key. It is written to disk on `persist`
+    pub fn rewrap_key(&mut self, master_key: &impl KeyWrapper) -> DatabaseResult<()> {
+        self.wrapped_key = master_key.wrap_key(&self.key)?;
+        self.persisted.set(None);
+
+        Ok(())
+    }
+
+    /// Persist the catalog, unless nothing changed since it was read, and close it
+    pub fn close(self) -> DatabaseResult<()> {
+        if self.is_dirty()? {
+            self.persist()?;
+        }
+
+        self.connection.close().map_err(|(_, error)| error)?;
+
+        Ok(())
+    }
+}
+
+/// The outcome of `EncryptedDatabase::migrate`
+#[derive(Debug, Default)]
+pub struct Migrated {
+    pub applied: &'static [Migration],
+    /// Where the catalog was copied before migrating, if it was on disk
+    pub backup: Option<PathBuf>,
+}
+
+impl Deref for EncryptedDatabase {
+    type Target = Connection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.connection
+    }
+}
+
+impl DerefMut for EncryptedDatabase {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.connection
+    }
+}
+
+/// Lock `<path>.lock`, or fail with `CatalogInUse` when another catalog holds it
+fn lock(path: &Path) -> DatabaseResult<File> {
+    let mut lock_path = OsString::from(path);
+    lock_path.push(".lock");
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(lock_path)?;
+
+    // SAFETY: the descriptor is valid as long as `lock`, and flock does not touch memory
+    if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let error = io::Error::last_os_error();
+
+        return Err(match error.kind() {
+            io::ErrorKind::WouldBlock => DatabaseError::CatalogInUse(path.to_path_buf()),
+            _ => error.into(),
+        });
+    }
+
+    Ok(lock)
+}
+
+/// Where `connection` stands, rows and schema changes included
+fn revision(connection: &Connection) -> DatabaseResult<Revision> {
+    // SAFETY: the handle is valid for the lifetime of `connection`
+    let changes = unsafe { ffi::sqlite3_total_changes(connection.handle()) };
+    let schema = connection.pragma_query_value(None, "schema_version", |row| row.get(0))?;
+
+    Ok((changes, schema))
+}
+
+/// Split the catalog file into the authenticated header, the wrapped key and the sealed
+/// catalog
+fn split(contents: &[u8]) -> DatabaseResult<(&[u8], &[u8], &[u8])> {
+    let key_start = MAGIC.len() + 3;
+
+    if contents.len() < key_start || !contents.starts_with(MAGIC) {
+        return Err(DatabaseError::InvalidCatalog);
+    }
+
+    if contents[MAGIC.len()] != VERSION {
+        return Err(DatabaseError::InvalidCatalog);
+    }
+
+    let key_len = u16::from_be_bytes([contents[key_start - 2], contents[key_start - 1]]) as usize;
+
+    if contents.len() < key_start + key_len {
+        return Err(DatabaseError::InvalidCatalog);
+    }
+
+    let (header, sealed) = contents.split_at(key_start + key_len);
+
+    Ok((header, &header[key_start..], sealed))
+}
+
+/// Hand the whole database in `connection` to `f`. The memory holding it is handed out as it
+/// is when the connection has been loaded by `deserialize`, instead of a copy that would
+/// double the memory used by large catalogs
+fn with_serialized<T>(
+    connection: &Connection,
+    f: impl FnOnce(&[u8]) -> DatabaseResult<T>,
+) -> DatabaseResult<T> {
+    let mut size: ffi::sqlite3_int64 = 0;
+
+    // SAFETY: the handle is valid for the lifetime of `connection`. With NOCOPY nothing is
+    // allocated, the database memory is only valid until the connection is used again,
+    // which cannot happen while it is borrowed here
+    let data = unsafe {
+        ffi::sqlite3_serialize(
+            connection.handle(),
+            MAIN_SCHEMA.as_ptr().cast(),
+            &mut size,
+            ffi::SQLITE_SERIALIZE_NOCOPY as u32,
+        )
+    };
+
+    if data.is_null() {
+        return f(&serialize(connection)?);
+    }
+
+    // SAFETY: see above, `data` is `size` bytes long
+    f(unsafe { std::slice::from_raw_parts(data, size as usize) })
+}
+
+/// Copy the whole database out of `connection`
+fn serialize(connection: &Connection) -> DatabaseResult<Zeroizing<Vec<u8>>> {
+    let mut size: ffi::sqlite3_int64 = 0;
+
+    // SAFETY: the handle is valid for the lifetime of `connection`, the returned buffer is
+    // `size` bytes long and is owned by us until sqlite3_free
+    unsafe {
+        let data = ffi::sqlite3_serialize(
+            connection.handle(),
+            MAIN_SCHEMA.as_ptr().cast(),
+            &mut size,
+            0,
+        );
+
+        if data.is_null() {
+            return Err(sqlite_error(ffi::SQLITE_NOMEM));
+        }
+
+        let mut contents = Zeroizing::new(vec![0u8; size as usize]);
+        copy_nonoverlapping(data, contents.as_mut_ptr(), size as usize);
+
+        std::ptr::write_bytes(data, 0, size as usize);
+        ffi::sqlite3_free(data.cast());
+
+        Ok(contents)
+    }
+}
+
+/// Load a whole database into a new in-memory connection
+fn deserialize(contents: &[u8]) -> DatabaseResult<Connection> {
+    let connection = Connection::open_in_memory()?;
+    let size = contents.len() as ffi::sqlite3_int64;
+
+    // SAFETY: the buffer is allocated with sqlite3_malloc64 as required by
+    // SQLITE_DESERIALIZE_FREEONCLOSE, from then on it is owned by SQLite
+    unsafe {
+        let data = ffi::sqlite3_malloc64(contents.len() as u64) as *mut u8;
+
+        if data.is_null() {
+            return Err(sqlite_error(ffi::SQLITE_NOMEM));
+        }
+
+        copy_nonoverlapping(contents.as_ptr(), data, contents.len());
+
+        let result = ffi::sqlite3_deserialize(
+            connection.handle(),
+            MAIN_SCHEMA.as_ptr().cast(),
+            data,
+            size,
+            size,
+            (ffi::SQLITE_DESERIALIZE_FREEONCLOSE | ffi::SQLITE_DESERIALIZE_RESIZEABLE) as u32,
+        );
+
+        if result != ffi::SQLITE_OK {
+            return Err(sqlite_error(result));
+        }
+    }
+
+    Ok(connection)
+}
+
+fn sqlite_error(code: i32) -> DatabaseError {
+    DatabaseError::Rusqlite(rusqlite::Error::SqliteFailure(ffi::Error::new(code), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{metadata, read, write},
+        os::unix::fs::PermissionsExt,
+    };
+
+    use crypto::{
+        crypt::{
+            generate_random_secure_key, generate_random_secure_key_nonce_pair, FileEncryptUnit,
+            SecretKey,
+        },
+        traits::ComputeUnit,
+    };
+    use rusqlite::{ffi, params, Connection};
+    use tmp::Tmp;
+
+    use crate::{
+        errors::DatabaseError,
+        migrations::{latest_version, schema_version, MIGRATIONS},
+        models::{File, Tag},
+        traits::Insert,
+        utils::load_schema,
+    };
+
+    use super::{EncryptedDatabase, MAIN_SCHEMA};
+
+    fn count_tags(connection: &Connection) -> i64 {
+        connection
+            .query_row("SELECT COUNT(*) FROM tag", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let master_key = generate_random_secure_key();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        Tag::new("holiday-photos").insert(&database).unwrap();
+        database.close().unwrap();
+
+        // Nothing is readable without the key
+        let contents = read(&path).unwrap();
+        assert!(!contents.windows(14).any(|w| w == b"holiday-photos"));
+        assert!(!contents.starts_with(b"SQLite format 3"));
+        assert_eq!(metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!tmp.base_path().join("database.db.tmp").exists());
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        assert_eq!(count_tags(&database), 1);
+        database.close().unwrap();
+
+        assert!(matches!(
+            EncryptedDatabase::open_or_create(&path, &generate_random_secure_key()),
+            Err(DatabaseError::Crypto(_))
+        ));
+    }
+
+    #[test]
+    fn test_catalog_in_use() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let master_key = generate_random_secure_key();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        Tag::new("first").insert(&database).unwrap();
+
+        assert!(matches!(
+            EncryptedDatabase::open_or_create(&path, &master_key),
+            Err(DatabaseError::CatalogInUse(in_use)) if in_use == path
+        ));
+
+        // Released once closed
+        database.close().unwrap();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        assert_eq!(count_tags(&database), 1);
+    }
+
+    #[test]
+    fn test_close_persists_only_changes() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let master_key = generate_random_secure_key();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        database.close().unwrap();
+        let contents = read(&path).unwrap();
+
+        // Every write seals with a new nonce, so equal contents mean no write
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        assert!(!database.is_dirty().unwrap());
+        database.close().unwrap();
+        assert_eq!(read(&path).unwrap(), contents);
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        Tag::new("holiday-photos").insert(&database).unwrap();
+        assert!(database.is_dirty().unwrap());
+        database.close().unwrap();
+        assert_ne!(read(&path).unwrap(), contents);
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        database.execute_batch("DROP TABLE `tag`").unwrap();
+        assert!(database.is_dirty().unwrap());
+    }
+
+    #[test]
+    fn test_serialized_without_copy() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let master_key = generate_random_secure_key();
+
+        let in_place = |database: &EncryptedDatabase| {
+            let mut size = 0;
+            // SAFETY: the handle is valid, NOCOPY allocates nothing
+            let data = unsafe {
+                ffi::sqlite3_serialize(
+                    database.handle(),
+                    MAIN_SCHEMA.as_ptr().cast(),
+                    &mut size,
+                    ffi::SQLITE_SERIALIZE_NOCOPY as u32,
+                )
+            };
+
+            !data.is_null()
+        };
+
+        // Both a new catalog and one read from disk
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        Tag::new("holiday-photos").insert(&database).unwrap();
+        assert!(in_place(&database));
+        database.close().unwrap();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        assert!(in_place(&database));
+        assert_eq!(count_tags(&database), 1);
+    }
+
+    #[test]
+    fn test_rewrap_key() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let old_master_key = generate_random_secure_key();
+        let new_master_key = generate_random_secure_key();
+
+        let mut database = EncryptedDatabase::open_or_create(&path, &old_master_key).unwrap();
+        database.rewrap_key(&new_master_key).unwrap();
+        database.close().unwrap();
+
+        assert!(EncryptedDatabase::open_or_create(&path, &old_master_key).is_err());
+        assert!(EncryptedDatabase::open_or_create(&path, &new_master_key).is_ok());
+    }
+
+    #[test]
+    fn test_encrypt_plaintext_database() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let master_key = generate_random_secure_key();
+
+        let connection = Connection::open(&path).unwrap();
+        load_schema(&connection).unwrap();
+        Tag::new("legacy").insert(&connection).unwrap();
+        connection.close().unwrap();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        assert_eq!(count_tags(&database), 1);
+        database.close().unwrap();
+
+        assert!(!read(&path).unwrap().starts_with(b"SQLite format 3"));
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        assert_eq!(count_tags(&database), 1);
+    }
+
+    #[test]
+    fn test_migrate_with_backup() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let master_key = generate_random_secure_key();
+
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(include_str!("../fixtures/v0.sql"))
+            .unwrap();
+        connection.close().unwrap();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        let migrated = database.migrate(&master_key).unwrap();
+        assert_eq!(migrated.applied.len(), MIGRATIONS.len());
+        database.close().unwrap();
+
+        // The backup is encrypted too and holds the old schema
+        let backup = migrated.backup.unwrap();
+        assert!(!read(&backup).unwrap().starts_with(b"SQLite format 3"));
+
+        let database = EncryptedDatabase::open_or_create(&backup, &master_key).unwrap();
+        assert_eq!(schema_version(&database).unwrap(), 0);
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        assert_eq!(schema_version(&database).unwrap(), latest_version());
+        assert!(database.migrate(&master_key).unwrap().applied.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_wraps_legacy_keys() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("database.db");
+        let master_key = generate_random_secure_key();
+
+        // Encrypted by a version which stored the per-file key as it is
+        let plaintext = tmp.base_path().join("plaintext");
+        write(&plaintext, b"legacy contents").unwrap();
+
+        let (key, nonce) = generate_random_secure_key_nonce_pair();
+        let locked = tmp.base_path().join("legacy");
+        let key_copy = SecretKey::try_from_slice(key.as_bytes()).unwrap();
+        FileEncryptUnit::try_new(&plaintext, &locked, key_copy, nonce)
+            .unwrap()
+            .start()
+            .unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(include_str!("../fixtures/v0.sql"))
+            .unwrap();
+        connection
+            .execute(
+                concat!(
+                    "INSERT INTO `file` (`title`, `path`, `locked_hash`, `contents_hash`, `size`, ",
+                    "`created_at`, `updated_at`, `key`, `nonce`) VALUES ('legacy.txt', ",
+                    "'legacy.txt', 'legacy', '', 15, '2022-08-03 10:00:00', ",
+                    "'2022-08-03 10:00:00', ?, ?)"
+                ),
+                params![key.as_bytes(), nonce.as_slice()],
+            )
+            .unwrap();
+        connection.close().unwrap();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        database.migrate(&master_key).unwrap();
+        database.close().unwrap();
+
+        let database = EncryptedDatabase::open_or_create(&path, &master_key).unwrap();
+        let file = File::find_by_path(&database, "legacy.txt")
+            .unwrap()
+            .unwrap();
+        assert_ne!(file.key.as_bytes(), key.as_bytes());
+        assert_eq!(file.unwrap_key(&master_key).unwrap(), key);
+
+        let unlocked = tmp.base_path().join("unlocked");
+        file.try_into_decryptor(tmp.base_path(), unlocked.clone(), &master_key)
+            .unwrap()
+            .start()
+            .unwrap();
+        assert_eq!(read(&unlocked).unwrap(), b"legacy contents");
+
+        // Keys are wrapped only once
+        assert!(database.migrate(&master_key).unwrap().applied.is_empty());
+        assert_eq!(File::wrap_legacy_keys(&database, &master_key).unwrap(), 0);
+    }
+}
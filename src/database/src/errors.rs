@@ -8,4 +8,14 @@ pub enum DatabaseError {
     Rusqlite(#[from] rusqlite::Error),
     #[error("Input/Output error")]
     IOError(#[from] std::io::Error),
+    #[error("Crypto error: {0}")]
+    Crypto(#[from] crypto::errors::CryptoError),
+    #[error("The database file is corrupted or is not a krypta database")]
+    InvalidCatalog,
+    #[error("The database schema version {0} is newer than this version of krypta")]
+    UnsupportedSchemaVersion(u32),
+    #[error("The database {0:?} is in use by another krypta process")]
+    CatalogInUse(std::path::PathBuf),
+    #[error("Please set DATABASE_FILE to the path of the krypta database")]
+    DatabaseFileNotSet,
 }
@@ -1,6 +1,14 @@
+mod encrypted;
 mod utils;
-pub use crate::utils::{connect_or_create, create_in_memory, database_file, Database};
+mod wrapped_key;
+pub use crate::encrypted::{EncryptedDatabase, Migrated};
+pub use crate::utils::{
+    agent_socket_file, connect_or_create, create_in_memory, database_file, recreate_schema,
+    vault_file, Database,
+};
+pub use crate::wrapped_key::WrappedKey;
 
 pub mod errors;
+pub mod migrations;
 pub mod models;
 pub mod traits;
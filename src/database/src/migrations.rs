@@ -0,0 +1,201 @@
+use crate::{
+    errors::{DatabaseError, DatabaseResult},
+    Database,
+};
+
+/// A change to the schema. The schema version is stored in `PRAGMA user_version` and is the
+/// version of the last applied migration, 0 for databases created before migrations existed
+#[derive(Debug)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    sql: &'static str,
+}
+
+/// Every migration, in order. Never edit an applied migration, add a new one instead
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "pending_rekey",
+        sql: include_str!("../migrations/0002_pending_rekey.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "file_attributes",
+        sql: include_str!("../migrations/0003_file_attributes.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "entry_kinds",
+        sql: include_str!("../migrations/0004_entry_kinds.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "escape_paths",
+        sql: include_str!("../migrations/0005_escape_paths.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "cipher_format",
+        sql: include_str!("../migrations/0006_cipher_format.sql"),
+    },
+];
+
+/// The version of the schema after every migration has been applied
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// The version of the schema of `db`
+pub fn schema_version(db: &Database) -> DatabaseResult<u32> {
+    Ok(db.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// The migrations that still have to be applied to `db`
+pub fn pending_migrations(db: &Database) -> DatabaseResult<&'static [Migration]> {
+    let version = schema_version(db)?;
+
+    if version > latest_version() {
+        return Err(DatabaseError::UnsupportedSchemaVersion(version));
+    }
+
+    let applied = MIGRATIONS.partition_point(|migration| migration.version <= version);
+
+    Ok(&MIGRATIONS[applied..])
+}
+
+/// Apply the pending migrations in a single transaction, so that a failure leaves the
+/// database untouched. Returns the applied migrations
+pub fn migrate(db: &Database) -> DatabaseResult<&'static [Migration]> {
+    let pending = pending_migrations(db)?;
+
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+
+    let transaction = db.unchecked_transaction()?;
+
+    for migration in pending {
+        log::info!(
+            "Applying migration {} {}",
+            migration.version,
+            migration.name
+        );
+
+        transaction.execute_batch(migration.sql)?;
+        transaction.pragma_update(None, "user_version", migration.version)?;
+    }
+
+    transaction.commit()?;
+
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use fs::decode_path;
+    use rusqlite::Connection;
+
+    use crate::{errors::DatabaseError, models::Tag, traits::Insert};
+
+    use super::{latest_version, migrate, pending_migrations, schema_version, MIGRATIONS};
+
+    fn v0_fixture() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(include_str!("../fixtures/v0.sql"))
+            .unwrap();
+
+        connection
+    }
+
+    fn table_exists(connection: &Connection, table: &str) -> bool {
+        connection
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+                [table],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap()
+            == 1
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, index + 1);
+        }
+    }
+
+    #[test]
+    fn test_migrate_v0_fixture() {
+        let connection = v0_fixture();
+
+        assert_eq!(schema_version(&connection).unwrap(), 0);
+        assert_eq!(
+            pending_migrations(&connection).unwrap().len(),
+            MIGRATIONS.len()
+        );
+        assert!(!table_exists(&connection, "pending_rekey"));
+
+        let applied = migrate(&connection).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+
+        assert_eq!(schema_version(&connection).unwrap(), latest_version());
+        assert!(pending_migrations(&connection).unwrap().is_empty());
+        assert!(table_exists(&connection, "pending_rekey"));
+
+        // The data survives
+        let path: String = connection
+            .query_row(
+                "SELECT path FROM file JOIN file_tag ON file.id = file_tag.file_id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(path, "photos/beach.jpg");
+
+        // Paths are escaped
+        let path: String = connection
+            .query_row("SELECT path FROM file WHERE id = 2", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(decode_path(path), Path::new(r"docs/back\slash.txt"));
+
+        // Migrating again does nothing
+        assert!(migrate(&connection).unwrap().is_empty());
+        Tag::new("new-tag").insert(&connection).unwrap();
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let connection = v0_fixture();
+
+        // Make migration 2 fail, after migration 1 has been applied
+        connection
+            .execute_batch("CREATE INDEX `pending_rekey` ON `tag` (`name`)")
+            .unwrap();
+
+        assert!(migrate(&connection).is_err());
+        assert_eq!(schema_version(&connection).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_newer_schema_is_rejected() {
+        let connection = v0_fixture();
+        connection
+            .pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        assert!(matches!(
+            migrate(&connection),
+            Err(DatabaseError::UnsupportedSchemaVersion(_))
+        ));
+    }
+}
@@ -1,56 +1,136 @@
 use std::any::type_name;
-use std::fmt::Debug;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 use std::{fs::Metadata, path::PathBuf};
 
 use chrono::{DateTime, Utc};
 use crypto::blake3;
 use crypto::crypt::{
-    generate_random_secure_key_nonce_pair, FileEncryptUnit, AEAD_KEY_SIZE, AEAD_NONCE_SIZE,
+    generate_random_secure_key_nonce_pair, open_sealed, seal, CipherFormat, FileDecryptUnit,
+    FileEncryptUnit, FileReencryptUnit, KeyWrapper, NonceArray, SecretKey, AEAD_KEY_SIZE,
+    AEAD_NONCE_SIZE,
 };
 use crypto::errors::CryptoError;
 use database_macros::{Insert, TableName, TryFromRow};
-use fs::PathTree;
-use rusqlite::named_params;
+use fs::{decode_path, encode_path, FileAttributes, PathTree};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{named_params, OptionalExtension};
+use zeroize::Zeroizing;
 
-use crate::{errors::DatabaseResult, Database};
+use crate::{errors::DatabaseResult, Database, WrappedKey};
 
 use crate::traits::{Count, FetchAll, Get, InsertMany, Search, TryFromRow, Update, UpdateMany};
 
 use super::Tag;
 
-#[derive(TableName, TryFromRow, Insert, Clone, PartialEq, Eq)]
+#[derive(TableName, TryFromRow, Insert, Debug, Clone, PartialEq, Eq)]
 pub struct File {
     pub id: Option<i64>,
     pub title: String,
+    /// Encoded with `fs::encode_path`, which keeps paths that are not valid UTF-8
     pub path: String,
     pub locked_hash: String,
     pub contents_hash: String,
     pub size: u64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-    pub key: Vec<u8>,
+    pub key: WrappedKey,
     pub nonce: Vec<u8>,
+    /// Attributes of the source file, missing for files added by older versions
+    pub modified_at: Option<DateTime<Utc>>,
+    pub accessed_at: Option<DateTime<Utc>>,
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub xattrs: Option<Vec<u8>>,
+    pub kind: EntryKind,
+    /// Target of a symbolic link, sealed with the per-file key
+    pub target: Option<Vec<u8>>,
+    /// How the contents are laid out in the locked path
+    pub format: ContentsFormat,
 }
 
-/// don't include crypto key and nonce in debug
-impl Debug for File {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut d = f.debug_struct("File");
+/// What a `File` row describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A regular file, encrypted into the locked path
+    File,
+    /// A symbolic link, which has no contents in the locked path
+    Symlink,
+    /// An empty directory, which has no contents in the locked path
+    Directory,
+}
+
+impl ToSql for EntryKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let kind = match self {
+            EntryKind::File => "file",
+            EntryKind::Symlink => "symlink",
+            EntryKind::Directory => "directory",
+        };
+
+        Ok(ToSqlOutput::Borrowed(ValueRef::Text(kind.as_bytes())))
+    }
+}
+
+impl FromSql for EntryKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "file" => Ok(EntryKind::File),
+            "symlink" => Ok(EntryKind::Symlink),
+            "directory" => Ok(EntryKind::Directory),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// How the contents of a `File` are encrypted, see `crypto::crypt::CipherFormat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentsFormat {
+    /// Encrypted one chunk after the other, as every file added by older versions
+    Stream,
+    /// Encrypted in independent chunks, in parallel
+    Chunked,
+}
 
-        if let Some(id) = self.id {
-            d.field("id", &id);
+impl From<ContentsFormat> for CipherFormat {
+    fn from(format: ContentsFormat) -> Self {
+        match format {
+            ContentsFormat::Stream => CipherFormat::Stream,
+            ContentsFormat::Chunked => CipherFormat::Chunked,
         }
+    }
+}
+
+impl From<CipherFormat> for ContentsFormat {
+    fn from(format: CipherFormat) -> Self {
+        match format {
+            CipherFormat::Stream => ContentsFormat::Stream,
+            CipherFormat::Chunked => ContentsFormat::Chunked,
+        }
+    }
+}
 
-        d.field("title", &self.title)
-            .field("path", &self.path)
-            .field("locked_hash", &self.locked_hash)
-            .field("contents_hash", &self.contents_hash)
-            .field("size", &self.size)
-            .field("created_at", &self.created_at)
-            .field("updated_at", &self.updated_at)
-            .finish()
+impl ToSql for ContentsFormat {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let format = match self {
+            ContentsFormat::Stream => "stream",
+            ContentsFormat::Chunked => "chunked",
+        };
+
+        Ok(ToSqlOutput::Borrowed(ValueRef::Text(format.as_bytes())))
+    }
+}
+
+impl FromSql for ContentsFormat {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "stream" => Ok(ContentsFormat::Stream),
+            "chunked" => Ok(ContentsFormat::Chunked),
+            _ => Err(FromSqlError::InvalidType),
+        }
     }
 }
 
@@ -111,6 +191,15 @@ impl Update for File {
                 ":updated_at": self.updated_at,
                 ":key": self.key,
                 ":nonce": self.nonce,
+                ":modified_at": self.modified_at,
+                ":accessed_at": self.accessed_at,
+                ":mode": self.mode,
+                ":uid": self.uid,
+                ":gid": self.gid,
+                ":xattrs": self.xattrs,
+                ":kind": self.kind,
+                ":target": self.target,
+                ":format": self.format,
                 ":id": self.id
             },
             |row| File::try_from_row(row),
@@ -124,25 +213,32 @@ impl UpdateMany for File {}
 
 impl From<&File> for PathBuf {
     fn from(file: &File) -> Self {
-        PathBuf::from(&file.path)
+        decode_path(&file.path)
     }
 }
 
 impl File {
-    /// Build a new `File` and generate on the fly some stuff
-    pub fn new(title: String, path: PathBuf, contents_hash: String, size: u64) -> Self {
-        let locked_hash = File::locked_hash_string(&contents_hash);
+    /// Build a new `File` and generate on the fly some stuff. The per-file key is stored
+    /// wrapped with `master_key`, and `size` picks the format of the contents
+    pub fn new(
+        title: String,
+        path: PathBuf,
+        contents_hash: String,
+        size: u64,
+        master_key: &impl KeyWrapper,
+    ) -> Result<Self, CryptoError> {
         let now = chrono::Utc::now();
 
         // Key and nonce generation
         let (key, nonce) = generate_random_secure_key_nonce_pair();
-        let key = Vec::from(key.as_slice());
+        let locked_hash = File::locked_hash_string(&contents_hash, &nonce);
+        let key = WrappedKey::wrap(master_key, &key)?;
         let nonce = Vec::from(nonce.as_slice());
 
-        File {
+        Ok(File {
             id: None,
             title,
-            path: path.to_string_lossy().to_string(),
+            path: encode_path(path),
             locked_hash,
             contents_hash,
             size,
@@ -150,17 +246,149 @@ impl File {
             updated_at: now,
             key,
             nonce,
+            modified_at: None,
+            accessed_at: None,
+            mode: None,
+            uid: None,
+            gid: None,
+            xattrs: None,
+            kind: EntryKind::File,
+            target: None,
+            format: CipherFormat::for_size(size).into(),
+        })
+    }
+
+    /// Build a symbolic link to `target`, which is sealed with the per-file key
+    pub fn new_symlink(
+        title: String,
+        path: PathBuf,
+        target: impl AsRef<Path>,
+        master_key: &impl KeyWrapper,
+    ) -> Result<Self, CryptoError> {
+        let mut file = File::new(title, path, String::new(), 0, master_key)?;
+        let key = file.unwrap_key(master_key)?;
+
+        file.kind = EntryKind::Symlink;
+        file.target = Some(seal(&key, target.as_ref().as_os_str().as_bytes(), &[])?);
+
+        Ok(file)
+    }
+
+    /// Build an empty directory
+    pub fn new_directory(
+        title: String,
+        path: PathBuf,
+        master_key: &impl KeyWrapper,
+    ) -> Result<Self, CryptoError> {
+        let mut file = File::new(title, path, String::new(), 0, master_key)?;
+        file.kind = EntryKind::Directory;
+
+        Ok(file)
+    }
+
+    /// The target of a symbolic link, `None` for the other kinds
+    pub fn symlink_target(
+        &self,
+        master_key: &impl KeyWrapper,
+    ) -> Result<Option<PathBuf>, CryptoError> {
+        let target = match &self.target {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+
+        let key = self.unwrap_key(master_key)?;
+        let target = open_sealed(&key, target, &[])?;
+
+        Ok(Some(PathBuf::from(OsString::from_vec(target))))
+    }
+
+    /// Rotate the key of an entry without contents in the locked path, sealing the target
+    /// of a symbolic link again. `previous_master_key` unwraps the current key
+    pub fn rekey_in_place(
+        &mut self,
+        previous_master_key: &impl KeyWrapper,
+        master_key: &impl KeyWrapper,
+    ) -> Result<(), CryptoError> {
+        assert_ne!(
+            self.kind,
+            EntryKind::File,
+            "files are rekeyed with PendingRekey"
+        );
+
+        let target = self.symlink_target(previous_master_key)?;
+        let (key, nonce) = generate_random_secure_key_nonce_pair();
+
+        self.target = match target {
+            Some(target) => Some(seal(&key, target.as_os_str().as_bytes(), &[])?),
+            None => None,
+        };
+        self.locked_hash = File::locked_hash_string(&self.contents_hash, &nonce);
+        self.key = WrappedKey::wrap(master_key, &key)?;
+        self.nonce = Vec::from(nonce.as_slice());
+
+        Ok(())
+    }
+
+    /// Store the attributes of the source file, to be restored on extract
+    pub fn set_attributes(&mut self, attributes: &FileAttributes) {
+        self.modified_at = attributes.modified_at.map(DateTime::from);
+        self.accessed_at = attributes.accessed_at.map(DateTime::from);
+        self.mode = attributes.mode;
+        self.uid = attributes.uid;
+        self.gid = attributes.gid;
+        self.xattrs = encode_xattrs(&attributes.xattrs);
+    }
+
+    /// The stored attributes of the source file
+    pub fn attributes(&self) -> FileAttributes {
+        FileAttributes {
+            modified_at: self.modified_at.map(SystemTime::from),
+            accessed_at: self.accessed_at.map(SystemTime::from),
+            mode: self.mode,
+            uid: self.uid,
+            gid: self.gid,
+            xattrs: self
+                .xattrs
+                .as_deref()
+                .map(decode_xattrs)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Search files by title, keeping those whose source file was modified in
+    /// `[modified_after, modified_before)`. Files without a modification time match only
+    /// when there are no bounds
+    pub fn search_modified(
+        db: &Database,
+        query: impl AsRef<str>,
+        modified_after: Option<DateTime<Utc>>,
+        modified_before: Option<DateTime<Utc>>,
+    ) -> DatabaseResult<Vec<Self>> {
+        let mut stmt = db.prepare(include_str!("sql/file/search_modified.sql"))?;
+        let mut rows = stmt.query(named_params! {
+            ":query": format!("%{}%", query.as_ref()),
+            ":modified_after": modified_after,
+            ":modified_before": modified_before,
+        })?;
+
+        let mut files = vec![];
+        while let Some(row) = rows.next()? {
+            files.push(File::try_from_row(row)?);
         }
+
+        Ok(files)
     }
 
-    /// Derive locked_hash from contents_hash + salt
-    fn locked_hash_string(contents_hash: impl AsRef<str>) -> String {
+    /// Derive locked_hash from contents_hash + salt + nonce, so that files with the same
+    /// contents, or the same file encrypted with a new key, get different locked names
+    pub(crate) fn locked_hash_string(contents_hash: impl AsRef<str>, nonce: &[u8]) -> String {
         let contents_hash = contents_hash.as_ref();
         let salt = "chicken mcnuggets";
 
         let mut hasher = blake3::Hasher::new();
         hasher.update(contents_hash.as_bytes());
         hasher.update(salt.as_bytes());
+        hasher.update(nonce);
 
         hasher.finalize().to_string()
     }
@@ -174,13 +402,26 @@ impl File {
     fn find_file_from_path(db: &Database, path: &Path) -> DatabaseResult<File> {
         let file = db.query_row(
             include_str!("sql/file/find_file_from_path.sql"),
-            named_params! { ":path":path.to_string_lossy() },
+            named_params! { ":path": encode_path(path) },
             |row| File::try_from_row(row),
         )?;
 
         Ok(file)
     }
 
+    /// Get the file in `path`, if any
+    pub fn find_by_path(db: &Database, path: impl AsRef<Path>) -> DatabaseResult<Option<File>> {
+        let file = db
+            .query_row(
+                include_str!("sql/file/find_file_from_path.sql"),
+                named_params! { ":path": encode_path(path) },
+                File::try_from_row,
+            )
+            .optional()?;
+
+        Ok(file)
+    }
+
     /// Get files matching paths
     pub fn find_files_from_paths(
         db: &mut Database,
@@ -220,24 +461,120 @@ impl File {
         Ok(size)
     }
 
+    /// Set the hash and size of contents that were only known after encrypting them, such
+    /// as the ones read from a stream. The locked hash changes accordingly
+    pub fn set_contents(&mut self, contents_hash: String, size: u64) {
+        self.locked_hash = File::locked_hash_string(&contents_hash, &self.nonce);
+        self.contents_hash = contents_hash;
+        self.size = size;
+    }
+
+    /// Unwrap the per-file key with `master_key`
+    pub fn unwrap_key(&self, master_key: &impl KeyWrapper) -> Result<SecretKey, CryptoError> {
+        self.key.unwrap(master_key)
+    }
+
+    /// Wrap with `master_key` the per-file keys that versions older than the vault stored
+    /// as they are, which are the only keys as long as a bare key. Returns how many there were
+    pub fn wrap_legacy_keys(db: &Database, master_key: &impl KeyWrapper) -> DatabaseResult<usize> {
+        let legacy_keys = db
+            .prepare(include_str!("sql/file/legacy_keys.sql"))?
+            .query_map(named_params! { ":key_size": AEAD_KEY_SIZE }, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    Zeroizing::new(row.get::<_, Vec<u8>>(1)?),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (id, key) in &legacy_keys {
+            let key = WrappedKey::wrap(master_key, &SecretKey::try_from_slice(key)?)?;
+
+            db.execute(
+                include_str!("sql/file/update_key.sql"),
+                named_params! { ":id": id, ":key": key },
+            )?;
+        }
+
+        Ok(legacy_keys.len())
+    }
+
+    /// Count the files sharing the same locked name
+    pub fn count_locked_hash(db: &Database, locked_hash: impl AsRef<str>) -> DatabaseResult<i64> {
+        let count = db.query_row(
+            include_str!("sql/file/count_locked_hash.sql"),
+            named_params! { ":locked_hash": locked_hash.as_ref() },
+            |row| row.get(0),
+        )?;
+
+        Ok(count)
+    }
+
     /// Convert self into a crypto::Encryptor, if possible
     pub fn try_into_encryptor<P: AsRef<Path>>(
         self,
         locked_path: P,
         source_path: P,
+        master_key: &impl KeyWrapper,
     ) -> Result<FileEncryptUnit, CryptoError> {
+        let key = self.unwrap_key(master_key)?;
+
         // Build absolute paths
         let mut source = source_path.as_ref().to_owned();
-        source.push(self.path);
+        source.push(decode_path(&self.path));
+
+        let mut locked = locked_path.as_ref().to_owned();
+        locked.push(self.locked_hash);
+
+        // Should never fail as nonce len is constant
+        let nonce: [u8; AEAD_NONCE_SIZE] = self.nonce.try_into().unwrap();
+
+        Ok(FileEncryptUnit::try_new(source, locked, key, nonce.into())?.format(self.format.into()))
+    }
+
+    /// Convert self into a crypto::Decryptor writing into `unlocked_path`, if possible
+    pub fn try_into_decryptor<P: AsRef<Path>>(
+        self,
+        locked_path: P,
+        unlocked_path: P,
+        master_key: &impl KeyWrapper,
+    ) -> Result<FileDecryptUnit, CryptoError> {
+        let key = self.unwrap_key(master_key)?;
 
         let mut locked = locked_path.as_ref().to_owned();
         locked.push(self.locked_hash);
 
-        // Should never fail as key and nonce lens are constant
-        let key: [u8; AEAD_KEY_SIZE] = self.key.try_into().unwrap();
+        // Should never fail as nonce len is constant
         let nonce: [u8; AEAD_NONCE_SIZE] = self.nonce.try_into().unwrap();
 
-        FileEncryptUnit::try_new(source, locked, key.into(), nonce.into())
+        Ok(
+            FileDecryptUnit::try_new(locked, unlocked_path.as_ref().to_owned(), key, nonce.into())?
+                .format(self.format.into()),
+        )
+    }
+
+    /// Convert self into a crypto::Reencryptor moving its contents under `new_key` and
+    /// `new_nonce`, into `new_locked_hash` in `locked_path`, if possible
+    pub fn try_into_reencryptor(
+        self,
+        locked_path: impl AsRef<Path>,
+        new_locked_hash: impl AsRef<Path>,
+        (new_key, new_nonce): (SecretKey, NonceArray),
+        master_key: &impl KeyWrapper,
+    ) -> Result<FileReencryptUnit, CryptoError> {
+        let key = self.unwrap_key(master_key)?;
+        let locked_path = locked_path.as_ref();
+
+        // Should never fail as nonce len is constant
+        let nonce: [u8; AEAD_NONCE_SIZE] = self.nonce.try_into().unwrap();
+
+        Ok(FileReencryptUnit::try_new(
+            locked_path.join(self.locked_hash),
+            locked_path.join(new_locked_hash),
+            (key, nonce.into()),
+            (new_key, new_nonce),
+        )?
+        .format(self.format.into()))
     }
 
     /// Get a list of tags related to a File
@@ -268,7 +605,7 @@ impl MetadataFile {
         let path = path.as_ref();
 
         MetadataFile {
-            title: path.to_string_lossy().to_string(),
+            title: encode_path(path),
             path: path.to_path_buf(),
             size: metadata.len(),
         }
@@ -276,38 +613,95 @@ impl MetadataFile {
 
     /// Converts a `MetadataFile` into a `File` with some additional fields that are
     /// not present in a `Metadata` struct
-    pub fn into_file(self, contents_hash: String) -> File {
-        File::new(self.title, self.path, contents_hash, self.size)
+    pub fn into_file(
+        self,
+        contents_hash: String,
+        master_key: &impl KeyWrapper,
+    ) -> Result<File, CryptoError> {
+        File::new(self.title, self.path, contents_hash, self.size, master_key)
+    }
+}
+
+/// Encode xattrs as a sequence of name length (u32 BE) || name || value length (u32 BE) || value
+fn encode_xattrs(xattrs: &[(OsString, Vec<u8>)]) -> Option<Vec<u8>> {
+    if xattrs.is_empty() {
+        return None;
+    }
+
+    let mut encoded = vec![];
+
+    for (name, value) in xattrs {
+        for bytes in [name.as_bytes(), value.as_slice()] {
+            encoded.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            encoded.extend_from_slice(bytes);
+        }
+    }
+
+    Some(encoded)
+}
+
+/// Decode xattrs encoded by `encode_xattrs`, stopping at the first malformed one
+fn decode_xattrs(mut encoded: &[u8]) -> Vec<(OsString, Vec<u8>)> {
+    fn next<'a>(encoded: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let (length, rest) = encoded.split_first_chunk::<4>()?;
+        let length = u32::from_be_bytes(*length) as usize;
+
+        if rest.len() < length {
+            return None;
+        }
+
+        let (bytes, rest) = rest.split_at(length);
+        *encoded = rest;
+
+        Some(bytes)
+    }
+
+    let mut xattrs = vec![];
+
+    while let (Some(name), Some(value)) = (next(&mut encoded), next(&mut encoded)) {
+        xattrs.push((OsStr::from_bytes(name).to_owned(), value.to_vec()));
     }
+
+    xattrs
 }
 
 impl FromIterator<File> for PathTree {
     fn from_iter<T: IntoIterator<Item = File>>(files: T) -> Self {
-        files
-            .into_iter()
-            .map(|f| f.path)
-            .map(PathBuf::from)
-            .collect()
+        let mut tree = PathTree::default();
+
+        for file in files {
+            let path = PathBuf::from(&file);
+
+            match file.kind {
+                EntryKind::File => tree.insert_file_path(path),
+                EntryKind::Symlink => tree.insert_symlink_path(path),
+                EntryKind::Directory => tree.insert_directory_path(path),
+            }
+        }
+
+        tree
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::ffi::OsStrExt;
     use std::path::PathBuf;
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, SystemTime};
 
-    use chrono::{DateTime, NaiveDateTime, Utc};
-    use crypto::crypt::{AEAD_KEY_SIZE, AEAD_NONCE_SIZE};
+    use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+    use crypto::crypt::{generate_random_secure_key, SecretKey, AEAD_KEY_SIZE, AEAD_NONCE_SIZE};
     use rand::SeedableRng;
     use rand_chacha::ChaCha20Rng;
     use utils::RandomString;
 
     use crate::create_in_memory;
     use crate::models::{FileTag, Tag};
-    use crate::traits::{Count, FetchAll, Insert, InsertMany, Update};
+    use crate::traits::{Count, FetchAll, Get, Insert, InsertMany, Update};
 
-    use super::File;
+    use super::{ContentsFormat, EntryKind, File, FileAttributes, PathTree};
 
     /// Generate a pseudorandom 32 bytes hex string
     fn random_hash_string() -> String {
@@ -315,13 +709,19 @@ mod tests {
         RandomString::hex_with_rng(&mut generator, 32)
     }
 
+    fn random_master_key() -> SecretKey {
+        generate_random_secure_key()
+    }
+
     fn new_random_file() -> File {
         File::new(
             RandomString::alphanum(10),
             PathBuf::from(format!("foo/bar/{}", RandomString::alphanum(10))),
             random_hash_string(),
             1337,
+            &random_master_key(),
         )
+        .unwrap()
     }
 
     #[test]
@@ -347,7 +747,9 @@ mod tests {
             PathBuf::from("/path/to/foo7bar"),
             "asdas".to_string(),
             0,
-        );
+            &random_master_key(),
+        )
+        .unwrap();
 
         assert_eq!(File::count(&database).unwrap(), 0);
         assert!(file1.insert(&database).is_ok());
@@ -358,7 +760,9 @@ mod tests {
             PathBuf::from("/path/to/foo7bar"),
             "bfsdfb".to_string(),
             0,
-        );
+            &random_master_key(),
+        )
+        .unwrap();
 
         assert!(file2.insert(&database).is_err());
         assert_eq!(File::count(&database).unwrap(), 1);
@@ -373,7 +777,9 @@ mod tests {
             PathBuf::from("/path/to/foo/bar"),
             "sdadfb".to_string(),
             0,
-        );
+            &random_master_key(),
+        )
+        .unwrap();
 
         let inserted_file = insert_file.insert(&database).unwrap();
 
@@ -400,7 +806,9 @@ mod tests {
                     PathBuf::from(format!("/path/to/foo/bar/{}", i)),
                     format!("test_hash_placeholder_{}", i),
                     0,
+                    &random_master_key(),
                 )
+                .unwrap()
             })
             .collect::<Vec<File>>();
 
@@ -419,7 +827,9 @@ mod tests {
             PathBuf::from("/path/to/foo/bar"),
             "test_hash_placeholder".to_string(),
             64,
-        );
+            &random_master_key(),
+        )
+        .unwrap();
 
         file.insert(&database).unwrap();
         let archive_size = File::archive_size(&database).unwrap();
@@ -438,7 +848,9 @@ mod tests {
                     PathBuf::from(format!("/path/to/foo/bar/{}", i)),
                     format!("test_hash_placeholder_{}", i),
                     1_u64.pow(10), // 10 GB
+                    &random_master_key(),
                 )
+                .unwrap()
             })
             .collect::<Vec<File>>();
 
@@ -463,7 +875,9 @@ mod tests {
                     PathBuf::from(format!("/path/to/foo/bar/{}", i)),
                     format!("test_hash_placeholder_{}", i),
                     0,
+                    &random_master_key(),
                 )
+                .unwrap()
             })
             .collect::<Vec<File>>();
 
@@ -475,12 +889,16 @@ mod tests {
 
     #[test]
     fn test_file_new() {
+        let master_key = random_master_key();
+
         let file = File::new(
             String::from("x.txt"),
             PathBuf::from("foo/bar/x.txt"),
             random_hash_string(),
             1337,
-        );
+            &master_key,
+        )
+        .unwrap();
 
         assert_eq!(file.id, None);
         assert_eq!(file.title, "x.txt".to_string());
@@ -497,8 +915,16 @@ mod tests {
             file.updated_at,
             DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(0, 0), Utc)
         );
-        assert_eq!(file.key.len(), AEAD_KEY_SIZE);
+        assert_ne!(file.key.as_bytes().len(), AEAD_KEY_SIZE);
+        assert_eq!(
+            file.unwrap_key(&master_key).unwrap().as_bytes().len(),
+            AEAD_KEY_SIZE
+        );
+        assert!(file.unwrap_key(&random_master_key()).is_err());
         assert_eq!(file.nonce.len(), AEAD_NONCE_SIZE);
+
+        // The key is redacted, even though it is wrapped
+        assert!(format!("{file:?}").contains("key: WrappedKey(<redacted>)"));
     }
 
     #[test]
@@ -516,6 +942,30 @@ mod tests {
         assert_ne!(inserted.updated_at, updated.updated_at);
     }
 
+    #[test]
+    fn test_large_files_are_chunked() {
+        let database = create_in_memory().unwrap();
+        let master_key = random_master_key();
+
+        let small = new_random_file().insert(&database).unwrap();
+        assert_eq!(small.format, ContentsFormat::Stream);
+
+        let large = File::new(
+            String::from("disk.img"),
+            PathBuf::from("vm/disk.img"),
+            random_hash_string(),
+            200 * 1024 * 1024 * 1024,
+            &master_key,
+        )
+        .unwrap()
+        .insert(&database)
+        .unwrap();
+        assert_eq!(large.format, ContentsFormat::Chunked);
+
+        let updated = large.clone().update(&database).unwrap();
+        assert_eq!(updated.format, ContentsFormat::Chunked);
+    }
+
     #[test]
     #[should_panic]
     fn test_update_non_existing() {
@@ -552,5 +1002,171 @@ mod tests {
             File::find_file_from_path(&database, &PathBuf::from(&inserted_file)).unwrap();
 
         assert_eq!(inserted_file, found_file);
+
+        let found_file = File::find_by_path(&database, PathBuf::from(&inserted_file)).unwrap();
+        assert_eq!(found_file, Some(inserted_file));
+        assert_eq!(File::find_by_path(&database, "missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_contents() {
+        let mut file = new_random_file();
+        let locked_hash = file.locked_hash.clone();
+        let contents_hash = random_hash_string();
+
+        file.set_contents(contents_hash.clone(), 42);
+
+        assert_eq!(file.contents_hash, contents_hash);
+        assert_eq!(file.size, 42);
+        assert_ne!(file.locked_hash, locked_hash);
+        assert_eq!(
+            file.locked_hash,
+            File::locked_hash_string(&contents_hash, &file.nonce)
+        );
+    }
+
+    #[test]
+    fn test_non_utf8_paths() {
+        let database = create_in_memory().unwrap();
+        let master_key = random_master_key();
+
+        let paths = [
+            PathBuf::from(OsStr::from_bytes(b"dir/file\xfe")),
+            PathBuf::from(OsStr::from_bytes(b"dir/file\xff")),
+            PathBuf::from(r"dir/file\xff"),
+        ];
+
+        for (i, path) in paths.iter().enumerate() {
+            File::new(
+                format!("file {i}"),
+                path.clone(),
+                random_hash_string(),
+                1,
+                &master_key,
+            )
+            .unwrap()
+            .insert(&database)
+            .unwrap();
+        }
+
+        for path in &paths {
+            let found = File::find_file_from_path(&database, path).unwrap();
+            assert_eq!(&PathBuf::from(&found), path);
+        }
+
+        let tree: PathTree = File::fetch_all(&database).unwrap().into_iter().collect();
+        let mut expected = paths.to_vec();
+        expected.sort();
+        assert_eq!(tree.paths_ordered(), expected);
+    }
+
+    fn attributes_modified_at(secs: u64) -> FileAttributes {
+        FileAttributes {
+            modified_at: Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(secs * 1_000_000_007)),
+            accessed_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            mode: Some(0o640),
+            uid: Some(1000),
+            gid: Some(100),
+            xattrs: vec![
+                (OsString::from("user.comment"), b"hello".to_vec()),
+                (OsString::from("user.empty"), vec![]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_attributes_roundtrip() {
+        let database = create_in_memory().unwrap();
+
+        let attributes = attributes_modified_at(1_600_000_000);
+
+        let mut file = new_random_file();
+        assert_eq!(file.attributes(), FileAttributes::default());
+
+        file.set_attributes(&attributes);
+        let inserted = file.insert(&database).unwrap();
+        let found = File::get(&database, inserted.id.unwrap()).unwrap().unwrap();
+
+        assert_eq!(found.attributes(), attributes);
+    }
+
+    #[test]
+    fn test_search_modified() {
+        let database = create_in_memory().unwrap();
+
+        let mut old_file = new_random_file();
+        old_file.set_attributes(&attributes_modified_at(1_000_000_000));
+        old_file.insert(&database).unwrap();
+
+        let mut new_file = new_random_file();
+        new_file.set_attributes(&attributes_modified_at(1_600_000_000));
+        let new_file = new_file.insert(&database).unwrap();
+
+        new_random_file().insert(&database).unwrap();
+
+        let middle = Utc.timestamp_opt(1_300_000_000, 0).unwrap();
+
+        let found = File::search_modified(&database, "", Some(middle), None).unwrap();
+        assert_eq!(found, vec![new_file]);
+
+        let found = File::search_modified(&database, "", None, Some(middle)).unwrap();
+        assert_eq!(found.len(), 1);
+
+        let found = File::search_modified(&database, "", None, None).unwrap();
+        assert_eq!(found.len(), 3);
+    }
+
+    #[test]
+    fn test_symlinks_and_directories() {
+        let database = create_in_memory().unwrap();
+        let master_key = random_master_key();
+
+        let symlink = File::new_symlink(
+            "link".to_string(),
+            PathBuf::from("dir/link"),
+            "../target",
+            &master_key,
+        )
+        .unwrap()
+        .insert(&database)
+        .unwrap();
+        let directory =
+            File::new_directory("empty".to_string(), PathBuf::from("empty"), &master_key)
+                .unwrap()
+                .insert(&database)
+                .unwrap();
+
+        let mut symlink = File::get(&database, symlink.id.unwrap()).unwrap().unwrap();
+        assert_eq!(symlink.kind, EntryKind::Symlink);
+        assert_eq!(
+            symlink.symlink_target(&master_key).unwrap(),
+            Some(PathBuf::from("../target"))
+        );
+        assert_eq!(directory.kind, EntryKind::Directory);
+        assert_eq!(directory.symlink_target(&master_key).unwrap(), None);
+
+        // The target is sealed, it does not show in the row
+        assert!(!symlink
+            .target
+            .as_ref()
+            .unwrap()
+            .windows(9)
+            .any(|w| w == b"../target"));
+
+        let new_master_key = random_master_key();
+        symlink
+            .rekey_in_place(&master_key, &new_master_key)
+            .unwrap();
+        let symlink = symlink.update(&database).unwrap();
+
+        assert!(symlink.symlink_target(&master_key).is_err());
+        assert_eq!(
+            symlink.symlink_target(&new_master_key).unwrap(),
+            Some(PathBuf::from("../target"))
+        );
+
+        let tree: PathTree = File::fetch_all(&database).unwrap().into_iter().collect();
+        assert!(tree.is_symlink("dir/link"));
+        assert!(tree.is_directory("empty"));
     }
 }
@@ -1,7 +1,9 @@
 mod file;
 mod file_tag;
+mod pending_rekey;
 mod tag;
 
-pub use file::{File, MetadataFile};
+pub use file::{ContentsFormat, EntryKind, File, MetadataFile};
 pub use file_tag::FileTag;
+pub use pending_rekey::PendingRekey;
 pub use tag::Tag;
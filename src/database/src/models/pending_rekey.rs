@@ -0,0 +1,152 @@
+use crypto::crypt::{generate_random_secure_key_nonce_pair, SecretKey, AEAD_NONCE_SIZE};
+use crypto::errors::CryptoError;
+use database_macros::{Insert, TableName, TryFromRow};
+use rusqlite::named_params;
+
+use crate::traits::{FetchAll, InsertMany, Update};
+use crate::{errors::DatabaseResult, Database, WrappedKey};
+
+use super::File;
+
+/// A new key, nonce and locked name for a `File` whose key is being rotated. It is kept
+/// until the old ciphertext is deleted, so that an interrupted rotation can be resumed
+#[derive(TableName, TryFromRow, Insert)]
+pub struct PendingRekey {
+    pub id: Option<i64>,
+    pub file_id: i64,
+    pub old_locked_hash: String,
+    pub locked_hash: String,
+    pub key: WrappedKey,
+    pub nonce: Vec<u8>,
+    pub encrypted: bool,
+}
+
+impl FetchAll for PendingRekey {}
+
+impl InsertMany for PendingRekey {}
+
+impl PendingRekey {
+    /// Generate a new key and nonce for `file`. The key is stored wrapped with `master_key`
+    pub fn new(file: &File, master_key: &SecretKey) -> Self {
+        let (key, nonce) = generate_random_secure_key_nonce_pair();
+        // Should never fail as the master key is in memory
+        let key = WrappedKey::wrap(master_key, &key).unwrap();
+
+        PendingRekey {
+            id: None,
+            file_id: file.id.expect("missing file.id"),
+            old_locked_hash: file.locked_hash.clone(),
+            locked_hash: File::locked_hash_string(&file.contents_hash, &nonce),
+            key,
+            nonce: Vec::from(nonce.as_slice()),
+            encrypted: false,
+        }
+    }
+
+    /// Unwrap the new key with `master_key`
+    pub fn unwrap_key(&self, master_key: &SecretKey) -> Result<SecretKey, CryptoError> {
+        self.key.unwrap(master_key)
+    }
+
+    /// The new nonce
+    pub fn nonce(&self) -> Result<[u8; AEAD_NONCE_SIZE], CryptoError> {
+        self.nonce
+            .clone()
+            .try_into()
+            .map_err(|nonce: Vec<u8>| CryptoError::InvalidNonceLength(nonce.len()))
+    }
+
+    /// Remember that the file has been encrypted with the new key
+    pub fn mark_encrypted(&mut self, db: &Database) -> DatabaseResult<()> {
+        db.execute(
+            include_str!("sql/pending_rekey/mark_encrypted.sql"),
+            named_params! { ":id": self.id.expect("missing pending_rekey.id") },
+        )?;
+
+        self.encrypted = true;
+        Ok(())
+    }
+
+    /// Whether the new key has already been swapped into `file`
+    pub fn is_swapped(&self, file: &File) -> bool {
+        file.locked_hash == self.locked_hash
+    }
+
+    /// Swap the new key, nonce and locked name into `file`
+    pub fn swap(&self, db: &Database, mut file: File) -> DatabaseResult<File> {
+        assert_eq!(file.id, Some(self.file_id));
+
+        file.locked_hash = self.locked_hash.clone();
+        file.key = self.key.clone();
+        file.nonce = self.nonce.clone();
+
+        file.update(db)
+    }
+
+    /// Forget about the rotation, once it is complete
+    pub fn delete(self, db: &Database) -> DatabaseResult<()> {
+        db.execute(
+            include_str!("sql/pending_rekey/delete.sql"),
+            named_params! { ":id": self.id.expect("missing pending_rekey.id") },
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crypto::crypt::generate_random_secure_key;
+
+    use crate::create_in_memory;
+    use crate::models::File;
+    use crate::traits::{FetchAll, Get, Insert};
+
+    use super::PendingRekey;
+
+    #[test]
+    fn test_rekey_lifecycle() {
+        let database = create_in_memory().unwrap();
+        let master_key = generate_random_secure_key();
+
+        let file = File::new(
+            "foobar".to_string(),
+            PathBuf::from("foo/bar"),
+            "test_hash_placeholder".to_string(),
+            0,
+            &master_key,
+        )
+        .unwrap()
+        .insert(&database)
+        .unwrap();
+
+        let mut pending = PendingRekey::new(&file, &master_key)
+            .insert(&database)
+            .unwrap();
+
+        assert_eq!(pending.old_locked_hash, file.locked_hash);
+        assert_ne!(pending.locked_hash, file.locked_hash);
+        assert!(!pending.is_swapped(&file));
+
+        pending.mark_encrypted(&database).unwrap();
+        let fetched = PendingRekey::fetch_all(&database).unwrap();
+        assert!(fetched[0].encrypted);
+
+        let swapped = pending.swap(&database, file.clone()).unwrap();
+        assert!(pending.is_swapped(&swapped));
+        assert_eq!(swapped.contents_hash, file.contents_hash);
+        assert_eq!(
+            swapped.unwrap_key(&master_key).unwrap(),
+            pending.unwrap_key(&master_key).unwrap()
+        );
+        assert_eq!(
+            File::get(&database, file.id.unwrap()).unwrap().unwrap(),
+            swapped
+        );
+
+        pending.delete(&database).unwrap();
+        assert!(PendingRekey::fetch_all(&database).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,3 @@
+SELECT COUNT(*)
+FROM file
+WHERE locked_hash = :locked_hash;
@@ -0,0 +1,3 @@
+SELECT id, key
+FROM file
+WHERE length(key) = :key_size;
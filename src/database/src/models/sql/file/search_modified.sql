@@ -0,0 +1,5 @@
+SELECT *
+FROM file
+WHERE title LIKE :query
+    AND (:modified_after IS NULL OR modified_at >= :modified_after)
+    AND (:modified_before IS NULL OR modified_at < :modified_before);
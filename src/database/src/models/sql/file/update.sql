@@ -7,5 +7,14 @@ SET title = :title,
     created_at = :created_at,
     updated_at = :updated_at,
     key = :key,
-    nonce = :nonce
+    nonce = :nonce,
+    modified_at = :modified_at,
+    accessed_at = :accessed_at,
+    mode = :mode,
+    uid = :uid,
+    gid = :gid,
+    xattrs = :xattrs,
+    kind = :kind,
+    target = :target,
+    format = :format
 WHERE id = :id RETURNING *;
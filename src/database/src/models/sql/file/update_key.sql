@@ -0,0 +1,3 @@
+UPDATE file
+SET key = :key
+WHERE id = :id;
@@ -0,0 +1,2 @@
+DELETE FROM pending_rekey
+WHERE id = :id;
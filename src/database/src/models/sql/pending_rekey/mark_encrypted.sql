@@ -0,0 +1,3 @@
+UPDATE pending_rekey
+SET encrypted = 1
+WHERE id = :id;
@@ -1,42 +1,70 @@
-use std::{
-    env,
-    path::{Path, PathBuf},
-};
+use std::{env, path::PathBuf};
 
+use crypto::crypt::KeyWrapper;
 use rusqlite::Connection;
 
-use crate::errors::DatabaseResult;
+use crate::{
+    encrypted::EncryptedDatabase,
+    errors::{DatabaseError, DatabaseResult},
+    migrations::migrate,
+};
 
 pub type Database = Connection;
 
-pub fn database_file() -> PathBuf {
-    let database_path = env::var("DATABASE_FILE").expect("Cannot read DATABASE_FILE env");
-    PathBuf::from(database_path)
+/// The database file, from the `DATABASE_FILE` env
+pub fn database_file() -> DatabaseResult<PathBuf> {
+    env::var_os("DATABASE_FILE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .ok_or(DatabaseError::DatabaseFileNotSet)
 }
 
-/// Connect to SQLite database
-pub fn connect_or_create() -> DatabaseResult<Database> {
-    let database_path = database_file();
+/// The vault file lives next to the database file
+pub fn vault_file() -> DatabaseResult<PathBuf> {
+    Ok(database_file()?.with_extension("vault"))
+}
 
-    let is_database_new = !Path::new(&database_path).exists();
-    let connection = Connection::open(database_path)?;
+/// The socket of `krypta agent` lives next to the database file
+pub fn agent_socket_file() -> DatabaseResult<PathBuf> {
+    Ok(database_file()?.with_extension("sock"))
+}
 
-    if is_database_new {
-        load_schema(&connection)?;
-    }
+/// Decrypt the SQLite database in memory, or create a new one, and apply the pending
+/// migrations. `master_key` unwraps the database key
+pub fn connect_or_create(master_key: &impl KeyWrapper) -> DatabaseResult<EncryptedDatabase> {
+    let database = EncryptedDatabase::open_or_create(database_file()?, master_key)?;
+    database.migrate(master_key)?;
 
-    Ok(connection)
+    Ok(database)
 }
 
-/// Load database schema
-fn load_schema(db: &Database) -> DatabaseResult<()> {
+/// Load database schema, by applying every migration
+pub(crate) fn load_schema(db: &Database) -> DatabaseResult<()> {
     log::trace!("New database... loading schema");
 
-    db.execute_batch(include_str!("../schema.sql"))?;
+    migrate(db)?;
 
     Ok(())
 }
 
+/// Drop every table and load the schema again, which empties the database
+pub fn recreate_schema(db: &Database) -> DatabaseResult<()> {
+    let tables = db
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        )?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for table in tables {
+        db.execute(&format!("DROP TABLE \"{table}\""), [])?;
+    }
+
+    db.pragma_update(None, "user_version", 0)?;
+
+    load_schema(db)
+}
+
 /// Create a temporary SQLite database in memory, used in tests
 pub fn create_in_memory() -> DatabaseResult<Database> {
     let connection = Connection::open_in_memory()?;
@@ -49,6 +77,7 @@ pub fn create_in_memory() -> DatabaseResult<Database> {
 pub mod tests {
     use std::env;
 
+    use crypto::crypt::generate_random_secure_key;
     use tmp::Tmp;
 
     use crate::{connect_or_create, utils::create_in_memory};
@@ -67,7 +96,13 @@ pub mod tests {
 
         env::set_var("DATABASE_FILE", &database_file);
 
-        connect_or_create().unwrap();
+        let master_key = generate_random_secure_key();
+
+        // The database lives in memory until closed
+        let database = connect_or_create(&master_key).unwrap();
+        assert!(!database_file.exists());
+
+        database.close().unwrap();
         assert!(database_file.is_file());
     }
 }
@@ -0,0 +1,53 @@
+use std::fmt::Debug;
+
+use crypto::{
+    crypt::{KeyWrapper, SecretKey},
+    errors::CryptoError,
+};
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use zeroize::Zeroize;
+
+/// A per-file key wrapped with the master key, as stored in the `key` columns. It is wiped
+/// from memory when dropped and never printed by `Debug`
+#[derive(Clone, PartialEq, Eq)]
+pub struct WrappedKey(Vec<u8>);
+
+impl WrappedKey {
+    /// Wrap `key` with `master_key`
+    pub fn wrap(master_key: &impl KeyWrapper, key: &SecretKey) -> Result<Self, CryptoError> {
+        Ok(WrappedKey(master_key.wrap_key(key)?))
+    }
+
+    /// Unwrap the key with `master_key`
+    pub fn unwrap(&self, master_key: &impl KeyWrapper) -> Result<SecretKey, CryptoError> {
+        master_key.unwrap_key(&self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for WrappedKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Debug for WrappedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WrappedKey(<redacted>)")
+    }
+}
+
+impl ToSql for WrappedKey {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Blob(&self.0)))
+    }
+}
+
+impl FromSql for WrappedKey {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Vec::<u8>::column_result(value).map(WrappedKey)
+    }
+}
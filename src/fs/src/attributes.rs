@@ -0,0 +1,123 @@
+use std::{
+    ffi::OsString,
+    fs::{set_permissions, symlink_metadata, FileTimes, Metadata, OpenOptions, Permissions},
+    io::ErrorKind,
+    os::unix::fs::{chown, lchown, MetadataExt, PermissionsExt},
+    path::Path,
+    time::SystemTime,
+};
+
+use filetime::{set_symlink_file_times, FileTime};
+
+use crate::errors::FsError;
+
+/// The attributes of a source file that are stored in the vault and restored on extract.
+/// Fields are optional because files added by older versions do not have them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAttributes {
+    pub modified_at: Option<SystemTime>,
+    pub accessed_at: Option<SystemTime>,
+    /// Unix permission bits, file type bits excluded
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Extended attributes as (name, value)
+    pub xattrs: Vec<(OsString, Vec<u8>)>,
+}
+
+impl FileAttributes {
+    /// Read the attributes of the file in `path`, whose metadata has already been fetched.
+    /// Extended attributes that cannot be read are skipped
+    pub fn read(path: impl AsRef<Path>, metadata: &Metadata) -> Self {
+        let path = path.as_ref();
+
+        FileAttributes {
+            modified_at: metadata.modified().ok(),
+            accessed_at: metadata.accessed().ok(),
+            mode: Some(metadata.mode() & 0o7777),
+            uid: Some(metadata.uid()),
+            gid: Some(metadata.gid()),
+            xattrs: read_xattrs(path),
+        }
+    }
+
+    /// Apply the attributes to the file in `path`. Ownership is only restored when allowed,
+    /// usually when running as root, like `tar` does. Symbolic links are not followed
+    pub fn apply(&self, path: impl AsRef<Path>) -> Result<(), FsError> {
+        let path = path.as_ref();
+        let is_symlink = symlink_metadata(path)?.file_type().is_symlink();
+
+        for (name, value) in &self.xattrs {
+            if let Err(error) = xattr::set(path, name, value) {
+                log::warn!("Cannot restore xattr {name:?} of {path:?}: {error}");
+            }
+        }
+
+        if self.uid.is_some() || self.gid.is_some() {
+            let result = if is_symlink {
+                lchown(path, self.uid, self.gid)
+            } else {
+                chown(path, self.uid, self.gid)
+            };
+
+            match result {
+                Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                    log::debug!("Not allowed to restore the owner of {path:?}");
+                }
+                result => result?,
+            }
+        }
+
+        if is_symlink {
+            // Symlinks have no permissions of their own
+            if let (Some(accessed_at), Some(modified_at)) = (self.accessed_at, self.modified_at) {
+                set_symlink_file_times(
+                    path,
+                    FileTime::from_system_time(accessed_at),
+                    FileTime::from_system_time(modified_at),
+                )?;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(mode) = self.mode {
+            set_permissions(path, Permissions::from_mode(mode))?;
+        }
+
+        let mut times = FileTimes::new();
+
+        if let Some(modified_at) = self.modified_at {
+            times = times.set_modified(modified_at);
+        }
+
+        if let Some(accessed_at) = self.accessed_at {
+            times = times.set_accessed(accessed_at);
+        }
+
+        // Opening for writing would fail on read-only files
+        OpenOptions::new().read(true).open(path)?.set_times(times)?;
+
+        Ok(())
+    }
+}
+
+fn read_xattrs(path: &Path) -> Vec<(OsString, Vec<u8>)> {
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(error) => {
+            log::debug!("Cannot list xattrs of {path:?}: {error}");
+            return vec![];
+        }
+    };
+
+    names
+        .filter_map(|name| match xattr::get(path, &name) {
+            Ok(value) => value.map(|value| (name, value)),
+            Err(error) => {
+                log::debug!("Cannot read xattr {name:?} of {path:?}: {error}");
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,136 @@
+use std::{
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+const ESCAPE: char = '\\';
+
+/// Encode `path` into a string that can be decoded back to the very same bytes. Valid UTF-8
+/// is kept as is, except for `\` which becomes `\\`, and every byte that is not valid UTF-8
+/// becomes `\xNN`
+pub fn encode_path(path: impl AsRef<Path>) -> String {
+    let mut bytes = path.as_ref().as_os_str().as_bytes();
+    let mut encoded = String::with_capacity(bytes.len());
+
+    while !bytes.is_empty() {
+        let (valid, rest) = match std::str::from_utf8(bytes) {
+            Ok(valid) => (valid, &[][..]),
+            Err(error) => {
+                let (valid, invalid) = bytes.split_at(error.valid_up_to());
+                // SAFETY: `from_utf8` checked the bytes up to `valid_up_to`
+                let valid = unsafe { std::str::from_utf8_unchecked(valid) };
+                let invalid_len = error.error_len().unwrap_or(invalid.len());
+
+                (valid, invalid.split_at(invalid_len).0)
+            }
+        };
+
+        for character in valid.chars() {
+            if character == ESCAPE {
+                encoded.push(ESCAPE);
+            }
+
+            encoded.push(character);
+        }
+
+        for byte in rest {
+            encoded.push_str(&format!("{ESCAPE}x{byte:02x}"));
+        }
+
+        bytes = &bytes[valid.len() + rest.len()..];
+    }
+
+    encoded
+}
+
+/// Decode a path encoded by `encode_path`. Malformed escapes are kept as they are
+pub fn decode_path(encoded: impl AsRef<str>) -> PathBuf {
+    let encoded = encoded.as_ref();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != ESCAPE as u8 {
+            decoded.push(byte);
+            rest = tail;
+            continue;
+        }
+
+        match tail {
+            [b'\\', tail @ ..] => {
+                decoded.push(b'\\');
+                rest = tail;
+            }
+            [b'x', high, low, tail @ ..] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                let hex = [*high, *low];
+                // Two hex digits always fit in a byte
+                let hex = std::str::from_utf8(&hex).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                rest = tail;
+            }
+            _ => {
+                decoded.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    PathBuf::from(OsStr::from_bytes(&decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    use quickcheck::quickcheck;
+
+    use super::{decode_path, encode_path};
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(encode_path("some/path/file.txt"), "some/path/file.txt");
+        assert_eq!(encode_path("città/perché"), "città/perché");
+        assert_eq!(encode_path(r"back\slash"), r"back\\slash");
+        assert_eq!(
+            encode_path(OsStr::from_bytes(b"bad\xffname\xc3")),
+            r"bad\xffname\xc3"
+        );
+    }
+
+    #[test]
+    fn test_invalid_paths_do_not_collide() {
+        let first = encode_path(OsStr::from_bytes(b"file\xfe"));
+        let second = encode_path(OsStr::from_bytes(b"file\xff"));
+        let literal = encode_path(r"file\xff");
+
+        assert_ne!(first, second);
+        assert_ne!(second, literal);
+    }
+
+    #[test]
+    fn test_decode_malformed_escapes() {
+        assert_eq!(decode_path(r"trailing\"), Path::new(r"trailing\"));
+        assert_eq!(decode_path(r"bad\xzz"), Path::new(r"bad\xzz"));
+    }
+
+    quickcheck! {
+        fn prop_roundtrip_bytes(bytes: Vec<u8>) -> bool {
+            let path = Path::new(OsStr::from_bytes(&bytes));
+            decode_path(encode_path(path)) == path
+        }
+
+        fn prop_roundtrip_strings(path: String) -> bool {
+            decode_path(encode_path(&path)) == Path::new(&path)
+        }
+
+        fn prop_utf8_without_escapes_is_unchanged(path: String) -> bool {
+            path.contains('\\') || encode_path(&path) == path
+        }
+
+        fn prop_encoding_is_injective(first: Vec<u8>, second: Vec<u8>) -> bool {
+            let encode = |bytes: &[u8]| encode_path(OsStr::from_bytes(bytes));
+            (first == second) == (encode(&first) == encode(&second))
+        }
+    }
+}
@@ -2,8 +2,14 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum FsError {
-    #[error("Input/Output error")]
+    #[error("Input/Output error: {0}")]
     IoError(#[from] std::io::Error),
-    #[error("Walkdir error: {0}")]
-    Walkdir(#[from] walkdir::Error),
+    #[error("Walk error: {0}")]
+    Walk(#[from] ignore::Error),
+    #[error("Unsupported file type, only files, directories and symlinks are stored")]
+    UnsupportedFileType,
+    #[error("Invalid glob: {0}")]
+    Glob(#[from] globset::Error),
+    #[error("{0:?} must be a relative path without `.` or `..`")]
+    UnsafePath(std::path::PathBuf),
 }
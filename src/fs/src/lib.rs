@@ -4,11 +4,29 @@
 ///
 /// A `PathFinder` instance holds the found paths which can be filtered to remove unwanted ones.
 mod path_finder;
-pub use path_finder::PathFinder;
+pub use path_finder::{
+    ParallelWalk, PathFinder, PathFinderBuilder, SkippedPath, Walk, WalkEntry, IGNORE_FILE_NAME,
+};
 
 /// The `PathTree` module is able to load a series of nested file paths and store them as a tree of
 /// paths
 mod tree;
 pub use tree::PathTree;
 
+/// The attributes of a file, such as times, permissions and ownership, that are preserved
+/// in the vault
+mod attributes;
+pub use attributes::FileAttributes;
+
+/// Reversible encoding of paths into strings, which keeps paths that are not valid UTF-8
+mod encoding;
+pub use encoding::{decode_path, encode_path};
+
+/// Unicode normalization and detection of paths that collide on case-insensitive filesystems,
+/// so that vaults can be shared between Linux and macOS
+mod portable;
+pub use portable::{
+    check_vault_path, find_collisions, normalize_path, Collision, CollisionDetector,
+};
+
 pub mod errors;
@@ -1,61 +1,578 @@
 use std::{
     collections::HashMap,
     fs::Metadata,
+    io::{self, ErrorKind},
     path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
     time::Instant,
 };
 
-use walkdir::WalkDir;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{WalkBuilder, WalkState};
 
 use crate::errors::FsError;
 
+/// Name of the gitignore-syntax files that exclude paths from the directory they are in
+pub const IGNORE_FILE_NAME: &str = ".kryptaignore";
+
+/// Paths found by a `ParallelWalk` ahead of the ones handed out
+const WALK_QUEUE_SIZE: usize = 1024;
+
 /// Holds the information about the found files
 #[derive(Debug)]
 pub struct PathFinder {
+    /// The absolute source path. Symbolic links in it are resolved, except for the last
+    /// component when it is not a directory
+    pub source_path: PathBuf,
+    /// The directory the found paths are relative to: the source path itself, or its parent
+    /// when the source path is a single file or symlink, which is then found by its name
+    pub root: PathBuf,
+    /// Regular files
     pub metadatas: HashMap<PathBuf, Metadata>,
+    /// Symbolic links, which are not followed
+    pub symlinks: HashMap<PathBuf, Metadata>,
+    /// Directories without anything inside, the others are implied by their contents
+    pub empty_dirs: HashMap<PathBuf, Metadata>,
+    /// Paths that were found but could not be read, or cannot be stored, sorted by path
+    pub skipped: Vec<SkippedPath>,
+}
+
+/// A path left out of a `PathFinder`, and why
+#[derive(Debug)]
+pub struct SkippedPath {
+    /// Relative to the source path, empty when the error is not about a specific path
+    pub path: PathBuf,
+    pub error: FsError,
 }
 
 impl PathFinder {
-    /// Build a PathFinder instance and populate it with file paths from absolute_source_path
+    /// Build a PathFinder instance and populate it with file paths from source_path, which
+    /// can also be a single file, honoring the `.kryptaignore` files
     pub fn from_source_path<P: AsRef<Path>>(source_path: P) -> Result<Self, FsError> {
-        let source_path = source_path.as_ref().to_owned().canonicalize()?;
-        let source_path_length = source_path.iter().count();
+        Self::builder(source_path).build()
+    }
+
+    /// Start configuring which paths to find in `source_path`
+    pub fn builder<P: AsRef<Path>>(source_path: P) -> PathFinderBuilder {
+        PathFinderBuilder {
+            source_path: source_path.as_ref().to_owned(),
+            ignore_files: true,
+            include: vec![],
+            exclude: vec![],
+            threads: 0,
+        }
+    }
+}
+
+/// Configures the paths found by a `PathFinder`. Globs are matched against paths relative
+/// to the source path, and globs without a `/` against the name in any directory, like
+/// in gitignore files
+#[derive(Debug, Clone)]
+pub struct PathFinderBuilder {
+    source_path: PathBuf,
+    ignore_files: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    threads: usize,
+}
+
+impl PathFinderBuilder {
+    /// Whether to skip the paths listed in the `.kryptaignore` files found while walking,
+    /// enabled by default
+    pub fn ignore_files(mut self, enabled: bool) -> Self {
+        self.ignore_files = enabled;
+        self
+    }
+
+    /// Only find the files, symlinks and empty directories matching `glob`. When called
+    /// more than once, matching any of the globs is enough
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Skip the paths matching `glob`, with everything inside when it is a directory
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// How many threads walk the directories in parallel, by default (with 0) as many as
+    /// the available CPUs
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Resolve the source path and set up the walker, shared by `build` and `walk`
+    fn prepare(self) -> Result<Prepared, FsError> {
+        // Fail early when missing, instead of reporting it as skipped
+        self.source_path.symlink_metadata()?;
+        let is_dir = self.source_path.is_dir();
+
+        let (source_path, root) = if is_dir {
+            let source_path = self.source_path.canonicalize()?;
+            (source_path.clone(), source_path)
+        } else {
+            // Not resolved, so that a link is found as a link
+            let name = self.source_path.file_name().ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidInput, "the source path has no name")
+            })?;
+
+            let root = match self.source_path.parent() {
+                Some(parent) if parent != Path::new("") => parent.canonicalize()?,
+                _ => Path::new(".").canonicalize()?,
+            };
+
+            (root.join(name), root)
+        };
+
+        let root_length = root.iter().count();
+
+        let include = (!self.include.is_empty())
+            .then(|| build_glob_set(&self.include))
+            .transpose()?;
+        let exclude = build_glob_set(&self.exclude)?;
+
+        let mut walker = WalkBuilder::new(&source_path);
+        walker.standard_filters(false).follow_links(false);
+
+        if self.ignore_files {
+            walker.add_custom_ignore_filename(IGNORE_FILE_NAME);
+        }
+
+        walker
+            .filter_entry(move |entry| !exclude.is_match(relative_path(entry.path(), root_length)));
+        walker.threads(self.threads);
+
+        Ok(Prepared {
+            source_path,
+            root,
+            root_length,
+            is_dir,
+            include,
+            walker,
+        })
+    }
+
+    /// Walk the source path and find the paths
+    pub fn build(self) -> Result<PathFinder, FsError> {
+        let walk = self.walk_parallel()?;
+        let source_path = walk.source_path.clone();
+        let root = walk.root.clone();
 
         log::trace!("starting with search in {:?}", source_path);
         let start = Instant::now();
 
-        // Find paths and relative metadata
-        let paths_metadata = WalkDir::new(&source_path)
-            .follow_links(false)
-            .into_iter()
-            .filter_map(|res| res.ok())
-            // Map into a tuple of (RelativePath, &Metadata)
-            .map(|entry| {
-                // Skip host-specific bits
-                let relative_path = entry
-                    .path()
-                    .canonicalize()?
-                    .iter()
-                    .skip(source_path_length)
-                    .collect();
-                let metadata = entry.metadata()?;
-
-                Ok((relative_path, metadata))
-            })
-            .collect::<Result<Vec<(_, _)>, FsError>>()?;
-
-        // Exclude dirs
-        let metadatas = paths_metadata
-            .into_iter()
-            .filter(|(_, metadata)| metadata.is_file())
-            .collect::<HashMap<_, _>>();
+        let mut metadatas = HashMap::new();
+        let mut symlinks = HashMap::new();
+        let mut empty_dirs = HashMap::new();
+        let mut skipped = vec![];
+
+        walk.run(|entry| {
+            match entry {
+                WalkEntry::File(path, metadata) => metadatas.insert(path, metadata),
+                WalkEntry::Symlink(path, metadata) => symlinks.insert(path, metadata),
+                WalkEntry::EmptyDir(path, metadata) => empty_dirs.insert(path, metadata),
+                WalkEntry::Skipped(skipped_path) => {
+                    skipped.push(skipped_path);
+                    None
+                }
+            };
+
+            true
+        });
+
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
 
         log::trace!(
-            "took {:?} to find {} files",
+            "took {:?} to find {} files, {} symlinks and {} empty directories, {} skipped",
             start.elapsed(),
-            metadatas.len()
+            metadatas.len(),
+            symlinks.len(),
+            empty_dirs.len(),
+            skipped.len()
         );
 
-        Ok(Self { metadatas })
+        Ok(PathFinder {
+            source_path,
+            root,
+            metadatas,
+            symlinks,
+            empty_dirs,
+            skipped,
+        })
+    }
+
+    /// Find the paths with threads walking in parallel, handing them out as they are found
+    /// instead of keeping them. Memory grows with the empty directories, which are only
+    /// handed out at the end, and with the directories waiting to be read, not with the files
+    pub fn walk_parallel(self) -> Result<ParallelWalk, FsError> {
+        let Prepared {
+            source_path,
+            root,
+            root_length,
+            is_dir,
+            include,
+            walker,
+        } = self.prepare()?;
+
+        Ok(ParallelWalk {
+            source_path,
+            root,
+            walker,
+            root_length,
+            is_dir,
+            include,
+        })
+    }
+
+    /// Find the paths one at a time instead of all at once, so that memory does not grow
+    /// with the number of paths. The walk is single-threaded
+    pub fn walk(self) -> Result<Walk, FsError> {
+        let Prepared {
+            source_path,
+            root,
+            root_length,
+            is_dir,
+            include,
+            mut walker,
+        } = self.prepare()?;
+
+        walker.sort_by_file_name(|a, b| a.cmp(b));
+
+        Ok(Walk {
+            source_path,
+            root,
+            walker: walker.build(),
+            root_length,
+            is_dir,
+            include,
+            pending_dir: None,
+            queued: None,
+        })
+    }
+}
+
+/// The paths in a source path, found by threads walking in parallel and handed out in no
+/// particular order. It finds the same paths as a `PathFinder`, built from the same
+/// `PathFinderBuilder`
+pub struct ParallelWalk {
+    /// See `PathFinder::source_path`
+    pub source_path: PathBuf,
+    /// See `PathFinder::root`
+    pub root: PathBuf,
+    walker: WalkBuilder,
+    root_length: usize,
+    is_dir: bool,
+    include: Option<GlobSet>,
+}
+
+impl ParallelWalk {
+    /// Walk, handing every path to `found` on the current thread: files, symlinks and skipped
+    /// paths as soon as they are found, empty directories at the end since only then nothing
+    /// can be found inside them. The walk stops as soon as `found` returns false.
+    ///
+    /// A directory is always found before its contents, as the walkers only read it after
+    /// sending it, so it is kept only until the first path inside it shows up
+    pub fn run(self, mut found: impl FnMut(WalkEntry) -> bool) {
+        let ParallelWalk {
+            walker,
+            root_length,
+            is_dir,
+            include,
+            ..
+        } = self;
+
+        // Bounded, so that the walk waits for `found`
+        let (sender, receiver) = mpsc::sync_channel(WALK_QUEUE_SIZE);
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                walker.build_parallel().run(|| {
+                    let sender = sender.clone();
+
+                    Box::new(move |result| {
+                        let found = match result {
+                            Ok(entry) if entry.depth() == 0 && is_dir => {
+                                return WalkState::Continue
+                            }
+                            Ok(entry) => {
+                                let path = relative_path(entry.path(), root_length);
+
+                                // Metadata of the link itself, as links are not followed.
+                                // Usually it comes for free from `readdir`
+                                match entry.metadata() {
+                                    Ok(metadata) => Ok((path, metadata)),
+                                    Err(error) => Err(SkippedPath {
+                                        path,
+                                        error: error.into(),
+                                    }),
+                                }
+                            }
+                            Err(error) => Err(SkippedPath {
+                                path: error_path(&error).map_or_else(PathBuf::new, |path| {
+                                    relative_path(path, root_length)
+                                }),
+                                error: error.into(),
+                            }),
+                        };
+
+                        match sender.send(found) {
+                            Ok(_) => WalkState::Continue,
+                            Err(_) => WalkState::Quit,
+                        }
+                    })
+                });
+            });
+
+            // Directories with nothing found inside them yet
+            let mut empty_dirs = HashMap::new();
+
+            // Returning drops the receiver, which stops the walkers
+            for result in receiver {
+                let entry = match result {
+                    Ok((path, metadata)) => {
+                        let file_type = metadata.file_type();
+
+                        // Directories are always walked, but only their contents need to be
+                        // included
+                        if !file_type.is_dir() && !is_included(&include, &path) {
+                            continue;
+                        }
+
+                        if let Some(parent) = path.parent() {
+                            empty_dirs.remove(parent);
+                        }
+
+                        if file_type.is_file() {
+                            WalkEntry::File(path, metadata)
+                        } else if file_type.is_symlink() {
+                            WalkEntry::Symlink(path, metadata)
+                        } else if file_type.is_dir() {
+                            empty_dirs.insert(path, metadata);
+                            continue;
+                        } else {
+                            WalkEntry::Skipped(SkippedPath {
+                                path,
+                                error: FsError::UnsupportedFileType,
+                            })
+                        }
+                    }
+                    // A directory that could not be read is not empty, its contents are
+                    // unknown, and neither is the one it is in
+                    Err(skipped) => {
+                        empty_dirs.remove(&skipped.path);
+
+                        if let Some(parent) = skipped.path.parent() {
+                            empty_dirs.remove(parent);
+                        }
+
+                        WalkEntry::Skipped(skipped)
+                    }
+                };
+
+                if !found(entry) {
+                    return;
+                }
+            }
+
+            for (dir, metadata) in empty_dirs {
+                if is_included(&include, &dir) && !found(WalkEntry::EmptyDir(dir, metadata)) {
+                    return;
+                }
+            }
+        });
     }
 }
+
+/// A configured walker and where it starts from
+struct Prepared {
+    source_path: PathBuf,
+    root: PathBuf,
+    /// Number of components of `root`, stripped to get relative paths
+    root_length: usize,
+    is_dir: bool,
+    include: Option<GlobSet>,
+    walker: WalkBuilder,
+}
+
+/// A path found by a `Walk`, relative to its root, with the same meaning as the fields
+/// of `PathFinder`
+#[derive(Debug)]
+pub enum WalkEntry {
+    File(PathBuf, Metadata),
+    Symlink(PathBuf, Metadata),
+    EmptyDir(PathBuf, Metadata),
+    Skipped(SkippedPath),
+}
+
+/// The paths in a source path, found lazily in depth-first order sorted by name. It finds
+/// the same paths as a `PathFinder`, built from the same `PathFinderBuilder`
+pub struct Walk {
+    /// See `PathFinder::source_path`
+    pub source_path: PathBuf,
+    /// See `PathFinder::root`
+    pub root: PathBuf,
+    walker: ignore::Walk,
+    root_length: usize,
+    is_dir: bool,
+    include: Option<GlobSet>,
+    /// The last directory found, which is empty unless the next paths are inside it
+    pending_dir: Option<(PathBuf, Metadata)>,
+    /// Found along with an empty directory, to be returned right after it
+    queued: Option<WalkEntry>,
+}
+
+impl Walk {
+    /// The pending directory, when `path` shows that nothing was found inside it. Either way
+    /// it is no longer pending
+    fn take_empty_dir(&mut self, path: Option<&Path>) -> Option<WalkEntry> {
+        let (dir, metadata) = self.pending_dir.take()?;
+
+        if path.is_some_and(|path| path.starts_with(&dir)) {
+            return None;
+        }
+
+        is_included(&self.include, &dir).then_some(WalkEntry::EmptyDir(dir, metadata))
+    }
+
+    /// Return `entry`, after the pending directory when it turned out to be empty
+    fn found(&mut self, path: &Path, entry: WalkEntry) -> WalkEntry {
+        match self.take_empty_dir(Some(path)) {
+            Some(empty_dir) => {
+                self.queued = Some(entry);
+                empty_dir
+            }
+            None => entry,
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = WalkEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.queued.take() {
+            return Some(entry);
+        }
+
+        loop {
+            let entry = match self.walker.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(error)) => {
+                    let path = error_path(&error)
+                        .map_or_else(PathBuf::new, |path| relative_path(path, self.root_length));
+
+                    // A directory that could not be read is not empty, its contents are unknown
+                    if self
+                        .pending_dir
+                        .as_ref()
+                        .is_some_and(|(dir, _)| dir == &path)
+                    {
+                        self.pending_dir = None;
+                    }
+
+                    let skipped = WalkEntry::Skipped(SkippedPath {
+                        path: path.clone(),
+                        error: error.into(),
+                    });
+
+                    return Some(self.found(&path, skipped));
+                }
+                None => return self.take_empty_dir(None),
+            };
+
+            if entry.depth() == 0 && self.is_dir {
+                continue;
+            }
+
+            let path = relative_path(entry.path(), self.root_length);
+
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    let skipped = WalkEntry::Skipped(SkippedPath {
+                        path: path.clone(),
+                        error: error.into(),
+                    });
+
+                    return Some(self.found(&path, skipped));
+                }
+            };
+
+            let file_type = metadata.file_type();
+
+            // Like in `build`, paths that are not included do not make a directory non-empty
+            if !file_type.is_dir() && !is_included(&self.include, &path) {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                let empty_dir = self.take_empty_dir(Some(&path));
+                // Only a directory without anything inside is found as such
+                self.pending_dir = Some((path, metadata));
+
+                match empty_dir {
+                    Some(empty_dir) => return Some(empty_dir),
+                    None => continue,
+                }
+            }
+
+            let entry = if file_type.is_file() {
+                WalkEntry::File(path.clone(), metadata)
+            } else if file_type.is_symlink() {
+                WalkEntry::Symlink(path.clone(), metadata)
+            } else {
+                WalkEntry::Skipped(SkippedPath {
+                    path: path.clone(),
+                    error: FsError::UnsupportedFileType,
+                })
+            };
+
+            return Some(self.found(&path, entry));
+        }
+    }
+}
+
+/// `path` without the first `root_length` components, which are host-specific. Symlinks are
+/// not canonicalized, which would resolve them
+fn relative_path(path: &Path, root_length: usize) -> PathBuf {
+    path.iter().skip(root_length).collect()
+}
+
+fn is_included(include: &Option<GlobSet>, path: &Path) -> bool {
+    match include {
+        Some(include) => include.is_match(path),
+        None => true,
+    }
+}
+
+/// The path an error from the walker is about, if any
+fn error_path(error: &ignore::Error) -> Option<&Path> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        ignore::Error::Loop { child, .. } => Some(child),
+        _ => None,
+    }
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, FsError> {
+    let mut builder = GlobSetBuilder::new();
+
+    for glob in globs {
+        let glob = if glob.contains('/') {
+            glob.trim_start_matches('/').to_owned()
+        } else {
+            format!("**/{glob}")
+        };
+
+        builder.add(GlobBuilder::new(&glob).literal_separator(true).build()?);
+    }
+
+    Ok(builder.build()?)
+}
@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::errors::FsError;
+
+/// Normalize every component of `path` that is valid UTF-8 to NFC, which is what Linux tools
+/// usually produce, while macOS hands out NFD names. Other components are kept as they are
+pub fn normalize_path(path: impl AsRef<Path>) -> PathBuf {
+    path.as_ref()
+        .components()
+        .map(|component| match component.as_os_str().to_str() {
+            Some(name) => OsString::from(name.nfc().collect::<String>()),
+            None => component.as_os_str().to_owned(),
+        })
+        .collect()
+}
+
+/// Make sure that `path` stays inside whatever directory it is joined to: it must be made of
+/// plain names only, without a root, `.` or `..`
+pub fn check_vault_path(path: impl AsRef<Path>) -> Result<(), FsError> {
+    let path = path.as_ref();
+    let is_plain = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    if path.as_os_str().is_empty() || !is_plain {
+        return Err(FsError::UnsafePath(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+/// The key under which a file name is looked up on a case-insensitive and
+/// normalization-insensitive filesystem, such as the default APFS or NTFS
+fn folded_name(name: &OsStr) -> OsString {
+    match name.to_str() {
+        Some(name) => {
+            let folded = name.nfd().collect::<String>().to_lowercase();
+            OsString::from(folded.nfc().collect::<String>())
+        }
+        None => name.to_owned(),
+    }
+}
+
+/// A path that ends up in the same place as another one on a case-insensitive filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub path: PathBuf,
+    /// The path that was found first, which `path` would overwrite or be merged into
+    pub collides_with: PathBuf,
+    /// Where `path` can be extracted without colliding
+    pub renamed: PathBuf,
+}
+
+/// Find the paths that collide on case-insensitive filesystems, like `Photo.jpg` and
+/// `photo.jpg`, or `Docs/a` and `docs/b` whose directories would be merged. Paths are
+/// checked in sorted order, so the first one of each group keeps its name and the others are
+/// renamed to `photo (1).jpg` and so on
+pub fn find_collisions<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Vec<Collision> {
+    let mut paths = paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+
+    // (renamed parent, folded name) -> (original name, original path) that took it
+    let mut taken: HashMap<(PathBuf, OsString), (OsString, PathBuf)> = HashMap::new();
+    // (renamed parent, original name) -> the name it was given and what it collided with
+    let mut renames: HashMap<(PathBuf, OsString), (OsString, Option<PathBuf>)> = HashMap::new();
+
+    let mut collisions = vec![];
+
+    for path in paths {
+        let mut original = PathBuf::new();
+        let mut renamed = PathBuf::new();
+        let mut collides_with = None;
+
+        for component in path.components() {
+            let name = component.as_os_str();
+            original.push(name);
+
+            if !matches!(component, Component::Normal(_)) {
+                renamed.push(name);
+                continue;
+            }
+
+            let key = (renamed.clone(), name.to_owned());
+
+            let (new_name, collision) = renames.entry(key).or_insert_with(|| {
+                let folded = (renamed.clone(), folded_name(name));
+
+                let (new_name, collision) = match taken.get(&folded) {
+                    Some((other, other_path)) if other != name => {
+                        (free_name(&taken, &renamed, name), Some(other_path.clone()))
+                    }
+                    _ => (name.to_owned(), None),
+                };
+
+                taken.insert(
+                    (renamed.clone(), folded_name(&new_name)),
+                    (name.to_owned(), original.clone()),
+                );
+
+                (new_name, collision)
+            });
+
+            if collision.is_some() {
+                collides_with = collision.clone();
+            }
+
+            renamed.push(new_name);
+        }
+
+        if let Some(collides_with) = collides_with {
+            collisions.push(Collision {
+                path: original,
+                collides_with,
+                renamed,
+            });
+        }
+    }
+
+    collisions
+}
+
+/// Append ` (n)` to the stem of `name` with the first `n` that is not taken in `parent`
+fn free_name(
+    taken: &HashMap<(PathBuf, OsString), (OsString, PathBuf)>,
+    parent: &Path,
+    name: &OsStr,
+) -> OsString {
+    let name_path = Path::new(name);
+    let stem = name_path.file_stem().unwrap_or(name);
+
+    (1..)
+        .map(|n| {
+            let mut candidate = stem.to_owned();
+            candidate.push(format!(" ({n})"));
+
+            if let Some(extension) = name_path.extension() {
+                candidate.push(".");
+                candidate.push(extension);
+            }
+
+            candidate
+        })
+        .find(|candidate| !taken.contains_key(&(parent.to_path_buf(), folded_name(candidate))))
+        .unwrap()
+}
+
+/// Finds collisions like `find_collisions`, between paths checked one at a time in
+/// depth-first order, as found by a `Walk`. Only the names in the directories leading to the
+/// last path are kept, so memory does not grow with the number of paths. Paths out of order
+/// are fine, but may hide some collisions
+#[derive(Debug, Default)]
+pub struct CollisionDetector {
+    /// The directories leading to the last path checked
+    directories: Vec<OsString>,
+    /// For the root and for each one of `directories`: folded name -> name found there
+    names: Vec<HashMap<OsString, OsString>>,
+}
+
+impl CollisionDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check `path` against the ones checked before, returning the path it collides with,
+    /// which is one of its parents when the collision is between directories
+    pub fn check(&mut self, path: impl AsRef<Path>) -> Option<PathBuf> {
+        let names = path
+            .as_ref()
+            .components()
+            .map(|component| component.as_os_str().to_owned())
+            .collect::<Vec<_>>();
+        let (_, parents) = names.split_last()?;
+
+        // Leave the directories that do not lead to `path`
+        let common = self
+            .directories
+            .iter()
+            .zip(parents)
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.directories.truncate(common);
+        self.names.truncate(common + 1);
+
+        if self.names.is_empty() {
+            self.names.push(HashMap::new());
+        }
+
+        let mut collides_with = None;
+
+        for (depth, name) in names.iter().enumerate().skip(common) {
+            let found = self.names[depth]
+                .entry(folded_name(name))
+                .or_insert_with(|| name.clone());
+
+            if found != name && collides_with.is_none() {
+                let mut other = self.directories.iter().collect::<PathBuf>();
+                other.push(found);
+                collides_with = Some(other);
+            }
+
+            if depth < parents.len() {
+                self.directories.push(name.clone());
+                self.names.push(HashMap::new());
+            }
+        }
+
+        collides_with
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    use super::{check_vault_path, find_collisions, normalize_path, Collision, CollisionDetector};
+
+    #[test]
+    fn test_normalize_path() {
+        // "é" decomposed, as macOS returns it
+        let decomposed = "cafe\u{301}/re\u{301}sume\u{301}.txt";
+
+        assert_eq!(
+            normalize_path(decomposed),
+            Path::new("caf\u{e9}/r\u{e9}sum\u{e9}.txt")
+        );
+        assert_eq!(normalize_path("already/nfc"), Path::new("already/nfc"));
+
+        let invalid = Path::new(OsStr::from_bytes(b"bad\xff/e\xcc\x81"));
+        assert_eq!(
+            normalize_path(invalid),
+            Path::new(OsStr::from_bytes(b"bad\xff/\xc3\xa9"))
+        );
+    }
+
+    #[test]
+    fn test_check_vault_path() {
+        assert!(check_vault_path("docs/a.txt").is_ok());
+        assert!(check_vault_path("docs/./a.txt").is_ok());
+
+        for path in ["", "/abs", "/", "../x", "docs/../../x", "./a.txt"] {
+            assert!(check_vault_path(path).is_err(), "{path} is accepted");
+        }
+    }
+
+    #[test]
+    fn test_no_collisions() {
+        assert!(find_collisions(["a/b", "a/c", "b/a", "c"]).is_empty());
+    }
+
+    #[test]
+    fn test_case_collisions() {
+        let collisions = find_collisions(["docs/Photo.jpg", "docs/photo.jpg", "docs/PHOTO.JPG"]);
+
+        assert_eq!(
+            collisions,
+            vec![
+                Collision {
+                    path: "docs/Photo.jpg".into(),
+                    collides_with: "docs/PHOTO.JPG".into(),
+                    renamed: "docs/Photo (1).jpg".into(),
+                },
+                Collision {
+                    path: "docs/photo.jpg".into(),
+                    collides_with: "docs/PHOTO.JPG".into(),
+                    renamed: "docs/photo (2).jpg".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalization_collisions() {
+        let collisions = find_collisions(["caf\u{e9}", "cafe\u{301}"]);
+
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].renamed, Path::new("caf\u{e9} (1)"));
+    }
+
+    #[test]
+    fn test_directory_collisions() {
+        let collisions = find_collisions(["Docs/a", "docs/b", "docs/c/d"]);
+
+        assert_eq!(
+            collisions,
+            vec![
+                Collision {
+                    path: "docs/b".into(),
+                    collides_with: "Docs".into(),
+                    renamed: "docs (1)/b".into(),
+                },
+                Collision {
+                    path: "docs/c/d".into(),
+                    collides_with: "Docs".into(),
+                    renamed: "docs (1)/c/d".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renamed_paths_do_not_collide() {
+        let collisions = find_collisions(["A", "a", "a (1)"]);
+
+        assert_eq!(collisions.len(), 2);
+        assert_eq!(collisions[0].renamed, Path::new("a (1)"));
+        assert_eq!(collisions[1].path, Path::new("a (1)"));
+        assert_eq!(collisions[1].collides_with, Path::new("a"));
+        assert_eq!(collisions[1].renamed, Path::new("a (1) (1)"));
+    }
+
+    #[test]
+    fn test_collision_detector() {
+        let mut detector = CollisionDetector::new();
+
+        let checked = [
+            "Docs/a",
+            "Docs/b/c",
+            "docs/d",
+            "docs/e",
+            "photo.jpg",
+            "Photo.jpg",
+            "z/caf\u{e9}",
+            "z/cafe\u{301}",
+        ]
+        .map(|path| detector.check(path));
+
+        assert_eq!(
+            checked,
+            [
+                None,
+                None,
+                Some("Docs".into()),
+                None,
+                None,
+                Some("photo.jpg".into()),
+                None,
+                Some("z/caf\u{e9}".into()),
+            ]
+        );
+    }
+}
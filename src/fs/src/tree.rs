@@ -32,46 +32,72 @@ impl FromIterator<PathBuf> for PathTree {
 
 impl PathTree {
     pub fn insert_file_path(&mut self, file_path: impl AsRef<Path>) {
+        self.insert_path(file_path, PathType::File);
+    }
+
+    /// Insert a symbolic link, which is a leaf of the tree like a file
+    pub fn insert_symlink_path(&mut self, symlink_path: impl AsRef<Path>) {
+        self.insert_path(symlink_path, PathType::Symlink);
+    }
+
+    /// Insert a directory, which is kept in the tree even if nothing is inserted into it
+    pub fn insert_directory_path(&mut self, directory_path: impl AsRef<Path>) {
+        self.insert_path(directory_path, PathType::Directory(HashMap::default()));
+    }
+
+    fn insert_path(&mut self, path: impl AsRef<Path>, leaf: PathType) {
         let mut current_path = match &mut self.0 {
             PathType::Directory(contents) => contents,
-            PathType::File => panic!(
-                "unexpected error: root is of type PathType::File instead of PathType::Directory"
-            ),
+            _ => panic!("unexpected error: root is not of type PathType::Directory"),
         };
-        let path_len = file_path.as_ref().iter().count();
+        let path_len = path.as_ref().iter().count();
+        let mut leaf = Some(leaf);
 
-        for (i, piece) in file_path.as_ref().iter().enumerate() {
+        for (i, piece) in path.as_ref().iter().enumerate() {
             let piece = piece.to_owned();
 
-            if current_path.get(&piece).is_some() {
-                // path already exists, just traverse
-                current_path = match current_path.get_mut(&piece).unwrap() {
-                    PathType::Directory (contents) => contents,
-                    PathType::File => panic!(
-                        "unexpected error: {piece:?} is of type PathType::File instead of PathType::Directory"
-                    ),
+            if current_path.get(&piece).is_none() {
+                let kind = if i + 1 == path_len {
+                    // Current piece is the leaf
+                    leaf.take().unwrap()
+                } else {
+                    // Current piece is a directory
+                    PathType::Directory(HashMap::default())
                 };
 
-                continue;
+                current_path.insert(piece.to_owned(), kind);
             }
 
             if i + 1 == path_len {
-                // Current piece is a file
-                current_path.insert(piece.to_owned(), PathType::File);
-            } else {
-                // Current piece is a directory
-                current_path.insert(piece.to_owned(), PathType::Directory(HashMap::default()));
-
-                current_path = match current_path.get_mut(&piece).unwrap() {
-                    PathType::Directory (contents ) => contents,
-                    PathType::File => panic!(
-                        "unexpected error: {piece:?} is of type PathType::File instead of PathType::Directory"
-                    ),
-                };
+                break;
             }
+
+            current_path = match current_path.get_mut(&piece).unwrap() {
+                PathType::Directory(contents) => contents,
+                _ => panic!("unexpected error: {piece:?} is not of type PathType::Directory"),
+            };
         }
     }
 
+    /// Whether `path` is a directory of the tree
+    pub fn is_directory(&self, path: impl AsRef<Path>) -> bool {
+        matches!(self.get(path), Some(PathType::Directory(_)))
+    }
+
+    /// Whether `path` is a symbolic link of the tree
+    pub fn is_symlink(&self, path: impl AsRef<Path>) -> bool {
+        matches!(self.get(path), Some(PathType::Symlink))
+    }
+
+    fn get(&self, path: impl AsRef<Path>) -> Option<&PathType> {
+        path.as_ref()
+            .iter()
+            .try_fold(&self.0, |current, piece| match current {
+                PathType::Directory(contents) => contents.get(piece),
+                _ => None,
+            })
+    }
+
     /// Traverse the tree and get all the paths sorted
     pub fn paths_ordered(&self) -> Vec<PathBuf> {
         let mut output = vec![];
@@ -86,10 +112,15 @@ impl PathTree {
         let mut paths_ordered: IndexSet<PathBuf> = IndexSet::new();
 
         for file_path in output {
-            let directory: PathBuf = file_path
-                .iter()
-                .take(file_path.iter().count() - 1)
-                .collect();
+            // Empty directories are part of the structure themselves
+            let directory: PathBuf = if self.is_directory(&file_path) {
+                file_path
+            } else {
+                file_path
+                    .iter()
+                    .take(file_path.iter().count() - 1)
+                    .collect()
+            };
 
             for len in 1..directory.iter().count() + 1 {
                 let partial_dir: PathBuf = directory.iter().take(len).collect();
@@ -105,9 +136,14 @@ impl PathTree {
     }
 }
 
-/// Fully traverse a PathType building a Vec<PathBuf>
+/// Fully traverse a PathType building a Vec<PathBuf>. Empty directories are included, the
+/// others are implied by their contents
 fn traverse_paths_ordered(item: &PathType, current_path: Vec<OsString>, output: &mut Vec<PathBuf>) {
     match item {
+        PathType::Directory(items) if items.is_empty() && !current_path.is_empty() => {
+            output.push(current_path.into_iter().collect());
+        }
+
         PathType::Directory(items) => {
             for (name, kind) in items.iter().sorted_by_key(|k| k.0) {
                 let mut current_path = current_path.clone();
@@ -116,7 +152,7 @@ fn traverse_paths_ordered(item: &PathType, current_path: Vec<OsString>, output:
             }
         }
 
-        PathType::File => {
+        PathType::File | PathType::Symlink => {
             let full_path: PathBuf = current_path.into_iter().collect();
             output.push(full_path);
         }
@@ -128,6 +164,7 @@ fn traverse_paths_ordered(item: &PathType, current_path: Vec<OsString>, output:
 enum PathType {
     Directory(HashMap<OsString, PathType>),
     File,
+    Symlink,
 }
 
 impl PathType {
@@ -308,4 +345,56 @@ mod tests {
             assert!(expected_dirs.contains(&dir))
         }
     }
+
+    /// Create a symlink with name
+    macro_rules! l {
+        ($name:tt) => {
+            (OsString::from($name), PathType::Symlink)
+        };
+    }
+
+    #[test]
+    fn test_path_tree_symlinks_and_empty_dirs() {
+        let mut tree: PathTree = ["some/file.txt"].iter().map(PathBuf::from).collect();
+        tree.insert_symlink_path("some/link");
+        tree.insert_directory_path("some/empty");
+        tree.insert_directory_path("other/empty");
+        tree.insert_directory_path("filled");
+        tree.insert_file_path("filled/file.txt");
+
+        let expected_structure = root!(
+            d!("some", f!("file.txt"), l!("link"), d!("empty",)),
+            d!("other", d!("empty",)),
+            d!("filled", f!("file.txt"))
+        );
+
+        assert_eq!(tree.0, expected_structure);
+
+        assert!(tree.is_symlink("some/link"));
+        assert!(tree.is_directory("some/empty"));
+        assert!(!tree.is_directory("some/file.txt"));
+        assert!(!tree.is_symlink("missing"));
+
+        let expected_paths: Vec<PathBuf> = vec![
+            "filled/file.txt",
+            "other/empty",
+            "some/empty",
+            "some/file.txt",
+            "some/link",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+
+        assert_eq!(tree.paths_ordered(), expected_paths);
+
+        let directories = tree.directory_structure();
+        let expected_dirs: Vec<PathBuf> =
+            vec!["filled", "other", "some", "other/empty", "some/empty"]
+                .into_iter()
+                .map(PathBuf::from)
+                .collect();
+
+        assert_eq!(directories, expected_dirs);
+    }
 }
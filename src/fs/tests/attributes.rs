@@ -0,0 +1,98 @@
+use std::{
+    fs::{metadata, set_permissions, symlink_metadata, write, File, FileTimes, Permissions},
+    os::unix::fs::{symlink, PermissionsExt},
+    time::{Duration, SystemTime},
+};
+
+use fs::FileAttributes;
+use tmp::Tmp;
+
+#[test]
+fn test_read_and_apply_attributes() {
+    let tmp = Tmp::random();
+    let source = tmp.base_path().join("source");
+    let destination = tmp.base_path().join("destination");
+
+    write(&source, b"contents").unwrap();
+    write(&destination, b"contents").unwrap();
+
+    let modified_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let accessed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_650_000_000);
+    let times = FileTimes::new()
+        .set_modified(modified_at)
+        .set_accessed(accessed_at);
+    File::options()
+        .write(true)
+        .open(&source)
+        .unwrap()
+        .set_times(times)
+        .unwrap();
+    set_permissions(&source, Permissions::from_mode(0o640)).unwrap();
+
+    // Not every filesystem supports user xattrs
+    let xattrs_supported = xattr::set(&source, "user.krypta", b"value").is_ok();
+
+    let attributes = FileAttributes::read(&source, &metadata(&source).unwrap());
+    assert_eq!(attributes.modified_at, Some(modified_at));
+    assert_eq!(attributes.accessed_at, Some(accessed_at));
+    assert_eq!(attributes.mode, Some(0o640));
+
+    attributes.apply(&destination).unwrap();
+
+    let restored = FileAttributes::read(&destination, &metadata(&destination).unwrap());
+    assert_eq!(restored, attributes);
+
+    if xattrs_supported {
+        assert_eq!(
+            xattr::get(&destination, "user.krypta").unwrap(),
+            Some(b"value".to_vec())
+        );
+    }
+}
+
+#[test]
+fn test_apply_missing_attributes() {
+    let tmp = Tmp::random();
+    let path = tmp.base_path().join("file");
+    write(&path, b"contents").unwrap();
+
+    let before = metadata(&path).unwrap();
+    FileAttributes::default().apply(&path).unwrap();
+    let after = metadata(&path).unwrap();
+
+    assert_eq!(before.modified().unwrap(), after.modified().unwrap());
+    assert_eq!(before.permissions(), after.permissions());
+}
+
+#[test]
+fn test_apply_to_symlink() {
+    let tmp = Tmp::random();
+    let target = tmp.base_path().join("target");
+    let link = tmp.base_path().join("link");
+
+    write(&target, b"contents").unwrap();
+    set_permissions(&target, Permissions::from_mode(0o600)).unwrap();
+    symlink("target", &link).unwrap();
+
+    let attributes = FileAttributes {
+        modified_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+        accessed_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_650_000_000)),
+        mode: Some(0o777),
+        ..Default::default()
+    };
+    attributes.apply(&link).unwrap();
+
+    // The link is changed, not its target
+    let link_metadata = symlink_metadata(&link).unwrap();
+    assert_eq!(
+        link_metadata.modified().unwrap(),
+        attributes.modified_at.unwrap()
+    );
+
+    let target_metadata = metadata(&target).unwrap();
+    assert_eq!(target_metadata.permissions().mode() & 0o777, 0o600);
+    assert_ne!(
+        target_metadata.modified().unwrap(),
+        attributes.modified_at.unwrap()
+    );
+}
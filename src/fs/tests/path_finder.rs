@@ -1,4 +1,12 @@
-use fs::PathFinder;
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, read_dir, set_permissions, write, Permissions},
+    os::unix::{fs::symlink, fs::PermissionsExt, net::UnixListener},
+    path::PathBuf,
+};
+
+use fs::{PathFinder, WalkEntry};
+use itertools::Itertools;
 use rand::{prelude::SmallRng, SeedableRng};
 use tmp::{RandomFill, Tmp};
 
@@ -31,3 +39,295 @@ fn test_path_finder() {
         assert_eq!(len_checksum, EXPECTED_CHECKSUMS[i]);
     }
 }
+
+#[test]
+fn test_path_finder_symlinks_and_empty_dirs() {
+    let tmp = Tmp::random();
+    let base_path = tmp.base_path();
+
+    create_dir_all(base_path.join("full/empty")).unwrap();
+    create_dir_all(base_path.join("other")).unwrap();
+    write(base_path.join("full/file"), b"contents").unwrap();
+    symlink("file", base_path.join("full/link")).unwrap();
+    symlink("../missing", base_path.join("dangling")).unwrap();
+    symlink("full", base_path.join("dir_link")).unwrap();
+
+    let path_finder = PathFinder::from_source_path(base_path).unwrap();
+
+    let sorted = |paths: Vec<&PathBuf>| paths.into_iter().sorted().cloned().collect::<Vec<_>>();
+    let expected = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+    assert_eq!(
+        sorted(path_finder.metadatas.keys().collect()),
+        expected(&["full/file"])
+    );
+    assert_eq!(
+        sorted(path_finder.symlinks.keys().collect()),
+        expected(&["dangling", "dir_link", "full/link"])
+    );
+    assert_eq!(
+        sorted(path_finder.empty_dirs.keys().collect()),
+        expected(&["full/empty", "other"])
+    );
+}
+
+#[test]
+fn test_path_finder_ignore_files_and_globs() {
+    let tmp = Tmp::random();
+    let base_path = tmp.base_path();
+
+    for path in [
+        "notes.txt",
+        "notes.txt.swp",
+        ".DS_Store",
+        "node_modules/left-pad/index.js",
+        "src/main.rs",
+        "src/.DS_Store",
+        "src/target/debug/main",
+        "docs/draft.md",
+        "docs/final.md",
+    ] {
+        let path = base_path.join(path);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, b"contents").unwrap();
+    }
+
+    write(
+        base_path.join(".kryptaignore"),
+        "node_modules/\n*.swp\n.DS_Store\n",
+    )
+    .unwrap();
+    write(base_path.join("src/.kryptaignore"), "target\n").unwrap();
+
+    let found = |path_finder: PathFinder| {
+        path_finder
+            .metadatas
+            .into_keys()
+            .sorted()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        found(PathFinder::from_source_path(&base_path).unwrap()),
+        [
+            ".kryptaignore",
+            "docs/draft.md",
+            "docs/final.md",
+            "notes.txt",
+            "src/.kryptaignore",
+            "src/main.rs"
+        ]
+    );
+
+    assert_eq!(
+        found(
+            PathFinder::builder(&base_path)
+                .exclude("docs/draft.md")
+                .exclude(".kryptaignore")
+                .build()
+                .unwrap()
+        ),
+        ["docs/final.md", "notes.txt", "src/main.rs"]
+    );
+
+    assert_eq!(
+        found(
+            PathFinder::builder(&base_path)
+                .include("*.md")
+                .include("src/*")
+                .build()
+                .unwrap()
+        ),
+        [
+            "docs/draft.md",
+            "docs/final.md",
+            "src/.kryptaignore",
+            "src/main.rs"
+        ]
+    );
+
+    assert_eq!(
+        found(
+            PathFinder::builder(&base_path)
+                .ignore_files(false)
+                .include("**/.DS_Store")
+                .build()
+                .unwrap()
+        ),
+        [".DS_Store", "src/.DS_Store"]
+    );
+
+    assert!(PathFinder::builder(&base_path)
+        .include("[")
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_path_finder_reports_skipped_paths() {
+    let tmp = Tmp::random();
+    let base_path = tmp.base_path();
+
+    create_dir_all(base_path.join("locked")).unwrap();
+    write(base_path.join("locked/secret"), b"contents").unwrap();
+    write(base_path.join("file"), b"contents").unwrap();
+    let _socket = UnixListener::bind(base_path.join("socket")).unwrap();
+
+    set_permissions(base_path.join("locked"), Permissions::from_mode(0o000)).unwrap();
+    // Permissions are not enforced for root
+    let locked_readable = read_dir(base_path.join("locked")).is_ok();
+
+    let path_finder = PathFinder::from_source_path(&base_path).unwrap();
+    set_permissions(base_path.join("locked"), Permissions::from_mode(0o755)).unwrap();
+
+    let skipped = path_finder
+        .skipped
+        .iter()
+        .map(|skipped| skipped.path.clone())
+        .collect::<Vec<_>>();
+
+    if locked_readable {
+        assert_eq!(skipped, [PathBuf::from("socket")]);
+    } else {
+        assert_eq!(skipped, [PathBuf::from("locked"), PathBuf::from("socket")]);
+        // Its contents are unknown, so it must not be stored as an empty directory
+        assert!(path_finder.empty_dirs.is_empty());
+    }
+
+    assert!(path_finder.metadatas.contains_key(&PathBuf::from("file")));
+}
+
+#[test]
+fn test_path_finder_parallel_matches_single_thread() {
+    let mut rng = SmallRng::seed_from_u64(40);
+    let tmp = Tmp::random();
+    tmp.random_fill(2_000, &mut rng).unwrap();
+
+    let find = |threads| {
+        let path_finder = PathFinder::builder(tmp.base_path())
+            .threads(threads)
+            .build()
+            .unwrap();
+
+        path_finder
+            .metadatas
+            .into_iter()
+            .map(|(path, metadata)| (path, metadata.len()))
+            .sorted()
+            .collect::<Vec<_>>()
+    };
+
+    let single = find(1);
+
+    assert_eq!(single.len(), 2_000);
+    assert_eq!(single, find(8));
+}
+
+#[test]
+fn test_path_finder_single_file() {
+    let tmp = Tmp::random();
+    let base_path = tmp.base_path();
+
+    create_dir_all(base_path.join("dir")).unwrap();
+    write(base_path.join("dir/file"), b"contents").unwrap();
+    symlink("file", base_path.join("dir/link")).unwrap();
+
+    let path_finder = PathFinder::from_source_path(base_path.join("dir/file")).unwrap();
+    assert_eq!(
+        path_finder.root,
+        base_path.join("dir").canonicalize().unwrap()
+    );
+    assert_eq!(
+        path_finder.metadatas.into_keys().collect::<Vec<_>>(),
+        [PathBuf::from("file")]
+    );
+
+    // Links given as source are not followed
+    let path_finder = PathFinder::from_source_path(base_path.join("dir/link")).unwrap();
+    assert!(path_finder.metadatas.is_empty());
+    assert_eq!(
+        path_finder.symlinks.into_keys().collect::<Vec<_>>(),
+        [PathBuf::from("link")]
+    );
+
+    assert!(PathFinder::from_source_path(base_path.join("missing")).is_err());
+}
+
+#[test]
+fn test_walk_matches_path_finder() {
+    let mut rng = SmallRng::seed_from_u64(45);
+    let tmp = Tmp::random();
+    let base_path = tmp.base_path();
+    tmp.random_fill(300, &mut rng).unwrap();
+
+    create_dir_all(base_path.join("empty/nested")).unwrap();
+    create_dir_all(base_path.join("only_excluded")).unwrap();
+    create_dir_all(base_path.join("only_logs")).unwrap();
+    write(base_path.join("only_excluded/cache.tmp"), b"contents").unwrap();
+    write(base_path.join("only_logs/out.log"), b"contents").unwrap();
+    symlink("missing", base_path.join("link")).unwrap();
+
+    let builders = [
+        PathFinder::builder(&base_path),
+        PathFinder::builder(&base_path).exclude("*.tmp"),
+        PathFinder::builder(&base_path)
+            .exclude("*.log")
+            .include("only_*"),
+        PathFinder::builder(base_path.join("link")),
+    ];
+
+    for builder in builders {
+        let path_finder = builder.clone().build().unwrap();
+        let walk = builder.walk().unwrap();
+        assert_eq!(walk.root, path_finder.root);
+
+        let (mut files, mut symlinks, mut empty_dirs, mut skipped) =
+            (vec![], vec![], vec![], vec![]);
+        let mut order = vec![];
+
+        for entry in walk {
+            let (found, path) = match entry {
+                WalkEntry::File(path, _) => (&mut files, path),
+                WalkEntry::Symlink(path, _) => (&mut symlinks, path),
+                WalkEntry::EmptyDir(path, _) => (&mut empty_dirs, path),
+                WalkEntry::Skipped(found) => (&mut skipped, found.path),
+            };
+
+            order.push(path.clone());
+            found.push(path);
+        }
+
+        // Depth-first, sorted by name
+        for pair in order.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+
+        let sorted_keys = |map: HashMap<PathBuf, _>| map.into_keys().sorted().collect::<Vec<_>>();
+        assert_eq!(files, sorted_keys(path_finder.metadatas));
+        assert_eq!(symlinks, sorted_keys(path_finder.symlinks));
+        assert_eq!(empty_dirs, sorted_keys(path_finder.empty_dirs));
+
+        let skipped_paths = path_finder.skipped.into_iter().map(|skipped| skipped.path);
+        assert_eq!(skipped, skipped_paths.collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_parallel_walk_stops() {
+    let mut rng = SmallRng::seed_from_u64(7);
+
+    let tmp = Tmp::random();
+    tmp.random_fill(200, &mut rng).unwrap();
+
+    let mut found = 0;
+    PathFinder::builder(tmp.base_path())
+        .walk_parallel()
+        .unwrap()
+        .run(|_| {
+            found += 1;
+            found < 5
+        });
+
+    assert_eq!(found, 5);
+}
@@ -1,179 +1,291 @@
-use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
-};
+use std::path::PathBuf;
 
-use byte_unit::Byte;
-use crypto::{
-    crypt::FileEncryptBulk, errors::CryptoError, hash::Blake3Concurrent, traits::ComputeBulk,
-};
-use database::{models, traits::InsertMany, Database};
-use fs::PathFinder;
+use crypto::types::Report;
+use database::EncryptedDatabase;
+use fs::{check_vault_path, errors::FsError, PathFinder, PathFinderBuilder, WalkEntry};
+use tokio::task::spawn_blocking;
 use utils::ask_yes_or_no;
 
-use crate::utils::config::Config;
+use crate::{
+    errors::{AtPath, KryptaError, KryptaResult},
+    utils::{
+        agent::Keyring,
+        config::Config,
+        output::ReportOutput,
+        pipeline::{add_paths, PipelineOptions},
+    },
+};
 
-/// Compute BLAKE3 hashes for files in `unlocked_path`
-/// returned paths are relative and do not contain host-specific bits
-fn compute_paths_hashes(
-    root_path: impl AsRef<Path>,
-    files: &[impl AsRef<Path>],
-) -> anyhow::Result<HashMap<PathBuf, String>> {
-    let root_path = root_path.as_ref();
+/// Which paths `add` finds in its inputs, and how
+#[derive(Debug, Default)]
+pub struct AddOptions {
+    /// Only add the paths matching these globs, if any
+    pub include: Vec<String>,
+    /// Never add the paths matching these globs
+    pub exclude: Vec<String>,
+    /// Add what can be read even if some paths cannot
+    pub allow_partial: bool,
+    /// Override how many files are read at once
+    pub io_concurrency: Option<usize>,
+    /// Override how many files are encrypted at once
+    pub cpu_concurrency: Option<usize>,
+}
 
-    let absolute_paths = files
-        .iter()
-        .map(|relative| {
-            let mut path = root_path.to_path_buf();
-            path.push(relative);
-            path
-        })
-        .collect::<Vec<_>>();
+/// Add the `inputs` to database in `prefix`. Inputs can be files, which are added by name,
+/// directories, whose contents are added, and glob patterns matching them. Unless
+/// `options.allow_partial` is set, a first walk lists every path that cannot be read, and any
+/// of them aborts the whole operation before anything is added. A path that only becomes
+/// unreadable after that walk stops the others from being added, keeping what was added
+pub async fn add(
+    db: &mut EncryptedDatabase,
+    master_key: &Keyring,
+    inputs: Vec<PathBuf>,
+    virtual_prefix: Option<PathBuf>,
+    options: AddOptions,
+) -> KryptaResult<ReportOutput> {
+    let locked_path = Config::get_locked_path()?;
+    let virtual_prefix = virtual_prefix.unwrap_or("".into());
 
-    let hasher = Blake3Concurrent::try_new(&absolute_paths)?;
-    let result = hasher.start_all();
+    if !virtual_prefix.as_os_str().is_empty() {
+        check_vault_path(&virtual_prefix)?;
+    }
 
-    let root_path_len = root_path.iter().count();
-    let relative_result = result
-        .into_iter()
-        .map(|(absolute_path, hash)| {
-            // Skip hosts bits
-            let relative_path: PathBuf = absolute_path.iter().skip(root_path_len).collect();
+    let builders = find_inputs(inputs, &options.include, &options.exclude)?;
 
-            (relative_path, hash.to_string())
-        })
-        .collect::<HashMap<_, _>>();
+    if !options.allow_partial {
+        let (unreadable, total) = find_unreadable(builders.clone()).await?;
 
-    Ok(relative_result)
-}
+        if !unreadable.is_empty() {
+            eprintln!("Cannot read {} paths:", unreadable.len());
 
-/// Encrypt many files
-pub async fn encrypt_many_files(
-    files: Vec<models::File>,
-    virtual_prefix: PathBuf,
-    source_root_path: impl AsRef<Path>,
-    locked_path: impl AsRef<Path>,
-) -> anyhow::Result<()> {
-    let source_root_path = source_root_path.as_ref();
-    let locked_path = locked_path.as_ref();
+            for (path, error) in &unreadable {
+                eprintln!("  {}: {error}", path.display());
+            }
 
-    let virtual_prefix_len = virtual_prefix.iter().count();
+            eprintln!("Nothing was added, use --allow-partial to add the other paths anyway");
 
-    // Start encryption job
-    log::trace!("Encryption job started");
+            return Err(KryptaError::Partial {
+                action: "read",
+                failed: unreadable.len(),
+                total,
+            });
+        }
+    }
 
-    let encryptors = files
+    let walks = builders
         .into_iter()
-        .map(|mut file| {
-            // remove `virtual_prefix` from File
-            let p: PathBuf = PathBuf::from(file.path)
-                .iter()
-                .skip(virtual_prefix_len)
-                .collect();
+        .map(|builder| Ok((builder.walk_parallel()?, virtual_prefix.clone())))
+        .collect::<KryptaResult<Vec<_>>>()?;
 
-            file.path = p.to_string_lossy().to_string();
+    let sources = walks
+        .iter()
+        .map(|(walk, _)| walk.source_path.display().to_string())
+        .collect::<Vec<_>>();
 
-            models::File::try_into_encryptor(file, locked_path, source_root_path)
-        })
-        .collect::<Result<Vec<_>, CryptoError>>()?;
+    let confirmed = ask_yes_or_no(format!(
+        "You are inserting {} into krypta. Are you sure?",
+        sources.join(", ")
+    ))
+    .map_err(KryptaError::Prompt)?;
 
-    let encryptor = FileEncryptBulk::new(encryptors);
-    let encryption_status = encryptor.start_all();
+    if !confirmed {
+        return Err(KryptaError::Declined);
+    }
 
-    log::trace!("Done with encryption job");
+    let mut pipeline_options = PipelineOptions {
+        read_mode: Config::get_read_mode()?,
+        allow_partial: options.allow_partial,
+        ..Default::default()
+    };
 
-    // Error check
-    let errors = encryption_status
-        .iter()
-        .filter_map(|(_, result)| match result {
-            Ok(_) => None,
-            Err(error) => Some(error),
-        })
-        .collect::<Vec<_>>();
+    if let Some(io_concurrency) = options.io_concurrency {
+        pipeline_options.io_concurrency = io_concurrency;
+    }
 
-    if errors.is_empty() {
-        log::info!(
-            "Encrypted {} files, with no errors",
-            encryption_status.len(),
-        );
+    if let Some(cpu_concurrency) = options.cpu_concurrency {
+        pipeline_options.cpu_concurrency = cpu_concurrency;
+    }
+
+    let pipeline_report = add_paths(db, master_key, &locked_path, walks, &pipeline_options).await?;
 
-        Ok(())
-    } else {
-        for error in &errors {
-            println!("Error: {:?}", error);
+    if let Some(stopped_at) = &pipeline_report.stopped_at {
+        for (path, error) in &pipeline_report.failed {
+            eprintln!("  {}: {error:#}", path.display());
         }
 
-        log::warn!(
-            "Encrypted {} files correctly, {} errors",
-            encryption_status.len(),
-            errors.len()
+        eprintln!(
+            "Stopped at {} after adding {} paths, use --allow-partial to add the paths that can \
+             be read anyway",
+            stopped_at.display(),
+            pipeline_report.added
         );
 
-        Err(anyhow::Error::msg("Unable to encrypt all files"))
+        return Err(KryptaError::Partial {
+            action: "added",
+            failed: pipeline_report.failed.len(),
+            total: pipeline_report.failed.len() + pipeline_report.added as usize,
+        });
+    }
+
+    let mut report = Report {
+        processed_file_count: pipeline_report.added as usize,
+        processed_bytes: pipeline_report.bytes,
+        ..Default::default()
+    };
+
+    for (path, error) in &pipeline_report.failed {
+        report.failed(path, format!("{error:#}"));
     }
+
+    Ok(ReportOutput {
+        action: "added",
+        report,
+    })
 }
 
-/// Add a path `target_path` to database in `prefix`
-pub async fn add(db: &mut Database, source_path: PathBuf, virtual_prefix: Option<PathBuf>) {
-    let locked_path = Config::get_locked_path();
-    let virtual_prefix = virtual_prefix.unwrap_or("".into());
+/// Walk the inputs without adding anything, getting the paths that cannot be read and how
+/// many paths were found in total. Only the unreadable ones are kept in memory
+async fn find_unreadable(
+    builders: Vec<PathFinderBuilder>,
+) -> KryptaResult<(Vec<(PathBuf, FsError)>, usize)> {
+    spawn_blocking(move || {
+        let mut unreadable = vec![];
+        let mut total = 0;
+
+        for builder in builders {
+            let walk = builder.walk_parallel()?;
+            let root = walk.root.clone();
+
+            walk.run(|entry| {
+                if let WalkEntry::Skipped(skipped) = entry {
+                    unreadable.push((root.join(skipped.path), skipped.error));
+                }
+
+                total += 1;
+                true
+            });
+        }
 
-    let pathfinder = PathFinder::from_source_path(&source_path)
-        .unwrap_or_else(|error| panic!("Cannot find files in {source_path:?}: {:?}", error));
+        Ok((unreadable, total))
+    })
+    .await?
+}
 
-    let found_paths = pathfinder
-        .metadatas
-        .keys()
-        .map(|path| path.as_path())
-        .collect::<Vec<_>>();
+/// Expand the glob patterns in `inputs` and configure how to find the paths in each of
+/// them, failing when an input is inside another one
+fn find_inputs(
+    inputs: Vec<PathBuf>,
+    include: &[String],
+    exclude: &[String],
+) -> KryptaResult<Vec<PathFinderBuilder>> {
+    let mut expanded = vec![];
+
+    for input in inputs {
+        let is_pattern = input
+            .to_str()
+            .is_some_and(|input| input.contains(['*', '?', '[']));
+
+        // A file may be named like a pattern
+        if !is_pattern || input.symlink_metadata().is_ok() {
+            expanded.push(input);
+            continue;
+        }
 
-    let total_size_bytes = pathfinder
-        .metadatas
-        .values()
-        .map(|metadata| metadata.len())
-        .sum::<u64>();
+        let pattern = input.to_string_lossy();
+        let invalid = |error: &dyn std::fmt::Display| {
+            KryptaError::InvalidInput(format!("Invalid pattern {pattern}: {error}"))
+        };
 
-    let total_size = Byte::from_bytes(total_size_bytes.into());
+        let matches = glob::glob(&pattern)
+            .map_err(|error| invalid(&error))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| invalid(&error))?;
 
-    ask_yes_or_no(format!(
-        "You are inserting {} paths ({}) into krypta. Are you sure?",
-        found_paths.len(),
-        total_size.get_appropriate_unit(false)
-    ));
+        if matches.is_empty() {
+            return Err(KryptaError::InvalidInput(format!(
+                "No paths match {pattern}"
+            )));
+        }
 
-    let hashes_map = compute_paths_hashes(&source_path, &found_paths).unwrap();
+        expanded.extend(matches);
+    }
 
-    // first add files to database
-    let mut files = vec![];
+    let mut builders = expanded
+        .into_iter()
+        .map(|input| {
+            let mut builder = PathFinder::builder(&input);
 
-    for (file_path, metadata) in pathfinder.metadatas {
-        let file_hash = hashes_map.get(&file_path).unwrap().to_owned();
+            for glob in include {
+                builder = builder.include(glob);
+            }
 
-        // full_path = prefix + host_relative_path
-        let full_path = {
-            let mut p = virtual_prefix.clone();
-            p.push(file_path);
-            p
-        };
+            for glob in exclude {
+                builder = builder.exclude(glob);
+            }
 
-        let title = full_path.to_string_lossy().to_string();
+            // Resolves the source path and checks the globs, without walking yet
+            let source_path = builder.clone().walk_parallel().at_path(&input)?.source_path;
 
-        let f = models::File::new(title, full_path, file_hash, metadata.len());
-        files.push(f);
-    }
+            Ok((source_path, builder))
+        })
+        .collect::<KryptaResult<Vec<_>>>()?;
 
-    let tx = db.transaction().unwrap();
+    // Sorting puts everything inside a path right after it
+    builders.sort_by(|a, b| a.0.cmp(&b.0));
 
-    // insert all files
-    let files = models::File::insert_many(&tx, files).unwrap();
-    println!("Added {} files to the database", files.len());
+    for pair in builders.windows(2) {
+        let (outer, inner) = (&pair[0].0, &pair[1].0);
 
-    // start encryption job
-    encrypt_many_files(files, virtual_prefix, source_path, locked_path)
-        .await
-        .unwrap();
+        if inner.starts_with(outer) {
+            return Err(KryptaError::InvalidInput(format!(
+                "{} overlaps with {}, add only one of them",
+                inner.display(),
+                outer.display()
+            )));
+        }
+    }
 
-    tx.commit().unwrap();
+    Ok(builders.into_iter().map(|(_, builder)| builder).collect())
+}
 
-    println!("All done.");
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir_all, write},
+        os::unix::net::UnixListener,
+    };
+
+    use fs::PathFinder;
+    use tmp::Tmp;
+
+    use super::find_unreadable;
+
+    #[tokio::test]
+    async fn test_find_unreadable() {
+        let tmp = Tmp::random();
+        let base_path = tmp.base_path();
+
+        create_dir_all(base_path.join("docs")).unwrap();
+        write(base_path.join("docs/file"), b"contents").unwrap();
+        write(base_path.join("file"), b"contents").unwrap();
+        let _sockets = [
+            UnixListener::bind(base_path.join("docs/socket")).unwrap(),
+            UnixListener::bind(base_path.join("socket")).unwrap(),
+        ];
+
+        let (mut unreadable, total) = find_unreadable(vec![PathFinder::builder(&base_path)])
+            .await
+            .unwrap();
+        unreadable.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // Both are listed, not only the first one found
+        assert_eq!(
+            unreadable
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            [base_path.join("docs/socket"), base_path.join("socket")]
+        );
+        assert_eq!(total, 4);
+    }
 }
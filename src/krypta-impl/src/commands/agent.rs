@@ -0,0 +1,34 @@
+use std::{fs::remove_file, time::Duration};
+
+use database::agent_socket_file;
+
+use crate::{
+    errors::{KryptaError, KryptaResult},
+    utils::{
+        agent::{serve, AgentClient},
+        vault::unlock_vault,
+    },
+};
+
+/// Unlock the vault and serve the master key to the other commands
+pub async fn agent(timeout: u64) -> KryptaResult<()> {
+    if AgentClient::connect().is_some() {
+        return Err(KryptaError::AgentRunning);
+    }
+
+    // Left behind by an agent that has been killed
+    let socket_path = agent_socket_file()?;
+    if socket_path.exists() {
+        remove_file(&socket_path)?;
+    }
+
+    let (_, master_key) = unlock_vault()?;
+
+    println!("Agent listening on {socket_path:?}, it locks after {timeout} idle seconds");
+
+    serve(&socket_path, master_key, Duration::from_secs(timeout)).await?;
+
+    println!("Agent locked");
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::{
+    fs::File,
+    io::{stdout, ErrorKind},
+    path::PathBuf,
+};
+
+use crypto::{
+    crypt::{CipherFormat, AEAD_NONCE_SIZE},
+    errors::CryptoError,
+};
+use database::{
+    models::{self, EntryKind},
+    Database,
+};
+
+use crate::{
+    errors::{AtPath, KryptaError, KryptaResult},
+    utils::{agent::Keyring, config::Config},
+};
+
+/// Decrypt the file in `path` to stdout. Messages go to stderr, so that stdout only gets the
+/// contents
+pub async fn cat(db: &mut Database, master_key: &Keyring, path: PathBuf) -> KryptaResult<()> {
+    let locked_path = Config::get_locked_path()?;
+
+    let file = match models::File::find_by_path(db, &path)? {
+        Some(file) if file.kind == EntryKind::File => file,
+        Some(_) => return Err(KryptaError::NotAFile(path)),
+        None => return Err(KryptaError::NotInVault(path)),
+    };
+
+    let key = file.unwrap_key(master_key)?;
+    // Should never fail as nonce len is constant
+    let nonce: [u8; AEAD_NONCE_SIZE] = file.nonce.try_into().unwrap();
+    let locked_file = File::open(locked_path.join(&file.locked_hash)).at_path(&path)?;
+
+    let format = CipherFormat::from(file.format);
+
+    match format.decrypt(locked_file, stdout().lock(), &key, &nonce.into()) {
+        Ok(_) => Ok(()),
+        // The reader went away, like `head` does
+        Err(CryptoError::InputOutput(error)) if error.kind() == ErrorKind::BrokenPipe => Ok(()),
+        Err(error) => Err(error).at_path(path),
+    }
+}
@@ -1,61 +1,117 @@
-use std::{collections::HashMap, path::PathBuf};
-
-use database::{
-    models,
-    traits::{FetchAll, Get},
-    Database,
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::PathBuf,
 };
-use fs::PathFinder;
 
-use crate::utils::config::Config;
+use database::{models, traits::FetchAll, Database};
+use fs::PathFinder;
+use serde::Serialize;
 
-pub async fn check(db: &mut Database) {
-    let db_files = models::File::fetch_all(db)
-        .unwrap()
-        .into_iter()
-        .map(|file| (PathBuf::from(file.locked_hash), file.id.unwrap()))
-        .collect::<HashMap<_, _>>(); // HashMap in order to remove duplicates
+use crate::{
+    errors::{KryptaError, KryptaResult},
+    utils::{
+        config::Config,
+        output::{lossy_path, Output},
+    },
+};
 
-    let locked_path = Config::get_locked_path();
-    let fs_files = PathFinder::from_source_path(locked_path).unwrap().metadatas;
+/// A file of the database whose encrypted contents are not in locked_path
+#[derive(Debug, Serialize)]
+pub struct MissingContents {
+    pub locked_hash: String,
+    #[serde(serialize_with = "lossy_path")]
+    pub path: PathBuf,
+}
 
-    let mut errors_count = 0;
+/// How the database and locked_path differ
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    /// Distinct contents used by the database
+    pub database_files: usize,
+    /// Files in locked_path
+    pub locked_files: usize,
+    pub missing_contents: Vec<MissingContents>,
+    /// Files in locked_path that the database does not use
+    pub orphaned_contents: Vec<String>,
+}
 
-    if db_files.len() != fs_files.len() {
-        println!(
-            "consistency error: Database has {} different files, while Fs has {} different files",
-            db_files.len(),
-            fs_files.len()
-        );
-    }
+impl Output for CheckReport {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.database_files != self.locked_files {
+            writeln!(
+                out,
+                "consistency error: Database has {} different files, while Fs has {} different files",
+                self.database_files, self.locked_files
+            )?;
+        }
 
-    for (db_file, id) in &db_files {
-        if fs_files.get(db_file).is_none() {
-            let file_record = models::File::get(db, *id).unwrap().unwrap();
+        for missing in &self.missing_contents {
+            writeln!(
+                out,
+                "consistency error: file with Hash {:?} is in Database but cannot be found in Fs\nthe file is: {}",
+                missing.locked_hash,
+                missing.path.display()
+            )?;
+        }
 
-            println!(
-                "consistency error: file with Hash {:?} is in Database but cannot be found in Fs\nthe file is: {:#?}",
-                db_file, file_record
-            );
+        for orphaned in &self.orphaned_contents {
+            writeln!(
+                out,
+                "consistency error: file with Hash {orphaned:?} is in Fs but cannot be found in Database"
+            )?;
+        }
 
-            errors_count += 1;
+        if self.missing_contents.is_empty() && self.orphaned_contents.is_empty() {
+            writeln!(out, "consistency check: all ok")?;
         }
+
+        Ok(())
     }
 
-    for fs_file in fs_files.keys() {
-        if db_files.get(fs_file).is_none() {
-            println!(
-                "consistency error: file with Hash {:?} is in Fs but cannot be found in Database",
-                fs_file
-            );
+    fn outcome(&self) -> KryptaResult<()> {
+        let errors_count = self.missing_contents.len() + self.orphaned_contents.len();
 
-            errors_count += 1;
+        if errors_count == 0 {
+            Ok(())
+        } else {
+            Err(KryptaError::Inconsistent(errors_count))
         }
     }
+}
 
-    if errors_count == 0 {
-        println!("consistency check: all ok");
-    } else {
-        println!("found a total of {errors_count} errors");
-    }
+pub async fn check(db: &mut Database) -> KryptaResult<CheckReport> {
+    let db_files = models::File::fetch_all(db)?
+        .into_iter()
+        // Only regular files have contents in locked_path
+        .filter(|file| file.kind == models::EntryKind::File)
+        .map(|file| (PathBuf::from(&file.locked_hash), file))
+        .collect::<HashMap<_, _>>(); // HashMap in order to remove duplicates
+
+    let locked_path = Config::get_locked_path()?;
+    let fs_files = PathFinder::from_source_path(locked_path)?.metadatas;
+
+    let mut missing_contents = db_files
+        .iter()
+        .filter(|(db_file, _)| !fs_files.contains_key(*db_file))
+        .map(|(_, file)| MissingContents {
+            locked_hash: file.locked_hash.clone(),
+            path: PathBuf::from(file),
+        })
+        .collect::<Vec<_>>();
+    missing_contents.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut orphaned_contents = fs_files
+        .keys()
+        .filter(|fs_file| !db_files.contains_key(*fs_file))
+        .map(|fs_file| fs_file.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    orphaned_contents.sort();
+
+    Ok(CheckReport {
+        database_files: db_files.len(),
+        locked_files: fs_files.len(),
+        missing_contents,
+        orphaned_contents,
+    })
 }
@@ -1,33 +1,67 @@
-use std::path::PathBuf;
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
 
-use crate::utils::config::Config;
+use crypto::ReadMode;
+use serde::Serialize;
 
-pub async fn config(key: String, value: Option<String>) {
-    let mut config = Config::get();
+use crate::{
+    errors::{AtPath, ConfigError, KryptaError, KryptaResult},
+    utils::{config::Config, output::Output},
+};
+
+/// A setting of `krypta.toml`, after it has been set
+#[derive(Debug, Serialize)]
+pub struct ConfigValue {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl Output for ConfigValue {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{} => {:?}", self.key, self.value)
+    }
+}
+
+pub async fn config(key: String, value: Option<String>) -> KryptaResult<ConfigValue> {
+    let mut config = Config::get()?;
 
     let value_mut = match key.as_str() {
         "locked" => &mut config.locked_path,
-        _ => panic!(),
+        "identity" => &mut config.identity_path,
+        "read-mode" => &mut config.read_mode,
+        _ => return Err(ConfigError::UnknownKey(key).into()),
     };
 
     match value {
         Some(new_value) => {
             // set
 
-            if key == "locked" {
-                let path = PathBuf::from(new_value).canonicalize().unwrap();
+            if key == "locked" || key == "identity" {
+                let path = PathBuf::from(&new_value)
+                    .canonicalize()
+                    .at_path(new_value)?;
                 let new_value = path.to_string_lossy().to_string();
 
                 *value_mut = Some(new_value);
+            } else if key == "read-mode" {
+                let read_mode = new_value
+                    .parse::<ReadMode>()
+                    .map_err(|error| KryptaError::InvalidInput(error.to_string()))?;
+
+                *value_mut = Some(read_mode.to_string());
             } else {
                 *value_mut = Some(new_value)
             }
         }
         None => {
             // get
-            println!("{key} => {value_mut:?}");
         }
     }
 
-    Config::set(config);
+    let value = value_mut.clone();
+    Config::set(config)?;
+
+    Ok(ConfigValue { key, value })
 }
@@ -0,0 +1,140 @@
+use std::io::{self, Write};
+
+use cli::{DbCommand, OutputFormat};
+use database::migrations::{
+    latest_version, pending_migrations, schema_version, Migration, MIGRATIONS,
+};
+use serde::Serialize;
+
+use crate::{
+    errors::KryptaResult,
+    utils::{
+        database::open_unmigrated_database,
+        output::{render, Output},
+    },
+};
+
+/// A migration of the schema, and whether the database has it
+#[derive(Debug, Serialize)]
+pub struct MigrationState {
+    pub version: u32,
+    pub name: &'static str,
+    pub applied: bool,
+}
+
+impl MigrationState {
+    fn new(migration: &Migration, version: u32) -> Self {
+        MigrationState {
+            version: migration.version,
+            name: migration.name,
+            applied: migration.version <= version,
+        }
+    }
+}
+
+/// Every migration, and the version of the database
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub version: u32,
+    pub latest_version: u32,
+    pub migrations: Vec<MigrationState>,
+}
+
+impl Output for MigrationStatus {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "Schema version: {} (latest: {})",
+            self.version, self.latest_version
+        )?;
+
+        for migration in &self.migrations {
+            let state = if migration.applied {
+                "applied"
+            } else {
+                "pending"
+            };
+
+            writeln!(
+                out,
+                "{:>4} {:<20} {state}",
+                migration.version, migration.name
+            )?;
+        }
+
+        let pending = self.migrations.iter().filter(|m| !m.applied).count();
+
+        if pending > 0 {
+            writeln!(out, "Run `krypta db migrate` to apply {pending} migrations")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The migrations that `krypta db migrate` has applied
+#[derive(Debug, Serialize)]
+pub struct MigrateOutput {
+    pub applied: Vec<MigrationState>,
+    /// Where the old database has been copied, if anything was applied
+    pub backup: Option<String>,
+    pub version: u32,
+}
+
+impl Output for MigrateOutput {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        for migration in &self.applied {
+            writeln!(out, "Applied {} {}", migration.version, migration.name)?;
+        }
+
+        match &self.backup {
+            Some(backup) => writeln!(out, "The old database is in {backup}"),
+            None => writeln!(out, "Database is up to date (version {})", self.version),
+        }
+    }
+}
+
+pub async fn db(command: DbCommand, format: OutputFormat) -> KryptaResult<()> {
+    match command {
+        DbCommand::Migrate { status: true } => render(&status()?, format),
+        DbCommand::Migrate { status: false } => render(&migrate()?, format),
+    }
+}
+
+/// List every migration and whether it has been applied, without touching the database
+fn status() -> KryptaResult<MigrationStatus> {
+    let (database, _) = open_unmigrated_database()?;
+
+    let version = schema_version(&database)?;
+    // Fails on databases newer than this krypta
+    pending_migrations(&database)?;
+
+    Ok(MigrationStatus {
+        version,
+        latest_version: latest_version(),
+        migrations: MIGRATIONS
+            .iter()
+            .map(|migration| MigrationState::new(migration, version))
+            .collect(),
+    })
+}
+
+/// Apply the pending migrations
+fn migrate() -> KryptaResult<MigrateOutput> {
+    let (database, keyring) = open_unmigrated_database()?;
+    let migrated = database.migrate(&keyring)?;
+
+    database.close()?;
+
+    Ok(MigrateOutput {
+        applied: migrated
+            .applied
+            .iter()
+            .map(|migration| MigrationState::new(migration, migration.version))
+            .collect(),
+        backup: migrated
+            .backup
+            .map(|backup| backup.to_string_lossy().into_owned()),
+        version: latest_version(),
+    })
+}
@@ -1,8 +1,10 @@
 use database::{models, traits::FetchAll, Database};
 use fs::PathTree;
 
-pub async fn debug(db: &mut Database) {
-    let files = models::File::fetch_all(db).unwrap();
+use crate::errors::KryptaResult;
+
+pub async fn debug(db: &mut Database) -> KryptaResult<()> {
+    let files = models::File::fetch_all(db)?;
     let tree: PathTree = files.into_iter().collect();
 
     let ciao = tree.directory_structure();
@@ -12,4 +14,6 @@ pub async fn debug(db: &mut Database) {
     }
 
     //vfs::KryptaFS::mount("/home/giovanni/krypta/fuse-mount");
+
+    Ok(())
 }
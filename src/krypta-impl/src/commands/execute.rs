@@ -1,31 +1,119 @@
-use cli::{CliCommand, Parser};
-use database::Database;
+use cli::{Cli, CliCommand, OutputFormat, ProgressMode};
 
 #[cfg(debug_assertions)]
 use super::prune;
 
-use super::{add, check, config, debug, find, list, status, tree};
-
-/// Parse and execute command, if valid
-pub async fn execute_command(database: &mut Database) -> anyhow::Result<()> {
-    match cli::Cli::parse().command {
-        CliCommand::Config { key, value } => config::config(key, value).await,
-        CliCommand::Status => status::status(database).await,
-        CliCommand::Find { query } => find::find(database, query).await,
-        CliCommand::Tree => tree::tree(database).await,
-        CliCommand::List => list::list(database).await,
-        CliCommand::Debug => debug::debug(database).await,
-        CliCommand::Add {
-            target_path: source_path,
-            prefix,
-        } => add::add(database, source_path, prefix).await,
-        CliCommand::Check => check::check(database).await,
-
-        #[cfg(debug_assertions)]
-        CliCommand::Prune => prune::prune(database).await,
-    };
-
-    Ok(())
+use super::{
+    add, agent, cat, check, config, db, debug, extract, find, init, list, lock, put, recipients,
+    recover, recovery_key, rekey, shares, status, tree,
+};
+use crate::{
+    errors::KryptaResult,
+    utils::{database::open_database, output::render},
+};
+
+/// Execute the parsed command and print its result in the chosen format
+pub async fn execute_command(cli: Cli) -> KryptaResult<()> {
+    let format = cli.format;
+
+    match cli.command {
+        CliCommand::Config { key, value } => render(&config::config(key, value).await?, format),
+        CliCommand::Init => init::init().await,
+        CliCommand::Recover { mnemonic: true, .. } => recover::recover_with_mnemonic().await,
+        CliCommand::Recover { shares: true, .. } => recover::recover_with_shares().await,
+        CliCommand::Recover { .. } => unreachable!("clap requires a recovery method"),
+        CliCommand::RecoveryKey => recovery_key::recovery_key().await,
+        CliCommand::Shares { command } => shares::shares(command).await,
+        CliCommand::Recipients { command } => recipients::recipients(command, format).await,
+        CliCommand::Agent { timeout } => agent::agent(timeout).await,
+        CliCommand::Lock => render(&lock::lock().await?, format),
+        CliCommand::Db { command } => db::db(command, format).await,
+        command => execute_database_command(command, cli.progress, format).await,
+    }
+}
+
+/// Execute a command that needs the database, which is decrypted first and encrypted back
+/// at the end, even when the command fails so that its completed work is kept
+async fn execute_database_command(
+    command: CliCommand,
+    progress: ProgressMode,
+    format: OutputFormat,
+) -> KryptaResult<()> {
+    let (mut database, keyring) = open_database()?;
+
+    // `?` only leaves the block, so that the database is closed whatever the command returns
+    let result = async {
+        match command {
+            CliCommand::Status => render(&status::status(&database).await?, format),
+            CliCommand::Find {
+                query,
+                modified_after,
+                modified_before,
+            } => render(
+                &find::find(&mut database, query, modified_after, modified_before).await?,
+                format,
+            ),
+            CliCommand::Tree => render(&tree::tree(&database).await?, format),
+            CliCommand::List => render(&list::list(&mut database).await?, format),
+            CliCommand::Debug => debug::debug(&mut database).await,
+            CliCommand::Add {
+                target_paths,
+                prefix,
+                include,
+                exclude,
+                allow_partial,
+                io_concurrency,
+                cpu_concurrency,
+            } => {
+                let options = add::AddOptions {
+                    include,
+                    exclude,
+                    allow_partial,
+                    io_concurrency,
+                    cpu_concurrency,
+                };
+
+                let report =
+                    add::add(&mut database, &keyring, target_paths, prefix, options).await?;
+                render(&report, format)
+            }
+            CliCommand::Extract {
+                destination,
+                prefix,
+                rename_collisions,
+            } => {
+                let report = extract::extract(
+                    &mut database,
+                    &keyring,
+                    destination,
+                    prefix,
+                    rename_collisions,
+                    progress,
+                )
+                .await?;
+                render(&report, format)
+            }
+            CliCommand::Put { path } => {
+                render(&put::put(&mut database, &keyring, path).await?, format)
+            }
+            CliCommand::Cat { path } => cat::cat(&mut database, &keyring, path).await,
+            CliCommand::Check => render(&check::check(&mut database).await?, format),
+            CliCommand::Rekey { prefix, master } => {
+                let report = rekey::rekey(&mut database, keyring, prefix, master, progress).await?;
+                render(&report, format)
+            }
+
+            #[cfg(debug_assertions)]
+            CliCommand::Prune => prune::prune(&mut database).await,
+
+            _ => unreachable!("not a database command"),
+        }
+    }
+    .await;
+
+    database.close()?;
+
+    result
 }
 
 //#[cfg(test)]
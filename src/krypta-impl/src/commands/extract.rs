@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir, create_dir_all, symlink_metadata},
+    io,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+use cli::ProgressMode;
+use crypto::{crypt::FileDecryptBulk, traits::ComputeBulk, types::Report};
+use database::{
+    models::{EntryKind, File},
+    traits::FetchAll,
+    Database,
+};
+use fs::{check_vault_path, errors::FsError, find_collisions};
+
+use crate::{
+    errors::{AtPath, KryptaResult},
+    utils::{
+        agent::Keyring,
+        config::Config,
+        output::ReportOutput,
+        progress::{cancel_on_ctrl_c, progress_sink},
+    },
+};
+
+/// Decrypt the files under `prefix`, or every file, into `destination` and restore the
+/// attributes of the source files. Paths that collide on case-insensitive filesystems are
+/// renamed when `rename_collisions` is set, otherwise they are only reported. Ctrl-C stops
+/// decrypting more files, the ones already extracted are kept.
+///
+/// Nothing is written through a symlink: symlinks are created once everything else is, and
+/// paths whose parent is a symlink fail
+pub async fn extract(
+    db: &mut Database,
+    master_key: &Keyring,
+    destination: PathBuf,
+    prefix: Option<PathBuf>,
+    rename_collisions: bool,
+    progress: ProgressMode,
+) -> KryptaResult<ReportOutput> {
+    let locked_path = Config::get_locked_path()?;
+    let read_mode = Config::get_read_mode()?;
+
+    let files = File::fetch_all(db)?
+        .into_iter()
+        .filter(|file| match &prefix {
+            Some(prefix) => PathBuf::from(file).starts_with(prefix),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+
+    if files.is_empty() {
+        return Ok(ReportOutput {
+            action: "extracted",
+            report: Report::default(),
+        });
+    }
+
+    let mut renamed = HashMap::new();
+
+    for collision in find_collisions(files.iter().map(PathBuf::from)) {
+        if rename_collisions {
+            eprintln!(
+                "Renaming {} to {}, it collides with {} on case-insensitive filesystems",
+                collision.path.display(),
+                collision.renamed.display(),
+                collision.collides_with.display()
+            );
+            renamed.insert(collision.path, collision.renamed);
+        } else {
+            eprintln!(
+                "Warning: {} collides with {} on case-insensitive filesystems, \
+                 use --rename-collisions to rename it",
+                collision.path.display(),
+                collision.collides_with.display()
+            );
+        }
+    }
+
+    create_dir_all(&destination).at_path(&destination)?;
+
+    let mut attributes = HashMap::new();
+    let mut sizes = HashMap::new();
+    let mut decryptors = vec![];
+    let mut symlinks = vec![];
+    let mut results = vec![];
+
+    for file in files {
+        let path = PathBuf::from(&file);
+        let vault_path = renamed.get(&path).unwrap_or(&path).clone();
+
+        let unlocked_path = match unlocked_path(&destination, &vault_path) {
+            Ok(unlocked_path) => unlocked_path,
+            Err(error) => {
+                results.push((path, Err(error.to_string())));
+                continue;
+            }
+        };
+
+        // Dangling symlinks do not exist according to `Path::exists`
+        if symlink_metadata(&unlocked_path).is_ok() {
+            eprintln!("Skipping {}, it already exists", unlocked_path.display());
+            continue;
+        }
+
+        attributes.insert(unlocked_path.clone(), file.attributes());
+        sizes.insert(unlocked_path.clone(), file.size);
+
+        if matches!(file.kind, EntryKind::Symlink) {
+            symlinks.push((vault_path, unlocked_path, file));
+            continue;
+        }
+
+        if let Err(error) = create_dir_inside(&destination, vault_path.parent().unwrap()) {
+            results.push((unlocked_path, Err(error.to_string())));
+            continue;
+        }
+
+        match file.kind {
+            EntryKind::File => {
+                match file.try_into_decryptor(&locked_path, &unlocked_path, master_key) {
+                    Ok(decryptor) => decryptors.push(decryptor.read_mode(read_mode)),
+                    Err(error) => results.push((unlocked_path, Err(error.to_string()))),
+                }
+            }
+            EntryKind::Directory => {
+                let result =
+                    create_dir_inside(&destination, &vault_path).map_err(|error| error.to_string());
+                results.push((unlocked_path, result));
+            }
+            EntryKind::Symlink => unreachable!("symlinks are created last"),
+        }
+    }
+
+    let report = FileDecryptBulk::new(decryptors)
+        .start_all_with(&*progress_sink(progress), &cancel_on_ctrl_c()?);
+    results.extend(
+        report
+            .outputs
+            .into_iter()
+            .map(|(unlocked_path, result)| (unlocked_path, result.map_err(|e| e.to_string()))),
+    );
+
+    // Created last, so that no file is written through them
+    for (vault_path, unlocked_path, file) in symlinks {
+        let result = create_dir_inside(&destination, vault_path.parent().unwrap())
+            .map_err(|error| error.to_string())
+            .and_then(|_| {
+                file.symlink_target(master_key)
+                    .map_err(|error| error.to_string())
+            })
+            .and_then(|target| {
+                symlink(target.unwrap(), &unlocked_path).map_err(|error| error.to_string())
+            });
+        results.push((unlocked_path, result));
+    }
+
+    let mut extract_report = Report {
+        cancelled_count: report.cancelled.len(),
+        ..Default::default()
+    };
+
+    for (unlocked_path, result) in &results {
+        let result = result.clone().and_then(|_| {
+            // Writing the contents has changed the times, so restore them afterwards
+            attributes
+                .get(unlocked_path)
+                .map_or(Ok(()), |attributes| attributes.apply(unlocked_path))
+                .map_err(|error| error.to_string())
+        });
+
+        match result {
+            Ok(()) => extract_report.processed(sizes.get(unlocked_path).copied().unwrap_or(0)),
+            Err(error) => extract_report.failed(unlocked_path, error),
+        }
+    }
+
+    Ok(ReportOutput {
+        action: "extracted",
+        report: extract_report,
+    })
+}
+
+/// Where `path` from the vault goes in `destination`, refusing the paths that would end up
+/// outside of it
+fn unlocked_path(destination: &Path, path: &Path) -> Result<PathBuf, FsError> {
+    check_vault_path(path)?;
+
+    Ok(destination.join(path))
+}
+
+/// Create `relative` and its missing parents in `destination`, like `create_dir_all`, but
+/// refusing to go through symlinks which could lead outside of `destination`
+fn create_dir_inside(destination: &Path, relative: &Path) -> io::Result<()> {
+    let mut current = destination.to_path_buf();
+
+    for component in relative.components() {
+        current.push(component);
+
+        match symlink_metadata(&current) {
+            Ok(metadata) if metadata.is_dir() => (),
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(io::Error::other(format!(
+                    "{} is a symlink",
+                    current.display()
+                )));
+            }
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} is not a directory", current.display()),
+                ));
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => create_dir(&current)?,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::create_dir, os::unix::fs::symlink, path::Path};
+
+    use tmp::Tmp;
+
+    use super::{create_dir_inside, unlocked_path};
+
+    #[test]
+    fn test_unlocked_path() {
+        let destination = Path::new("/tmp/extracted");
+
+        assert_eq!(
+            unlocked_path(destination, Path::new("docs/a.txt")).unwrap(),
+            destination.join("docs/a.txt")
+        );
+
+        for path in ["/abs", "../x", "docs/../../x"] {
+            assert!(unlocked_path(destination, Path::new(path)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_create_dir_inside() {
+        let tmp = Tmp::random();
+        let destination = tmp.base_path().join("extracted");
+        let outside = tmp.base_path().join("outside");
+        create_dir(&destination).unwrap();
+        create_dir(&outside).unwrap();
+
+        create_dir_inside(&destination, Path::new("a/b")).unwrap();
+        assert!(destination.join("a/b").is_dir());
+
+        // Like a symlink extracted before the files under it
+        symlink(&outside, destination.join("link")).unwrap();
+
+        assert!(create_dir_inside(&destination, Path::new("link/c")).is_err());
+        assert!(!outside.join("c").exists());
+    }
+}
@@ -1,33 +1,76 @@
 use std::{
-    io::{stdout, BufWriter, Write},
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
     path::PathBuf,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use database::{
-    models,
-    traits::{Count, Search},
-    Database,
-};
+use chrono::{DateTime, NaiveDate, Utc};
+use database::{models, traits::Count, Database};
 use fs::PathTree;
+use serde::Serialize;
+
+use crate::{
+    errors::{KryptaError, KryptaResult},
+    utils::output::{lossy_paths, Output},
+};
+
+/// The paths matching a query, in order
+#[derive(Debug, Serialize)]
+pub struct FindResults {
+    #[serde(serialize_with = "lossy_paths")]
+    pub paths: Vec<PathBuf>,
+    /// How many files have been searched
+    pub files_count: i64,
+    #[serde(skip)]
+    pub took: Duration,
+}
+
+impl Output for FindResults {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        // Paths are written as they are, even if they are not valid UTF-8
+        for path in &self.paths {
+            out.write_all(path.as_os_str().as_bytes())?;
+            out.write_all(b"\n")?;
+        }
 
-pub async fn find(db: &mut Database, query: String) {
+        writeln!(
+            out,
+            "Took {:?} for finding {} files",
+            self.took, self.files_count
+        )
+    }
+}
+
+pub async fn find(
+    db: &mut Database,
+    query: String,
+    modified_after: Option<String>,
+    modified_before: Option<String>,
+) -> KryptaResult<FindResults> {
     let start = Instant::now();
 
-    let query_result = models::File::search(db, query).unwrap();
+    let modified_after = modified_after.map(|date| parse_date(&date)).transpose()?;
+    let modified_before = modified_before.map(|date| parse_date(&date)).transpose()?;
+
+    let query_result = models::File::search_modified(db, query, modified_after, modified_before)?;
 
     let paths_tree: PathTree = query_result.iter().map(PathBuf::from).collect();
-    let paths_ordered = paths_tree.paths_ordered();
 
-    let mut stdout = BufWriter::new(stdout());
+    Ok(FindResults {
+        paths: paths_tree.paths_ordered(),
+        files_count: models::File::count(db)?,
+        took: start.elapsed(),
+    })
+}
 
-    for path in paths_ordered {
-        let line = format!("{}\n", path.to_string_lossy());
-        stdout.write_all(line.as_bytes()).unwrap();
+/// Parse a date as `2022-08-01`, meaning midnight UTC, or as RFC 3339
+fn parse_date(date: &str) -> KryptaResult<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
     }
 
-    stdout.flush().unwrap();
-
-    let files_count = models::File::count(db).unwrap();
-    println!("Took {:?} for finding {files_count} files", start.elapsed());
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|error| KryptaError::InvalidInput(format!("Invalid date {date:?}: {error}")))
 }
@@ -0,0 +1,19 @@
+use database::vault_file;
+use utils::ask_new_passphrase;
+use vault::Vault;
+
+use crate::{
+    errors::{KryptaError, KryptaResult},
+    utils::vault::print_recovery_key,
+};
+
+pub async fn init() -> KryptaResult<()> {
+    println!("Creating a new vault, choose a passphrase");
+    let passphrase = ask_new_passphrase().map_err(KryptaError::Prompt)?;
+
+    let (_, _, recovery_key) = Vault::create(vault_file()?, passphrase)?;
+
+    print_recovery_key(&recovery_key.to_mnemonic());
+
+    Ok(())
+}
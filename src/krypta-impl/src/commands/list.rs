@@ -1,29 +1,68 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::PathBuf,
+};
 
 use database::{models, traits::FetchAll, Database};
 use fs::PathTree;
+use serde::Serialize;
 
-pub async fn list(db: &mut Database) {
-    let files: HashMap<PathBuf, models::File> = models::File::fetch_all(db)
-        .unwrap()
+use crate::{
+    errors::KryptaResult,
+    utils::output::{lossy_path, Output},
+};
+
+#[derive(Debug, Serialize)]
+pub struct ListEntry {
+    #[serde(serialize_with = "lossy_path")]
+    pub path: PathBuf,
+    pub tags: Vec<String>,
+}
+
+/// Every file with its tags
+#[derive(Debug, Serialize)]
+pub struct List {
+    pub files: Vec<ListEntry>,
+}
+
+impl Output for List {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        for file in &self.files {
+            let tags_pretty: String = if file.tags.is_empty() {
+                "(no tags)".to_string()
+            } else {
+                file.tags.iter().map(|tag| format!("{tag} ")).collect()
+            };
+
+            writeln!(out, "{} -> {}", file.path.to_string_lossy(), tags_pretty)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn list(db: &mut Database) -> KryptaResult<List> {
+    let files: HashMap<PathBuf, models::File> = models::File::fetch_all(db)?
         .into_iter()
         .map(|file| (PathBuf::from(&file), file))
         .collect();
 
     let paths_tree: PathTree = files.keys().map(|path| path.to_owned()).collect();
-    let paths_ordered = paths_tree.paths_ordered();
 
-    for path in paths_ordered {
-        let tags = files.get(&path).unwrap().tags(db).unwrap();
+    let files = paths_tree
+        .paths_ordered()
+        .into_iter()
+        .map(|path| {
+            let tags = files[&path]
+                .tags(db)?
+                .into_iter()
+                .map(|tag| tag.name)
+                .collect();
 
-        let tags_pretty: String = if tags.is_empty() {
-            "(no tags)".to_string()
-        } else {
-            tags.into_iter()
-                .map(|tag| format!("{} ", tag.name))
-                .collect()
-        };
+            Ok(ListEntry { path, tags })
+        })
+        .collect::<KryptaResult<_>>()?;
 
-        println!("{} -> {}", path.to_string_lossy(), tags_pretty);
-    }
+    Ok(List { files })
 }
@@ -0,0 +1,15 @@
+use crate::{
+    errors::KryptaResult,
+    utils::{agent::AgentClient, output::Message},
+};
+
+/// Make the running agent wipe the master key and exit
+pub async fn lock() -> KryptaResult<Message> {
+    match AgentClient::connect() {
+        Some(client) => {
+            client.lock()?;
+            Ok(Message::new("Agent locked"))
+        }
+        None => Ok(Message::new("No agent is running")),
+    }
+}
@@ -1,10 +1,22 @@
 mod add;
+mod agent;
+mod cat;
 mod check;
 mod config;
+mod db;
 mod debug;
 mod execute;
+mod extract;
 mod find;
+mod init;
 mod list;
+mod lock;
+mod put;
+mod recipients;
+mod recover;
+mod recovery_key;
+mod rekey;
+mod shares;
 mod status;
 mod tree;
 
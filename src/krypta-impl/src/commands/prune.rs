@@ -1,20 +1,28 @@
 use std::{collections::HashSet, fs::remove_file, path::PathBuf};
 
-use database::{connect_or_create, database_file, models, traits::FetchAll, Database};
+use database::{models, recreate_schema, traits::FetchAll, Database};
 use utils::ask_yes_or_no;
 
-use crate::utils::config::Config;
+use crate::{
+    errors::{KryptaError, KryptaResult},
+    utils::config::Config,
+};
 
-pub async fn prune(db: &mut Database) {
-    ask_yes_or_no("Are you sure you want to remove everything? This action is irreversible!");
+pub async fn prune(db: &mut Database) -> KryptaResult<()> {
+    let confirmed =
+        ask_yes_or_no("Are you sure you want to remove everything? This action is irreversible!")
+            .map_err(KryptaError::Prompt)?;
 
-    let db_files = models::File::fetch_all(db)
-        .unwrap()
+    if !confirmed {
+        return Err(KryptaError::Declined);
+    }
+
+    let db_files = models::File::fetch_all(db)?
         .into_iter()
         .map(|file| PathBuf::from(file.locked_hash))
         .collect::<HashSet<_>>(); // HashSet in order to remove duplicates
 
-    let locked_path = Config::get_locked_path();
+    let locked_path = Config::get_locked_path()?;
 
     println!("deleting {} files...", db_files.len());
 
@@ -29,9 +37,9 @@ pub async fn prune(db: &mut Database) {
     }
 
     println!("recreating database...");
-    remove_file(database_file()).unwrap();
-
-    let _ = connect_or_create().unwrap();
+    recreate_schema(db)?;
 
     println!("all done");
+
+    Ok(())
 }
@@ -0,0 +1,92 @@
+use std::{
+    fs::{remove_file, rename, File},
+    io::{self, stdin, BufWriter, Write},
+    path::PathBuf,
+};
+
+use byte_unit::Byte;
+use crypto::crypt::{encrypt_stream, AEAD_NONCE_SIZE};
+use database::{models, traits::Insert, Database};
+use fs::{check_vault_path, encode_path, normalize_path};
+use serde::Serialize;
+
+use crate::{
+    errors::{AtPath, KryptaError, KryptaResult},
+    utils::{
+        agent::Keyring,
+        config::Config,
+        output::{lossy_path, Output},
+    },
+};
+
+/// The file that `put` has added
+#[derive(Debug, Serialize)]
+pub struct PutOutput {
+    #[serde(serialize_with = "lossy_path")]
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl Output for PutOutput {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "Added {} ({})",
+            self.path.display(),
+            Byte::from_bytes(self.size.into()).get_appropriate_unit(false)
+        )
+    }
+}
+
+/// Encrypt everything from stdin into `path` in the vault, without temporary plaintext files
+pub async fn put(
+    db: &mut Database,
+    master_key: &Keyring,
+    path: PathBuf,
+) -> KryptaResult<PutOutput> {
+    let locked_path = Config::get_locked_path()?;
+    let path = normalize_path(path);
+    check_vault_path(&path)?;
+
+    if models::File::find_by_path(db, &path)?.is_some() {
+        return Err(KryptaError::AlreadyExists(path));
+    }
+
+    let mut file = models::File::new(
+        encode_path(&path),
+        path.clone(),
+        String::new(),
+        0,
+        master_key,
+    )?;
+
+    let key = file.unwrap_key(master_key)?;
+    // Should never fail as nonce len is constant
+    let nonce: [u8; AEAD_NONCE_SIZE] = file.nonce.clone().try_into().unwrap();
+
+    // The locked name depends on the contents hash, which is only known at the end
+    let partial_path = locked_path.join(format!(".{}.partial", file.locked_hash));
+    let partial_file = File::create(&partial_path).at_path(&partial_path)?;
+
+    let summary = match encrypt_stream(
+        stdin().lock(),
+        BufWriter::new(partial_file),
+        &key,
+        &nonce.into(),
+    ) {
+        Ok(summary) => summary,
+        Err(error) => {
+            let _ = remove_file(&partial_path);
+            return Err(error).at_path(path);
+        }
+    };
+
+    file.set_contents(summary.hash.to_string(), summary.size);
+    rename(&partial_path, locked_path.join(&file.locked_hash)).at_path(&partial_path)?;
+    file.insert(db)?;
+
+    Ok(PutOutput {
+        path,
+        size: summary.size,
+    })
+}
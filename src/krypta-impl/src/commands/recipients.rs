@@ -0,0 +1,154 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+};
+
+use cli::{OutputFormat, RecipientsCommand};
+use crypto::crypt::{Identity, Recipient};
+use database::vault_file;
+use serde::Serialize;
+use vault::Vault;
+
+use crate::{
+    errors::{AtPath, KryptaError, KryptaResult},
+    utils::{
+        output::{render, Message, Output},
+        vault::unlock_vault,
+    },
+};
+
+/// The recipients that can unlock the vault
+#[derive(Debug, Serialize)]
+pub struct Recipients {
+    pub recipients: Vec<String>,
+}
+
+impl Output for Recipients {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        for recipient in &self.recipients {
+            writeln!(out, "{recipient}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The public key of a new identity
+#[derive(Debug, Serialize)]
+pub struct PublicKey {
+    pub public_key: String,
+}
+
+impl Output for PublicKey {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Public key: {}", self.public_key)
+    }
+}
+
+pub async fn recipients(command: RecipientsCommand, format: OutputFormat) -> KryptaResult<()> {
+    match command {
+        RecipientsCommand::Add { recipient } => {
+            render(&add(parse_recipient(recipient)?).await?, format)
+        }
+        RecipientsCommand::Rm { recipient } => {
+            render(&rm(parse_recipient(recipient)?).await?, format)
+        }
+        RecipientsCommand::Ls => render(&ls().await?, format),
+        RecipientsCommand::Keygen { output } => render(&keygen(output).await?, format),
+    }
+}
+
+fn parse_recipient(recipient: String) -> KryptaResult<Recipient> {
+    recipient
+        .parse()
+        .map_err(|error| KryptaError::InvalidInput(format!("{recipient}: {error}")))
+}
+
+/// Wrap the master key for a new recipient
+async fn add(recipient: Recipient) -> KryptaResult<Message> {
+    let (mut vault, master_key) = unlock_vault()?;
+
+    vault.add_recipient(&master_key, &recipient)?;
+
+    Ok(Message::new(format!(
+        "{recipient} can now unlock the vault"
+    )))
+}
+
+/// Remove a recipient from the vault
+async fn rm(recipient: Recipient) -> KryptaResult<Message> {
+    let mut vault = Vault::open(vault_file()?)?;
+
+    vault.remove_recipient(&recipient)?;
+
+    Ok(Message::new(format!(
+        "{recipient} has been removed, keys that were already unwrapped are not revoked"
+    )))
+}
+
+/// List the recipients of the vault
+async fn ls() -> KryptaResult<Recipients> {
+    let vault = Vault::open(vault_file()?)?;
+
+    Ok(Recipients {
+        recipients: vault
+            .recipients()?
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+/// Write a new identity to `output`, readable only by the current user
+async fn keygen(output: PathBuf) -> KryptaResult<PublicKey> {
+    let identity = Identity::generate();
+
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&output)
+    {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+            return Err(KryptaError::AlreadyExists(output));
+        }
+        Err(error) => return Err(error).at_path(&output),
+    };
+
+    file.write_all(identity.to_identity_file().as_bytes())
+        .at_path(&output)?;
+
+    Ok(PublicKey {
+        public_key: identity.to_recipient().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::metadata, os::unix::fs::PermissionsExt};
+
+    use tmp::Tmp;
+
+    use super::keygen;
+    use crate::errors::KryptaError;
+
+    #[tokio::test]
+    async fn test_keygen_is_private() {
+        let tmp = Tmp::random();
+        let output = tmp.base_path().join("identity.txt");
+
+        keygen(output.clone()).await.unwrap();
+        assert_eq!(
+            metadata(&output).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        assert!(matches!(
+            keygen(output).await,
+            Err(KryptaError::AlreadyExists(_))
+        ));
+    }
+}
@@ -0,0 +1,44 @@
+use crypto::crypt::RecoveryKey;
+use database::vault_file;
+use utils::{ask_line, ask_new_passphrase};
+use vault::{MasterKey, Vault};
+
+use crate::{
+    errors::{KryptaError, KryptaResult},
+    utils::vault::ask_shares,
+};
+
+/// Regain access to the vault with the recovery key and set a new passphrase
+pub async fn recover_with_mnemonic() -> KryptaResult<()> {
+    let vault = Vault::open(vault_file()?)?;
+
+    let words = ask_line("Recovery key words:").map_err(KryptaError::Prompt)?;
+    let recovery_key = RecoveryKey::from_mnemonic(words)?;
+
+    let master_key = vault.unlock_with_recovery_key(&recovery_key)?;
+
+    set_new_passphrase(vault, &master_key)
+}
+
+/// Regain access to the vault with the key shares and set a new passphrase
+pub async fn recover_with_shares() -> KryptaResult<()> {
+    let vault = Vault::open(vault_file()?)?;
+
+    let threshold = vault.shares_threshold()?;
+    let shares = ask_shares(threshold)?;
+
+    let master_key = vault.unlock_with_shares(&shares)?;
+
+    set_new_passphrase(vault, &master_key)
+}
+
+fn set_new_passphrase(mut vault: Vault, master_key: &MasterKey) -> KryptaResult<()> {
+    println!("Vault unlocked, choose a new passphrase");
+    let passphrase = ask_new_passphrase().map_err(KryptaError::Prompt)?;
+
+    vault.set_passphrase(master_key, passphrase)?;
+
+    println!("Passphrase updated.");
+
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use crate::{
+    errors::KryptaResult,
+    utils::vault::{print_recovery_key, unlock_vault},
+};
+
+/// Generate a new recovery key, invalidating the old one
+pub async fn recovery_key() -> KryptaResult<()> {
+    let (mut vault, master_key) = unlock_vault()?;
+
+    let recovery_key = vault.rotate_recovery_key(&master_key)?;
+
+    print_recovery_key(&recovery_key.to_mnemonic());
+
+    Ok(())
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    fs::remove_file,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use cli::ProgressMode;
+use crypto::{
+    crypt::FileReencryptBulk,
+    errors::CryptoError,
+    progress::{CancellationToken, ProgressSink},
+    traits::ComputeBulk,
+    types::Report,
+};
+use database::{
+    errors::DatabaseError,
+    models::{EntryKind, File, PendingRekey},
+    traits::{FetchAll, InsertMany, Update},
+    vault_file, Database, EncryptedDatabase,
+};
+use utils::{ask_new_passphrase, ask_yes_or_no};
+use vault::{MasterKey, Vault};
+
+use crate::{
+    errors::{KryptaError, KryptaResult},
+    utils::{
+        agent::{AgentClient, Keyring},
+        config::Config,
+        output::ReportOutput,
+        progress::{cancel_on_ctrl_c, progress_sink},
+        vault::{print_recovery_key, unlock_vault},
+    },
+};
+
+/// How many files are re-encrypted between two saves of the catalog
+const BATCH_SIZE: usize = 256;
+
+/// Rotate the per-file keys of the files in `prefix`, or of every file. With `rotate_master`
+/// the master key is rotated as well, which implies rotating every per-file key. Ctrl-C
+/// stops starting more files, the others are left to a later `krypta rekey`
+pub async fn rekey(
+    db: &mut EncryptedDatabase,
+    keyring: Keyring,
+    prefix: Option<PathBuf>,
+    rotate_master: bool,
+    progress: ProgressMode,
+) -> KryptaResult<ReportOutput> {
+    let locked_path = Config::get_locked_path()?;
+
+    // The agent never hands out the master key
+    let (mut vault, mut master_key) = match keyring {
+        Keyring::Local(master_key) => (Vault::open(vault_file()?)?, master_key),
+        Keyring::Agent(_) => unlock_vault()?,
+    };
+
+    let mut pending = PendingRekey::fetch_all(db)?;
+
+    if rotate_master && vault.previous_master_key(&master_key)?.is_none() {
+        if !pending.is_empty() {
+            return Err(KryptaError::RekeyInProgress);
+        }
+
+        let had_shares = vault.shares_threshold().is_ok();
+
+        let passphrase = if vault.has_passphrase() {
+            eprintln!("Rotating the master key, choose a new passphrase");
+            Some(ask_new_passphrase().map_err(KryptaError::Prompt)?)
+        } else {
+            None
+        };
+
+        master_key =
+            vault.rotate_master_key(&master_key, passphrase.as_deref(), |recovery_key| {
+                print_recovery_key(&recovery_key.to_mnemonic())
+            })?;
+
+        // It holds the old master key, which stops unwrapping anything once the per-file keys
+        // are rotated
+        if let Some(agent) = AgentClient::connect() {
+            agent.lock()?;
+            eprintln!(
+                "The agent held the old master key and has been locked, run `krypta agent` again"
+            );
+        }
+
+        if had_shares {
+            eprintln!("Key shares are not valid anymore, run `krypta shares split` again");
+        }
+    }
+
+    let previous_master_key = vault.previous_master_key(&master_key)?;
+
+    if previous_master_key.is_some() {
+        db.rewrap_key(&master_key)?;
+        db.persist()?;
+    }
+
+    if pending.is_empty() {
+        let files = File::fetch_all(db)?
+            .into_iter()
+            .filter(|file| match (&previous_master_key, &prefix) {
+                // Every key is wrapped with the old master key, so rotate them all
+                (Some(_), _) => true,
+                (None, Some(prefix)) => PathBuf::from(file).starts_with(prefix),
+                (None, None) => true,
+            })
+            .collect::<Vec<_>>();
+
+        if files.is_empty() {
+            return Ok(ReportOutput {
+                action: "rekeyed",
+                report: Report::default(),
+            });
+        }
+
+        let confirmed = ask_yes_or_no(format!(
+            "You are rotating the keys of {} files. Are you sure?",
+            files.len()
+        ))
+        .map_err(KryptaError::Prompt)?;
+
+        if !confirmed {
+            return Err(KryptaError::Declined);
+        }
+
+        // Symlinks and empty directories have nothing in locked_path, rotate them right away
+        let (files, entries): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|file| file.kind == EntryKind::File);
+
+        let tx = db.transaction().map_err(DatabaseError::from)?;
+        for mut entry in entries {
+            let entry_master_key = match &previous_master_key {
+                Some(previous) if entry.unwrap_key(&master_key).is_err() => previous,
+                _ => &master_key,
+            };
+
+            entry.rekey_in_place(entry_master_key, &master_key)?;
+            entry.update(&tx)?;
+        }
+
+        pending = PendingRekey::insert_many(
+            &tx,
+            files
+                .iter()
+                .map(|file| PendingRekey::new(file, &master_key)),
+        )?;
+        tx.commit().map_err(DatabaseError::from)?;
+
+        db.persist()?;
+    } else {
+        if prefix.is_some() {
+            eprintln!("Ignoring prefix, an interrupted rekey is being resumed");
+        }
+
+        eprintln!("Resuming rekey of {} files", pending.len());
+    }
+
+    let mut files = File::fetch_all(db)?
+        .into_iter()
+        .map(|file| (file.id.unwrap(), file))
+        .collect::<HashMap<_, _>>();
+
+    // Files removed since the rekey started have nothing left to rekey, drop their new
+    // ciphertext if any
+    let mut pending_files = vec![];
+    for p in pending {
+        match files.remove(&p.file_id) {
+            Some(file) => pending_files.push((p, file)),
+            None => {
+                remove_locked_file(&locked_path.join(&p.locked_hash));
+                p.delete(db)?;
+            }
+        }
+    }
+
+    // Encrypt with the new keys into the new locked names
+    let mut errors = vec![];
+    let progress = progress_sink(progress);
+    let cancel = cancel_on_ctrl_c()?;
+
+    let to_encrypt = pending_files
+        .iter_mut()
+        .filter(|(p, file)| !p.encrypted && !p.is_swapped(file))
+        .collect::<Vec<_>>();
+
+    let mut to_encrypt = to_encrypt.into_iter().peekable();
+    while to_encrypt.peek().is_some() && !cancel.is_cancelled() {
+        let batch = to_encrypt.by_ref().take(BATCH_SIZE).collect::<Vec<_>>();
+
+        errors.extend(reencrypt_batch(
+            db,
+            batch,
+            &locked_path,
+            &master_key,
+            previous_master_key.as_ref(),
+            &*progress,
+            &cancel,
+        )?);
+
+        db.persist()?;
+    }
+
+    // Atomically swap the database rows
+    let tx = db.transaction().map_err(DatabaseError::from)?;
+    for (p, file) in pending_files
+        .iter()
+        .filter(|(p, file)| p.encrypted && !p.is_swapped(file))
+    {
+        p.swap(&tx, file.clone())?;
+    }
+    tx.commit().map_err(DatabaseError::from)?;
+
+    db.persist()?;
+
+    // Delete the old ciphertext, unless some other file still uses it
+    let mut report = Report::default();
+    let mut remaining: usize = 0;
+
+    for (p, file) in pending_files {
+        if !p.encrypted {
+            remaining += 1;
+            continue;
+        }
+
+        if File::count_locked_hash(db, &p.old_locked_hash)? == 0 {
+            remove_locked_file(&locked_path.join(&p.old_locked_hash));
+        }
+
+        report.processed(file.size);
+        p.delete(db)?;
+    }
+
+    for (path, error) in &errors {
+        report.failed(path, error);
+    }
+
+    if remaining == 0 {
+        if previous_master_key.is_some() {
+            vault.forget_previous_master_key()?;
+        }
+    } else {
+        eprintln!("{remaining} files could not be rekeyed, run `krypta rekey` again to retry");
+    }
+
+    if cancel.is_cancelled() {
+        report.cancelled_count = remaining.saturating_sub(errors.len());
+    }
+
+    Ok(ReportOutput {
+        action: "rekeyed",
+        report,
+    })
+}
+
+/// Remove a ciphertext which nothing uses anymore
+fn remove_locked_file(path: &Path) {
+    match remove_file(path) {
+        Ok(_) => (),
+        Err(error) if error.kind() == ErrorKind::NotFound => (),
+        Err(error) => eprintln!("cannot remove file {path:?}: {error}"),
+    }
+}
+
+/// Re-encrypt a batch of files with their new keys into their new locked names, checking
+/// that the plaintext is what has been added in the first place. The plaintext only goes
+/// through memory. Once `cancel` is cancelled no more files are started
+fn reencrypt_batch(
+    db: &Database,
+    batch: Vec<&mut (PendingRekey, File)>,
+    locked_path: &Path,
+    master_key: &MasterKey,
+    previous_master_key: Option<&MasterKey>,
+    progress: &dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> KryptaResult<Vec<(String, CryptoError)>> {
+    let read_mode = Config::get_read_mode()?;
+    let mut errors = vec![];
+
+    let mut reencryptors = vec![];
+    let mut started = vec![];
+    for entry in batch {
+        let (p, file) = &*entry;
+
+        // Keys which have not been rotated yet may still be wrapped with the old master key
+        let file_master_key = match previous_master_key {
+            Some(previous) if file.unwrap_key(master_key).is_err() => previous,
+            _ => master_key,
+        };
+
+        let reencryptor = p.unwrap_key(master_key).and_then(|new_key| {
+            file.clone().try_into_reencryptor(
+                locked_path,
+                &p.locked_hash,
+                (new_key, p.nonce()?.into()),
+                file_master_key,
+            )
+        });
+
+        match reencryptor {
+            Ok(reencryptor) => {
+                reencryptors.push(reencryptor.read_mode(read_mode));
+                started.push(entry);
+            }
+            Err(error) => errors.push((file.path.clone(), error)),
+        }
+    }
+
+    let mut reencrypted = FileReencryptBulk::new(reencryptors)
+        .start_all_with(progress, cancel)
+        .outputs;
+
+    let tx = db.unchecked_transaction().map_err(DatabaseError::from)?;
+    for (p, file) in started {
+        let new_locked_path = locked_path.join(&p.locked_hash);
+
+        match reencrypted.remove(&new_locked_path) {
+            Some(Ok(summary)) if summary.hash.to_string() == file.contents_hash => {
+                p.mark_encrypted(&tx)?
+            }
+            Some(Ok(_)) => {
+                remove_locked_file(&new_locked_path);
+                errors.push((
+                    file.path.clone(),
+                    CryptoError::InputOutput(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        "decrypted contents do not match the stored hash",
+                    )),
+                ));
+            }
+            Some(Err(error)) => errors.push((file.path.clone(), error)),
+            // Cancelled
+            None => (),
+        }
+    }
+    tx.commit().map_err(DatabaseError::from)?;
+
+    Ok(errors)
+}
@@ -0,0 +1,32 @@
+use cli::SharesCommand;
+
+use crate::{
+    errors::KryptaResult,
+    utils::vault::{print_shares, unlock_vault},
+};
+
+pub async fn shares(command: SharesCommand) -> KryptaResult<()> {
+    match command {
+        SharesCommand::Split {
+            threshold,
+            shares,
+            remove_passphrase,
+        } => split(threshold, shares, remove_passphrase).await,
+    }
+}
+
+/// Split the vault key into `count` shares, any `threshold` of which can unlock the vault
+async fn split(threshold: u8, count: u8, remove_passphrase: bool) -> KryptaResult<()> {
+    let (mut vault, master_key) = unlock_vault()?;
+
+    let shares = vault.split_into_shares(&master_key, threshold, count)?;
+
+    if remove_passphrase {
+        vault.remove_passphrase()?;
+        println!("Passphrase removed, the vault can now be unlocked only with {threshold} shares or the recovery key");
+    }
+
+    print_shares(&shares);
+
+    Ok(())
+}
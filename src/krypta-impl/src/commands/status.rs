@@ -1,12 +1,35 @@
+use std::io::{self, Write};
+
 use byte_unit::Byte;
 use database::{models, traits::Count, Database};
+use serde::Serialize;
+
+use crate::{errors::KryptaResult, utils::output::Output};
 
-pub async fn status(db: &Database) {
-    let archive_size_bytes = models::File::archive_size(db).unwrap();
-    let archive_size = Byte::from_bytes(archive_size_bytes.into());
+/// How much is in the vault
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub files: i64,
+    /// Plaintext size of every file
+    pub archive_size: u64,
+}
 
-    let archive_count = models::File::count(db).unwrap();
+impl Output for Status {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let archive_size = Byte::from_bytes(self.archive_size.into());
+
+        writeln!(out, "Files stored in database: {}", self.files)?;
+        writeln!(
+            out,
+            "Archive size: {}",
+            archive_size.get_appropriate_unit(false)
+        )
+    }
+}
 
-    println!("Files stored in database: {archive_count}");
-    println!("Archive size: {}", archive_size.get_appropriate_unit(false));
+pub async fn status(db: &Database) -> KryptaResult<Status> {
+    Ok(Status {
+        files: models::File::count(db)?,
+        archive_size: models::File::archive_size(db)?,
+    })
 }
@@ -1,11 +1,78 @@
+use std::{
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+};
+
 use database::{models, traits::FetchAll, Database};
 use fs::PathTree;
+use serde::Serialize;
 
-pub async fn tree(db: &Database) {
-    let files = models::File::fetch_all(db).unwrap();
-    let tree: PathTree = files.into_iter().collect();
+use crate::{
+    errors::KryptaResult,
+    utils::output::{lossy_path, Output},
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeEntryKind {
+    File,
+    Symlink,
+    Directory,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeEntry {
+    #[serde(serialize_with = "lossy_path")]
+    pub path: PathBuf,
+    pub kind: TreeEntryKind,
+}
+
+/// Every path in the vault, in order
+#[derive(Debug, Serialize)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+}
 
-    for path in tree.paths_ordered() {
-        println!("{}", path.to_string_lossy());
+impl Output for Tree {
+    /// Mark symlinks with `@` and empty directories with `/` like `ls -F`
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        for entry in &self.entries {
+            let suffix = match entry.kind {
+                TreeEntryKind::File => "",
+                TreeEntryKind::Symlink => "@",
+                TreeEntryKind::Directory => "/",
+            };
+
+            // Paths are written as they are, even if they are not valid UTF-8
+            out.write_all(entry.path.as_os_str().as_bytes())?;
+            writeln!(out, "{suffix}")?;
+        }
+
+        Ok(())
     }
 }
+
+/// Every path, with what it is
+pub async fn tree(db: &Database) -> KryptaResult<Tree> {
+    let files = models::File::fetch_all(db)?;
+    let tree: PathTree = files.into_iter().collect();
+
+    let entries = tree
+        .paths_ordered()
+        .into_iter()
+        .map(|path| {
+            let kind = if tree.is_symlink(&path) {
+                TreeEntryKind::Symlink
+            } else if tree.is_directory(&path) {
+                TreeEntryKind::Directory
+            } else {
+                TreeEntryKind::File
+            };
+
+            TreeEntry { path, kind }
+        })
+        .collect();
+
+    Ok(Tree { entries })
+}
@@ -0,0 +1,194 @@
+use std::{io, path::PathBuf};
+
+use crypto::errors::CryptoError;
+use database::errors::DatabaseError;
+use fs::errors::FsError;
+use thiserror::Error;
+use vault::errors::VaultError;
+
+pub type KryptaResult<T> = Result<T, KryptaError>;
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+/// Exit codes of `krypta`, besides 0 on success. Invalid arguments exit with 2, as clap does
+pub mod exit_code {
+    /// Anything not covered below
+    pub const FAILURE: i32 = 1;
+    /// Invalid arguments or input
+    pub const USAGE: i32 = 2;
+    /// `krypta.toml` is missing a setting or is invalid
+    pub const CONFIG: i32 = 3;
+    /// The vault cannot be opened or unlocked
+    pub const VAULT: i32 = 4;
+    /// The database cannot be read or written
+    pub const DATABASE: i32 = 5;
+    /// Encryption or decryption failed
+    pub const CRYPTO: i32 = 6;
+    /// Files cannot be found, read or written
+    pub const FS: i32 = 7;
+    /// Some paths failed while the others succeeded, or the vault is inconsistent
+    pub const PARTIAL: i32 = 8;
+    /// Interrupted by Ctrl-C, like shells report SIGINT
+    pub const CANCELLED: i32 = 130;
+}
+
+/// Why a command failed
+#[derive(Debug, Error)]
+pub enum KryptaError {
+    #[error("{0}")]
+    Config(#[from] ConfigError),
+    #[error("{0}")]
+    Vault(#[from] VaultError),
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("{0}")]
+    Crypto(#[from] CryptoError),
+    #[error("{0}")]
+    Fs(#[from] FsError),
+    #[error("Input/Output error: {0}")]
+    InputOutput(#[from] io::Error),
+    #[error("Cannot read the answer: {0}")]
+    Prompt(io::Error),
+    #[error("Stopped, nothing was changed")]
+    Declined,
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0:?} is not in the vault")]
+    NotInVault(PathBuf),
+    #[error("{0:?} is not a file")]
+    NotAFile(PathBuf),
+    #[error("{0:?} already exists")]
+    AlreadyExists(PathBuf),
+    #[error("An agent is already running, run `krypta lock` to stop it")]
+    AgentRunning,
+    #[error("An interrupted rekey must be completed first, please run `krypta rekey`")]
+    RekeyInProgress,
+    #[error("{failed} of {total} paths could not be {action}")]
+    Partial {
+        action: &'static str,
+        failed: usize,
+        total: usize,
+    },
+    #[error("Found {0} inconsistencies between the database and locked_path")]
+    Inconsistent(usize),
+    #[error("Cancelled, {0} paths were left untouched")]
+    Cancelled(usize),
+    #[error("Background task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+    #[error("{}: {source}", .path.display())]
+    AtPath {
+        path: PathBuf,
+        source: Box<KryptaError>,
+    },
+}
+
+impl KryptaError {
+    /// The exit code of `krypta` when a command fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            KryptaError::Config(_) => exit_code::CONFIG,
+            KryptaError::Vault(VaultError::IOError(_)) => exit_code::FS,
+            KryptaError::Vault(_) => exit_code::VAULT,
+            KryptaError::Database(DatabaseError::DatabaseFileNotSet) => exit_code::CONFIG,
+            KryptaError::Database(_) => exit_code::DATABASE,
+            KryptaError::Crypto(CryptoError::InputOutput(_)) => exit_code::FS,
+            KryptaError::Crypto(_) => exit_code::CRYPTO,
+            KryptaError::Fs(FsError::UnsafePath(_)) => exit_code::USAGE,
+            KryptaError::Fs(_) | KryptaError::InputOutput(_) => exit_code::FS,
+            KryptaError::InvalidInput(_)
+            | KryptaError::NotInVault(_)
+            | KryptaError::NotAFile(_)
+            | KryptaError::AlreadyExists(_)
+            | KryptaError::Prompt(_) => exit_code::USAGE,
+            KryptaError::AgentRunning | KryptaError::RekeyInProgress | KryptaError::Declined => {
+                exit_code::FAILURE
+            }
+            KryptaError::Partial { .. } | KryptaError::Inconsistent(_) => exit_code::PARTIAL,
+            KryptaError::Cancelled(_) => exit_code::CANCELLED,
+            KryptaError::Task(_) => exit_code::FAILURE,
+            KryptaError::AtPath { source, .. } => source.exit_code(),
+        }
+    }
+}
+
+/// Tell which path an error is about
+pub trait AtPath<T> {
+    fn at_path(self, path: impl Into<PathBuf>) -> KryptaResult<T>;
+}
+
+impl<T, E: Into<KryptaError>> AtPath<T> for Result<T, E> {
+    fn at_path(self, path: impl Into<PathBuf>) -> KryptaResult<T> {
+        self.map_err(|error| KryptaError::AtPath {
+            path: path.into(),
+            source: Box::new(error.into()),
+        })
+    }
+}
+
+/// Why `krypta.toml` cannot be used
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Cannot read krypta.toml: {0}")]
+    Read(io::Error),
+    #[error("Cannot parse krypta.toml: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Cannot write krypta.toml: {0}")]
+    Write(io::Error),
+    #[error("Cannot serialize krypta.toml: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("Please set the `locked_path` by running `krypta config locked <locked_path>`")]
+    LockedPathNotSet,
+    #[error("Cannot open `locked_path` {0:?}: {1}")]
+    LockedPathMissing(PathBuf, io::Error),
+    #[error("Invalid `read_mode` in the config: {0}")]
+    InvalidReadMode(CryptoError),
+    #[error("Unknown config key {0:?}, expected locked, identity or read-mode")]
+    UnknownKey(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let not_set = KryptaError::from(ConfigError::LockedPathNotSet);
+        assert_eq!(not_set.exit_code(), exit_code::CONFIG);
+
+        let unreadable = KryptaError::from(CryptoError::InputOutput(io::Error::other("")));
+        assert_eq!(unreadable.exit_code(), exit_code::FS);
+        assert_eq!(
+            KryptaError::from(CryptoError::Open).exit_code(),
+            exit_code::CRYPTO
+        );
+
+        assert_eq!(
+            KryptaError::from(VaultError::WrongSecret).exit_code(),
+            exit_code::VAULT
+        );
+        assert_eq!(
+            KryptaError::from(DatabaseError::CatalogInUse(PathBuf::from("db"))).exit_code(),
+            exit_code::DATABASE
+        );
+        assert_eq!(
+            KryptaError::from(DatabaseError::DatabaseFileNotSet).exit_code(),
+            exit_code::CONFIG
+        );
+        assert_eq!(
+            KryptaError::Prompt(io::ErrorKind::UnexpectedEof.into()).exit_code(),
+            exit_code::USAGE
+        );
+        assert_eq!(KryptaError::Declined.exit_code(), exit_code::FAILURE);
+        assert_eq!(KryptaError::Cancelled(1).exit_code(), exit_code::CANCELLED);
+    }
+
+    #[test]
+    fn test_at_path() {
+        let error = Err::<(), _>(CryptoError::Open).at_path("a/b").unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "a/b: Cannot decrypt data: wrong key or corrupted data"
+        );
+        assert_eq!(error.exit_code(), exit_code::CRYPTO);
+    }
+}
@@ -1,28 +1,36 @@
 mod commands;
+mod errors;
 mod utils;
 
+use cli::Parser;
 use dotenv::dotenv;
 
+use crate::utils::output::render_error;
+
 pub fn entrypoint() {
-    tokio::runtime::Builder::new_multi_thread()
+    let exit_code = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap()
-        .block_on(async { start().await })
+        .block_on(async { start().await });
+
+    std::process::exit(exit_code);
 }
 
-async fn start() {
+/// Run the command and tell how it went as an exit code, see `errors::exit_code`
+async fn start() -> i32 {
     dotenv().ok();
     pretty_env_logger::init();
 
-    // Initialize database
-    let mut database = database::connect_or_create().expect("Cannot open database");
-
     // Parse cli arguments and execute requested operation
-    match commands::execute_command(&mut database).await {
-        Ok(_) => (),
-        Err(error) => println!("{:?}", error),
-    }
+    let cli = cli::Cli::parse();
+    let format = cli.format;
 
-    database.close().unwrap();
+    match commands::execute_command(cli).await {
+        Ok(_) => 0,
+        Err(error) => {
+            render_error(&error, format);
+            error.exit_code()
+        }
+    }
 }
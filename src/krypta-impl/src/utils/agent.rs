@@ -0,0 +1,335 @@
+use std::{
+    fs::{remove_file, set_permissions, Permissions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    os::unix::{fs::PermissionsExt, net::UnixStream},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crypto::{
+    crypt::{KeyWrapper, SecretKey},
+    errors::CryptoError,
+};
+use database::agent_socket_file;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader},
+    net::{UnixListener, UnixStream as AsyncUnixStream},
+    time::timeout,
+};
+use vault::MasterKey;
+use zeroize::Zeroizing;
+
+/// How long a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A request sent to the agent, one JSON object per line. Keys are hex encoded
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum AgentRequest {
+    Wrap { key: Zeroizing<String> },
+    Unwrap { wrapped_key: String },
+    Lock,
+}
+
+/// The agent answer to an `AgentRequest`
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+enum AgentResponse {
+    Key { key: Zeroizing<String> },
+    WrappedKey { wrapped_key: String },
+    Locked,
+    WrongKey,
+    Error { message: String },
+}
+
+/// Talk to a running `krypta agent`
+pub struct AgentClient {
+    socket_path: PathBuf,
+}
+
+impl AgentClient {
+    /// Get a client for the running agent, if any
+    pub fn connect() -> Option<Self> {
+        let socket_path = agent_socket_file().ok()?;
+        UnixStream::connect(&socket_path).ok()?;
+
+        Some(AgentClient { socket_path })
+    }
+
+    /// Ask the agent to wipe the master key and exit
+    pub fn lock(&self) -> Result<(), CryptoError> {
+        match self.request(&AgentRequest::Lock)? {
+            AgentResponse::Locked => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn request(&self, request: &AgentRequest) -> Result<AgentResponse, CryptoError> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+
+        let mut line = Zeroizing::new(serde_json::to_string(request).map_err(invalid_data)?);
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+
+        let mut response = Zeroizing::new(String::new());
+        BufReader::new(stream).read_line(&mut response)?;
+
+        Ok(serde_json::from_str(&response).map_err(invalid_data)?)
+    }
+}
+
+impl KeyWrapper for AgentClient {
+    fn wrap_key(&self, key: &SecretKey) -> Result<Vec<u8>, CryptoError> {
+        let key = Zeroizing::new(hex::encode(key.as_bytes()));
+
+        match self.request(&AgentRequest::Wrap { key })? {
+            AgentResponse::WrappedKey { wrapped_key } => {
+                Ok(hex::decode(wrapped_key).map_err(invalid_data)?)
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn unwrap_key(&self, wrapped: &[u8]) -> Result<SecretKey, CryptoError> {
+        let wrapped_key = hex::encode(wrapped);
+
+        match self.request(&AgentRequest::Unwrap { wrapped_key })? {
+            AgentResponse::Key { key } => {
+                let key = Zeroizing::new(hex::decode(&*key).map_err(invalid_data)?);
+                SecretKey::try_from_slice(&key)
+            }
+            AgentResponse::WrongKey => Err(CryptoError::KeyUnwrap),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+/// The master key, either held by the agent or unlocked by this process
+pub enum Keyring {
+    Agent(AgentClient),
+    Local(MasterKey),
+}
+
+impl KeyWrapper for Keyring {
+    fn wrap_key(&self, key: &SecretKey) -> Result<Vec<u8>, CryptoError> {
+        match self {
+            Keyring::Agent(client) => client.wrap_key(key),
+            Keyring::Local(master_key) => master_key.wrap_key(key),
+        }
+    }
+
+    fn unwrap_key(&self, wrapped: &[u8]) -> Result<SecretKey, CryptoError> {
+        match self {
+            Keyring::Agent(client) => client.unwrap_key(wrapped),
+            Keyring::Local(master_key) => master_key.unwrap_key(wrapped),
+        }
+    }
+}
+
+/// A master key pinned in memory with mlock, so that it is never written to swap
+struct LockedKey(Option<MasterKey>);
+
+impl LockedKey {
+    fn try_new(master_key: MasterKey) -> io::Result<Self> {
+        let bytes = master_key.as_bytes();
+
+        // SAFETY: the pointer and length come from a live slice
+        if unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(LockedKey(Some(master_key)))
+    }
+
+    fn key(&self) -> &MasterKey {
+        // Only None while dropping
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        if let Some(master_key) = self.0.take() {
+            let bytes = master_key.as_bytes();
+            let (ptr, len) = (bytes.as_ptr(), bytes.len());
+
+            // Wipe the key before unlocking its page
+            drop(master_key);
+
+            // SAFETY: munlock does not access the memory, it only changes the page flags
+            unsafe { libc::munlock(ptr.cast(), len) };
+        }
+    }
+}
+
+/// Answer key wrapping requests with `master_key` on `socket_path` until the agent is
+/// locked, interrupted or idle for `idle_timeout`. The master key is wiped before returning
+pub async fn serve(
+    socket_path: &Path,
+    master_key: MasterKey,
+    idle_timeout: Duration,
+) -> io::Result<()> {
+    // Keep the master key out of core dumps
+    #[cfg(target_os = "linux")]
+    // SAFETY: PR_SET_DUMPABLE only changes a flag of the current process
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+
+    let master_key = LockedKey::try_new(master_key)?;
+
+    let listener = UnixListener::bind(socket_path)?;
+    set_permissions(socket_path, Permissions::from_mode(0o600))?;
+
+    let result = loop {
+        let accepted = tokio::select! {
+            accepted = timeout(idle_timeout, listener.accept()) => accepted,
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        };
+
+        let stream = match accepted {
+            Ok(Ok((stream, _))) => stream,
+            Ok(Err(error)) => break Err(error),
+            Err(_) => {
+                println!("Idle for {} seconds", idle_timeout.as_secs());
+                break Ok(());
+            }
+        };
+
+        match timeout(REQUEST_TIMEOUT, handle_client(stream, master_key.key())).await {
+            Ok(Ok(true)) => break Ok(()),
+            Ok(Ok(false)) => (),
+            Ok(Err(error)) => log::warn!("Agent client error: {error}"),
+            Err(_) => log::warn!("Agent client timed out"),
+        }
+    };
+
+    drop(master_key);
+    remove_file(socket_path)?;
+
+    result
+}
+
+/// Answer a single request, return whether the agent has been locked
+async fn handle_client(mut stream: AsyncUnixStream, master_key: &MasterKey) -> io::Result<bool> {
+    // Only the user running the agent can use it
+    // SAFETY: getuid cannot fail
+    if stream.peer_cred()?.uid() != unsafe { libc::getuid() } {
+        return Err(io::Error::new(
+            ErrorKind::PermissionDenied,
+            "client belongs to another user",
+        ));
+    }
+
+    let (reader, mut writer) = stream.split();
+
+    let mut line = Zeroizing::new(String::new());
+    AsyncBufReader::new(reader).read_line(&mut line).await?;
+
+    let request = serde_json::from_str::<AgentRequest>(&line).map_err(invalid_data);
+
+    let (response, locked) = match request {
+        Ok(AgentRequest::Lock) => (AgentResponse::Locked, true),
+        Ok(request) => (answer(request, master_key), false),
+        Err(error) => (
+            AgentResponse::Error {
+                message: error.to_string(),
+            },
+            false,
+        ),
+    };
+
+    let mut line = Zeroizing::new(serde_json::to_string(&response).map_err(invalid_data)?);
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+
+    Ok(locked)
+}
+
+fn answer(request: AgentRequest, master_key: &MasterKey) -> AgentResponse {
+    let error = |error: &dyn std::fmt::Display| AgentResponse::Error {
+        message: error.to_string(),
+    };
+
+    match request {
+        AgentRequest::Wrap { key } => {
+            let key = match hex::decode(&*key).map(Zeroizing::new) {
+                Ok(key) => key,
+                Err(e) => return error(&e),
+            };
+
+            match SecretKey::try_from_slice(&key).and_then(|key| master_key.wrap_key(&key)) {
+                Ok(wrapped_key) => AgentResponse::WrappedKey {
+                    wrapped_key: hex::encode(wrapped_key),
+                },
+                Err(e) => error(&e),
+            }
+        }
+        AgentRequest::Unwrap { wrapped_key } => {
+            let wrapped_key = match hex::decode(wrapped_key) {
+                Ok(wrapped_key) => wrapped_key,
+                Err(e) => return error(&e),
+            };
+
+            match master_key.unwrap_key(&wrapped_key) {
+                Ok(key) => AgentResponse::Key {
+                    key: Zeroizing::new(hex::encode(key.as_bytes())),
+                },
+                Err(CryptoError::KeyUnwrap) => AgentResponse::WrongKey,
+                Err(e) => error(&e),
+            }
+        }
+        AgentRequest::Lock => AgentResponse::Locked,
+    }
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, error)
+}
+
+fn unexpected(response: AgentResponse) -> CryptoError {
+    let message = match response {
+        AgentResponse::Error { message } => message,
+        _ => "unexpected response from the agent".to_string(),
+    };
+
+    CryptoError::InputOutput(io::Error::other(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto::crypt::generate_random_secure_key;
+    use zeroize::Zeroizing;
+
+    use super::{answer, AgentRequest, AgentResponse, LockedKey};
+
+    #[test]
+    fn test_answer_wrap_and_unwrap() {
+        let master_key = LockedKey::try_new(generate_random_secure_key()).unwrap();
+        let key = generate_random_secure_key();
+
+        let request = AgentRequest::Wrap {
+            key: Zeroizing::new(hex::encode(key.as_bytes())),
+        };
+        let wrapped_key = match answer(request, master_key.key()) {
+            AgentResponse::WrappedKey { wrapped_key } => wrapped_key,
+            _ => panic!("expected a wrapped key"),
+        };
+
+        let request = AgentRequest::Unwrap {
+            wrapped_key: wrapped_key.clone(),
+        };
+        match answer(request, master_key.key()) {
+            AgentResponse::Key { key: unwrapped } => {
+                assert_eq!(*unwrapped, hex::encode(key.as_bytes()))
+            }
+            _ => panic!("expected a key"),
+        }
+
+        // Another master key cannot unwrap it
+        let other = generate_random_secure_key();
+        let request = AgentRequest::Unwrap { wrapped_key };
+        assert!(matches!(answer(request, &other), AgentResponse::WrongKey));
+    }
+}
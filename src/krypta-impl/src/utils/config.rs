@@ -5,67 +5,85 @@ use std::{
     sync::RwLock,
 };
 
+use crypto::ReadMode;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
-static CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| RwLock::from(Config::read_from_disk()));
+use crate::errors::{ConfigError, ConfigResult};
+
+/// Read from disk on first use
+static CONFIG: Lazy<RwLock<Option<Config>>> = Lazy::new(|| RwLock::new(None));
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub locked_path: Option<String>,
+    pub identity_path: Option<String>,
+    /// `auto`, `mmap` or `buffered`, see `ReadMode`
+    pub read_mode: Option<String>,
 }
 
 impl Config {
-    fn read_from_disk() -> Self {
+    fn read_from_disk() -> ConfigResult<Self> {
         let config_path = config_file_path();
 
         if config_path.exists() {
-            let mut f = File::open(config_path).unwrap();
+            let mut f = File::open(config_path).map_err(ConfigError::Read)?;
             let mut s = String::new();
-            f.read_to_string(&mut s).unwrap();
+            f.read_to_string(&mut s).map_err(ConfigError::Read)?;
 
-            toml::from_str(&s).unwrap()
+            Ok(toml::from_str(&s)?)
         } else {
-            Config::default()
+            Ok(Config::default())
         }
     }
 
-    pub(crate) fn get() -> Self {
-        // read from global config
-        CONFIG.read().unwrap().to_owned()
+    pub(crate) fn get() -> ConfigResult<Self> {
+        if let Some(config) = CONFIG.read().unwrap().as_ref() {
+            return Ok(config.to_owned());
+        }
+
+        let config = Config::read_from_disk()?;
+        *CONFIG.write().unwrap() = Some(config.clone());
+
+        Ok(config)
     }
 
-    pub(crate) fn set(config: Self) {
+    pub(crate) fn set(config: Self) -> ConfigResult<()> {
         let config_path = config_file_path();
+        let s = toml::to_string_pretty(&config)?;
 
-        let mut f = File::options()
+        File::options()
             .create(true)
             .write(true)
+            .truncate(true)
             .open(config_path)
-            .unwrap();
-
-        let s = toml::to_string_pretty(&config).unwrap();
-        f.write_all(s.as_bytes()).unwrap();
+            .and_then(|mut f| f.write_all(s.as_bytes()))
+            .map_err(ConfigError::Write)?;
 
         // Update global config
-        let mut c = CONFIG.write().unwrap();
-        *c = config;
+        *CONFIG.write().unwrap() = Some(config);
+
+        Ok(())
     }
 
-    pub fn get_locked_path() -> PathBuf {
-        let p = Config::get()
+    pub fn get_locked_path() -> ConfigResult<PathBuf> {
+        let p = Config::get()?
             .locked_path
             .map(PathBuf::from)
-            .expect("Please set the `locked_path` by running `krypta config locked <locked_path>`");
+            .ok_or(ConfigError::LockedPathNotSet)?;
 
-        File::open(&p).unwrap_or_else(|_| {
-            panic!(
-                "cannot open `locked_path` {}: No such file or directory",
-                p.to_string_lossy()
-            )
-        });
+        File::open(&p).map_err(|error| ConfigError::LockedPathMissing(p.clone(), error))?;
+
+        Ok(p)
+    }
 
-        p
+    /// How to read the files to hash, encrypt or decrypt, `auto` when not set
+    pub fn get_read_mode() -> ConfigResult<ReadMode> {
+        Config::get()?
+            .read_mode
+            .map(|read_mode| read_mode.parse().map_err(ConfigError::InvalidReadMode))
+            .transpose()
+            .map(Option::unwrap_or_default)
     }
 }
 
@@ -0,0 +1,61 @@
+use crypto::errors::CryptoError;
+use database::{
+    database_file, errors::DatabaseError, migrations::latest_version, vault_file, EncryptedDatabase,
+};
+use vault::Vault;
+
+use super::{agent::Keyring, vault::unlock_keyring};
+use crate::errors::KryptaResult;
+
+/// Unlock the master key, with the agent if it is running, decrypt the database and apply
+/// the pending schema migrations
+pub fn open_database() -> KryptaResult<(EncryptedDatabase, Keyring)> {
+    let (database, keyring) = open_unmigrated_database()?;
+
+    let migrated = database.migrate(&keyring)?;
+
+    if let Some(backup) = migrated.backup {
+        eprintln!(
+            "Database migrated to version {}, the old one is in {}",
+            latest_version(),
+            backup.display()
+        );
+    }
+
+    Ok((database, keyring))
+}
+
+/// Unlock the master key and decrypt the database, as it is on disk
+pub fn open_unmigrated_database() -> KryptaResult<(EncryptedDatabase, Keyring)> {
+    let keyring = unlock_keyring()?;
+
+    let database = match EncryptedDatabase::open_or_create(database_file()?, &keyring) {
+        Ok(database) => database,
+        Err(DatabaseError::Crypto(CryptoError::KeyUnwrap)) => {
+            open_with_previous_master_key(&keyring)?
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    Ok((database, keyring))
+}
+
+/// The database key may still be wrapped with the previous master key, if a master key
+/// rotation has been interrupted
+fn open_with_previous_master_key(keyring: &Keyring) -> KryptaResult<EncryptedDatabase> {
+    let previous_master_key = match keyring {
+        Keyring::Local(master_key) => {
+            Vault::open(vault_file()?)?.previous_master_key(master_key)?
+        }
+        Keyring::Agent(_) => None,
+    };
+
+    match previous_master_key {
+        Some(previous_master_key) => Ok(EncryptedDatabase::open_or_create(
+            database_file()?,
+            &previous_master_key,
+        )?),
+        // The master key cannot unwrap the database key
+        None => Err(DatabaseError::Crypto(CryptoError::KeyUnwrap).into()),
+    }
+}
@@ -1 +1,7 @@
+pub mod agent;
 pub mod config;
+pub mod database;
+pub mod output;
+pub mod pipeline;
+pub mod progress;
+pub mod vault;
@@ -0,0 +1,207 @@
+use std::{
+    io::{self, stdout, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use byte_unit::Byte;
+use cli::OutputFormat;
+use crypto::types::Report;
+use serde::{Serialize, Serializer};
+use serde_json::json;
+
+use crate::errors::{KryptaError, KryptaResult};
+
+/// The result of a command, printed as text for humans or as JSON for scripts
+pub trait Output: Serialize {
+    /// Print as text
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()>;
+
+    /// How the command ends once this is printed: an error when only part of it succeeded
+    fn outcome(&self) -> KryptaResult<()> {
+        Ok(())
+    }
+}
+
+/// Print `output` on stdout in `format`, then end the command with its outcome
+pub fn render(output: &impl Output, format: OutputFormat) -> KryptaResult<()> {
+    let mut stdout = BufWriter::new(stdout().lock());
+
+    match format {
+        OutputFormat::Text => output.write_text(&mut stdout)?,
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut stdout, output).map_err(io::Error::from)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    stdout.flush()?;
+
+    output.outcome()
+}
+
+/// Print why the command failed on stderr in `format`
+pub fn render_error(error: &KryptaError, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => eprintln!("Error: {error}"),
+        OutputFormat::Json => eprintln!(
+            "{}",
+            json!({ "error": error.to_string(), "exit_code": error.exit_code() })
+        ),
+    }
+}
+
+/// A sentence about what the command did
+#[derive(Debug, Serialize)]
+pub struct Message {
+    pub message: String,
+}
+
+impl Message {
+    pub fn new(message: impl Into<String>) -> Self {
+        Message {
+            message: message.into(),
+        }
+    }
+}
+
+impl Output for Message {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}", self.message)
+    }
+}
+
+/// The `Report` of an operation on many files, such as `extract`
+#[derive(Debug, Serialize)]
+pub struct ReportOutput {
+    /// What has been done to the files, like "extracted"
+    pub action: &'static str,
+    #[serde(flatten)]
+    pub report: Report,
+}
+
+impl Output for ReportOutput {
+    fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut action = self.action.to_string();
+        action[..1].make_ascii_uppercase();
+
+        writeln!(
+            out,
+            "{action} {} paths ({})",
+            self.report.processed_file_count,
+            Byte::from_bytes(self.report.processed_bytes.into()).get_appropriate_unit(false)
+        )?;
+
+        if self.report.errors_count > 0 {
+            writeln!(out, "{} paths failed:", self.report.errors_count)?;
+
+            for error in &self.report.errors {
+                writeln!(out, "  {}: {}", error.path, error.error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn outcome(&self) -> KryptaResult<()> {
+        if self.report.cancelled_count > 0 {
+            return Err(KryptaError::Cancelled(self.report.cancelled_count));
+        }
+
+        if self.report.errors_count > 0 {
+            return Err(KryptaError::Partial {
+                action: self.action,
+                failed: self.report.errors_count,
+                total: self.report.processed_file_count + self.report.errors_count,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Serialize a path as UTF-8, replacing what is not valid
+pub fn lossy_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+/// Serialize paths as UTF-8, replacing what is not valid
+pub fn lossy_paths<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(output: &impl Output) -> String {
+        let mut out = vec![];
+        output.write_text(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_report_output() {
+        let mut report = Report::default();
+        report.processed(1000);
+        report.failed("a/b", "Cannot read it");
+
+        let output = ReportOutput {
+            action: "extracted",
+            report,
+        };
+
+        assert_eq!(
+            text(&output),
+            "Extracted 1 paths (1000 B)\n1 paths failed:\n  a/b: Cannot read it\n"
+        );
+        assert!(matches!(
+            output.outcome(),
+            Err(KryptaError::Partial {
+                failed: 1,
+                total: 2,
+                ..
+            })
+        ));
+
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["action"], "extracted");
+        assert_eq!(json["processed_bytes"], 1000);
+        assert_eq!(json["errors"][0]["path"], "a/b");
+    }
+
+    #[test]
+    fn test_cancelled_report() {
+        let output = ReportOutput {
+            action: "rekeyed",
+            report: Report {
+                cancelled_count: 3,
+                ..Default::default()
+            },
+        };
+
+        assert!(matches!(output.outcome(), Err(KryptaError::Cancelled(3))));
+    }
+
+    #[test]
+    fn test_lossy_paths() {
+        #[derive(Serialize)]
+        struct Paths {
+            #[serde(serialize_with = "lossy_path")]
+            path: PathBuf,
+            #[serde(serialize_with = "lossy_paths")]
+            paths: Vec<PathBuf>,
+        }
+
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let invalid = PathBuf::from(OsStr::from_bytes(b"a\xffb"));
+
+        let json = serde_json::to_value(Paths {
+            path: invalid.clone(),
+            paths: vec![invalid, "c".into()],
+        })
+        .unwrap();
+
+        assert_eq!(json["path"], "a\u{fffd}b");
+        assert_eq!(json["paths"][1], "c");
+    }
+}
@@ -0,0 +1,701 @@
+use std::{
+    fs::{read_link, remove_file, rename, File},
+    io::{self, BufWriter, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::available_parallelism,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use crypto::{
+    crypt::{CipherFormat, SecretKey, StreamSummary, AEAD_NONCE_SIZE},
+    progress::CancellationToken,
+    ReadMode, SourceFile,
+};
+use database::{errors::DatabaseError, models, traits::Insert, Database, EncryptedDatabase};
+use fs::{
+    check_vault_path, encode_path, normalize_path, CollisionDetector, FileAttributes, ParallelWalk,
+    WalkEntry,
+};
+use tokio::{
+    sync::{mpsc, mpsc::error::SendError, Semaphore},
+    task::spawn_blocking,
+};
+
+use super::agent::Keyring;
+use crate::errors::KryptaResult;
+
+/// Plaintext read at once from a file
+const CHUNK_SIZE: usize = 32 * 1024;
+
+/// Chunks read ahead of the encryption, for each file being read
+const READ_AHEAD_CHUNKS: usize = 4;
+
+/// Writing the catalog takes longer as it grows, so the time between two writes is at least
+/// this many times the last one took. This keeps writing it to a fraction of an `add_paths`
+/// of millions of paths, instead of most of it
+const PERSIST_SLOWDOWN: u32 = 10;
+
+/// How the work of a `add_paths` is split, which bounds the memory it uses
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    /// Files being read at the same time. More than `cpu_concurrency` hides the latency of
+    /// slow disks and network mounts, as their first chunks are ready when encryption starts
+    pub io_concurrency: usize,
+    /// Files being hashed and encrypted at the same time
+    pub cpu_concurrency: usize,
+    /// Paths waiting between two stages
+    pub queue_size: usize,
+    /// Paths recorded in the database between two commits
+    pub commit_every: usize,
+    /// Minimum time between two writes of the catalog to disk, after a commit. Each one
+    /// encrypts the whole catalog, so they cannot be as frequent as commits, and they get
+    /// further apart as the catalog grows, see `PERSIST_SLOWDOWN`
+    pub persist_every: Duration,
+    pub read_mode: ReadMode,
+    /// Keep going past the paths that cannot be read, instead of stopping at the first one
+    pub allow_partial: bool,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        let cpus = available_parallelism().map_or(1, |cpus| cpus.get());
+
+        PipelineOptions {
+            io_concurrency: 2 * cpus,
+            cpu_concurrency: cpus,
+            queue_size: 1024,
+            commit_every: 1000,
+            persist_every: Duration::from_secs(30),
+            read_mode: ReadMode::default(),
+            allow_partial: false,
+        }
+    }
+}
+
+/// What happened to the paths given to `add_paths`
+#[derive(Debug, Default)]
+pub struct PipelineReport {
+    /// Paths recorded in the database
+    pub added: u64,
+    /// Plaintext size of the files recorded
+    pub bytes: u64,
+    /// Source paths that were not added, and why
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+    /// The path that could not be read and stopped the others from being added, unless
+    /// `PipelineOptions::allow_partial` is set
+    pub stopped_at: Option<PathBuf>,
+}
+
+/// A path found by the walk, where it comes from and where it goes in the vault
+struct Found {
+    source_path: PathBuf,
+    virtual_path: PathBuf,
+    entry: WalkEntry,
+}
+
+/// What the encryption stage hands to the database one
+enum Processed {
+    Ready {
+        file: Box<models::File>,
+        source_path: PathBuf,
+        /// The virtual path before normalization
+        raw_path: PathBuf,
+        /// The encrypted contents, to be removed if the file cannot be recorded
+        locked_file: Option<PathBuf>,
+    },
+    Failed(PathBuf, anyhow::Error),
+}
+
+/// Walk, hash, encrypt and record in `db` the paths found by `walks`, each one added under
+/// its virtual prefix. Stages are connected by bounded queues, so that the paths in flight
+/// do not grow with the number of paths: the walk waits when files cannot be encrypted fast
+/// enough, and encryption waits for the database. The catalog does grow, as it is held in
+/// memory by `EncryptedDatabase`, and so do the empty directories found by the walk.
+///
+/// Recorded paths are committed and persisted as they come, so an interrupted run keeps most
+/// of its work. When recording fails, the other stages stop and the files that were encrypted
+/// but not recorded are removed
+pub async fn add_paths(
+    db: &mut EncryptedDatabase,
+    master_key: &Keyring,
+    locked_path: &Path,
+    walks: Vec<(ParallelWalk, PathBuf)>,
+    options: &PipelineOptions,
+) -> KryptaResult<PipelineReport> {
+    let (found_sender, mut found_receiver) = mpsc::channel(options.queue_size);
+    let (processed_sender, mut processed_receiver) = mpsc::channel(options.queue_size);
+
+    let io_concurrency = options.io_concurrency.max(1);
+    let io_permits = Arc::new(Semaphore::new(io_concurrency));
+    let cpu_permits = Arc::new(Semaphore::new(options.cpu_concurrency.max(1)));
+
+    // Cancelled when a stage stops early, so that the others stop too
+    let cancel = CancellationToken::new();
+
+    // Walk, on threads of its own since it blocks
+    let walker_cancel = cancel.clone();
+    let walker = spawn_blocking(move || {
+        for (walk, virtual_prefix) in walks {
+            let root = walk.root.clone();
+            let mut open = true;
+
+            walk.run(|entry| {
+                let relative_path = match &entry {
+                    WalkEntry::File(path, _)
+                    | WalkEntry::Symlink(path, _)
+                    | WalkEntry::EmptyDir(path, _) => path,
+                    WalkEntry::Skipped(skipped) => &skipped.path,
+                };
+
+                let found = Found {
+                    source_path: root.join(relative_path),
+                    virtual_path: virtual_prefix.join(relative_path),
+                    entry,
+                };
+
+                open = !walker_cancel.is_cancelled() && found_sender.blocking_send(found).is_ok();
+                open
+            });
+
+            if !open {
+                return;
+            }
+        }
+    });
+
+    // Prepare the entries and start encrypting the files
+    let dispatch_cancel = cancel.clone();
+    let dispatch = async move {
+        let cancel = dispatch_cancel;
+        let mut collisions = CollisionDetector::new();
+        let mut stopped_at = None;
+
+        while let Some(found) = found_receiver.recv().await {
+            if cancel.is_cancelled() {
+                break;
+            }
+
+            let Found {
+                source_path,
+                virtual_path: raw_path,
+                entry,
+            } = found;
+
+            if matches!(entry, WalkEntry::Skipped(_)) && !options.allow_partial {
+                // Recorded as failed like the other paths that cannot be read
+                stopped_at = Some(source_path.clone());
+                cancel.cancel();
+            }
+
+            let virtual_path = normalize_path(&raw_path);
+
+            if let Some(collides_with) = collisions.check(&virtual_path) {
+                eprintln!(
+                    "Warning: {} collides with {} on case-insensitive filesystems",
+                    virtual_path.display(),
+                    collides_with.display()
+                );
+            }
+
+            let file = match new_file(&source_path, virtual_path, &entry, master_key) {
+                Ok(file) => file,
+                Err(error) => {
+                    let _ = processed_sender
+                        .send(Processed::Failed(source_path, error))
+                        .await;
+                    continue;
+                }
+            };
+
+            if !matches!(entry, WalkEntry::File(..)) {
+                let ready = Processed::Ready {
+                    file: Box::new(file),
+                    source_path,
+                    raw_path,
+                    locked_file: None,
+                };
+                let _ = processed_sender.send(ready).await;
+                continue;
+            }
+
+            let key = match file.unwrap_key(master_key) {
+                Ok(key) => key,
+                Err(error) => {
+                    let failed = Processed::Failed(source_path, error.into());
+                    let _ = processed_sender.send(failed).await;
+                    continue;
+                }
+            };
+
+            // Held until the file is handed to the database stage, which bounds the files in
+            // flight
+            let io_permit = io_permits.clone().acquire_owned().await.unwrap();
+
+            let processed_sender = processed_sender.clone();
+            let cpu_permits = cpu_permits.clone();
+            let locked_path = locked_path.to_path_buf();
+            let read_mode = options.read_mode;
+            let cancel = cancel.clone();
+
+            tokio::spawn(async move {
+                if cancel.is_cancelled() {
+                    return;
+                }
+
+                let mut file = file;
+
+                // The locked name depends on the contents hash, which is only known at the end
+                let partial_path = locked_path.join(format!(".{}.partial", file.locked_hash));
+                // Should never fail as nonce len is constant
+                let nonce: [u8; AEAD_NONCE_SIZE] = file.nonce.clone().try_into().unwrap();
+
+                let encrypted = encrypt_file(
+                    source_path.clone(),
+                    partial_path.clone(),
+                    key,
+                    nonce,
+                    file.format.into(),
+                    read_mode,
+                    cpu_permits,
+                )
+                .await
+                .and_then(|summary| {
+                    file.set_contents(summary.hash.to_string(), summary.size);
+                    let locked_file = locked_path.join(&file.locked_hash);
+                    rename(&partial_path, &locked_file)?;
+
+                    Ok(locked_file)
+                });
+
+                let processed = match encrypted {
+                    Ok(locked_file) => Processed::Ready {
+                        file: Box::new(file),
+                        source_path,
+                        raw_path,
+                        locked_file: Some(locked_file),
+                    },
+                    Err(error) => {
+                        let _ = remove_file(&partial_path);
+                        Processed::Failed(source_path, error)
+                    }
+                };
+
+                // The database stage has stopped, nothing is going to record the file
+                if let Err(SendError(Processed::Ready {
+                    locked_file: Some(locked_file),
+                    ..
+                })) = processed_sender.send(processed).await
+                {
+                    let _ = remove_file(locked_file);
+                }
+
+                drop(io_permit);
+            });
+        }
+
+        // Stop the walk, then wait for the files in flight, which release their permits once
+        // handed to the database stage or removed
+        drop(found_receiver);
+        drop(processed_sender);
+        let _ = io_permits.acquire_many(io_concurrency as u32).await;
+
+        stopped_at
+    };
+
+    // Record the results, committing every now and then
+    let record = async {
+        let mut uncommitted_files = vec![];
+        let report =
+            record_processed(db, &mut processed_receiver, options, &mut uncommitted_files).await;
+
+        // Done, or failed and nothing else is going to be recorded
+        cancel.cancel();
+        processed_receiver.close();
+
+        // Their records have been rolled back, or they were not recorded at all
+        if report.is_err() {
+            for locked_file in uncommitted_files {
+                let _ = remove_file(locked_file);
+            }
+        }
+
+        while let Some(processed) = processed_receiver.recv().await {
+            if let Processed::Ready {
+                locked_file: Some(locked_file),
+                ..
+            } = processed
+            {
+                let _ = remove_file(locked_file);
+            }
+        }
+
+        report
+    };
+
+    let (stopped_at, report) = tokio::join!(dispatch, record);
+    walker.await?;
+
+    Ok(PipelineReport {
+        stopped_at,
+        ..report?
+    })
+}
+
+/// Record the files handed by the encryption stage in `db`. `uncommitted_files` are the
+/// locked files whose records are not committed yet
+async fn record_processed(
+    db: &mut EncryptedDatabase,
+    processed_receiver: &mut mpsc::Receiver<Processed>,
+    options: &PipelineOptions,
+    uncommitted_files: &mut Vec<PathBuf>,
+) -> KryptaResult<PipelineReport> {
+    let mut report = PipelineReport::default();
+    let mut transaction = db.transaction().map_err(DatabaseError::from)?;
+    let mut uncommitted = 0;
+    let mut persisted_at = Instant::now();
+    let mut persist_every = options.persist_every;
+
+    while let Some(processed) = processed_receiver.recv().await {
+        let (file, source_path, raw_path, locked_file) = match processed {
+            Processed::Ready {
+                file,
+                source_path,
+                raw_path,
+                locked_file,
+            } => (file, source_path, raw_path, locked_file),
+            Processed::Failed(source_path, error) => {
+                report.failed.push((source_path, error));
+                continue;
+            }
+        };
+
+        match record_file(&transaction, *file, &raw_path) {
+            Ok(file) => {
+                report.added += 1;
+                report.bytes += file.size;
+                uncommitted += 1;
+                uncommitted_files.extend(locked_file);
+            }
+            Err(error) => {
+                if let Some(locked_file) = locked_file {
+                    let _ = remove_file(locked_file);
+                }
+
+                report.failed.push((source_path, error));
+            }
+        }
+
+        if uncommitted >= options.commit_every {
+            transaction.commit().map_err(DatabaseError::from)?;
+            uncommitted_files.clear();
+            log::info!("Committed {} paths so far", report.added);
+
+            if persisted_at.elapsed() >= persist_every {
+                let started_at = Instant::now();
+                db.persist()?;
+
+                persisted_at = Instant::now();
+                persist_every = options
+                    .persist_every
+                    .max(started_at.elapsed() * PERSIST_SLOWDOWN);
+            }
+
+            transaction = db.transaction().map_err(DatabaseError::from)?;
+            uncommitted = 0;
+        }
+    }
+
+    transaction.commit().map_err(DatabaseError::from)?;
+
+    Ok(report)
+}
+
+/// The record of a path found by the walk, with an empty contents hash for files
+fn new_file(
+    source_path: &Path,
+    virtual_path: PathBuf,
+    entry: &WalkEntry,
+    master_key: &Keyring,
+) -> anyhow::Result<models::File> {
+    check_vault_path(&virtual_path)?;
+
+    let title = encode_path(&virtual_path);
+
+    let (mut file, metadata) = match entry {
+        WalkEntry::File(_, metadata) => {
+            let file = models::File::new(
+                title,
+                virtual_path,
+                String::new(),
+                metadata.len(),
+                master_key,
+            )?;
+
+            (file, metadata)
+        }
+        WalkEntry::Symlink(_, metadata) => {
+            let target = read_link(source_path)?;
+            let file = models::File::new_symlink(title, virtual_path, target, master_key)?;
+
+            (file, metadata)
+        }
+        WalkEntry::EmptyDir(_, metadata) => {
+            let file = models::File::new_directory(title, virtual_path, master_key)?;
+
+            (file, metadata)
+        }
+        WalkEntry::Skipped(skipped) => {
+            anyhow::bail!("Cannot read it: {}", skipped.error)
+        }
+    };
+
+    file.set_attributes(&FileAttributes::read(source_path, metadata));
+
+    Ok(file)
+}
+
+/// Read `source_path` on a thread, while another one hashes and encrypts it into
+/// `partial_path` in `format` once a CPU permit is available. A chunked file is encrypted
+/// across the rayon pool, on top of its permit
+async fn encrypt_file(
+    source_path: PathBuf,
+    partial_path: PathBuf,
+    key: SecretKey,
+    nonce: [u8; AEAD_NONCE_SIZE],
+    format: CipherFormat,
+    read_mode: ReadMode,
+    cpu_permits: Arc<Semaphore>,
+) -> anyhow::Result<StreamSummary> {
+    let (chunk_sender, chunk_receiver) = mpsc::channel::<Vec<u8>>(READ_AHEAD_CHUNKS);
+
+    let reader = spawn_blocking(move || {
+        let mut source_file = SourceFile::open(&source_path, read_mode)?;
+
+        loop {
+            let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+            (&mut source_file)
+                .take(CHUNK_SIZE as u64)
+                .read_to_end(&mut chunk)?;
+
+            // Stop when done, or when encryption has failed
+            if chunk.is_empty() || chunk_sender.blocking_send(chunk).is_err() {
+                break;
+            }
+        }
+
+        source_file.check_unchanged()
+    });
+
+    let cpu_permit = cpu_permits.acquire_owned().await?;
+
+    let encryptor = spawn_blocking(move || {
+        let _cpu_permit = cpu_permit;
+        let locked_file = BufWriter::new(File::create(&partial_path)?);
+
+        format.encrypt(
+            ChunkReader::new(chunk_receiver),
+            locked_file,
+            &key,
+            &nonce.into(),
+        )
+    });
+
+    let (read, encrypted) = tokio::join!(reader, encryptor);
+
+    // A failed encryption stops the reading halfway, which is not worth reporting
+    let summary = encrypted?.context("Cannot encrypt it")?;
+    read?.context("Cannot read it")?;
+
+    Ok(summary)
+}
+
+/// Insert `file`, or fail when its path is already in the vault. `raw_path` is the path
+/// before normalization, a path that is only taken once normalized is a conflict too
+fn record_file(db: &Database, file: models::File, raw_path: &Path) -> anyhow::Result<models::File> {
+    let virtual_path = PathBuf::from(&file);
+
+    if models::File::find_by_path(db, &virtual_path)?.is_some() {
+        if raw_path == virtual_path {
+            anyhow::bail!("{} is already in the vault", raw_path.display());
+        }
+
+        anyhow::bail!(
+            "{} is already in the vault as {}, its Unicode normalized form",
+            raw_path.display(),
+            virtual_path.display()
+        );
+    }
+
+    Ok(file.insert(db)?)
+}
+
+/// Reads the chunks sent by the reading thread
+struct ChunkReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl ChunkReader {
+    fn new(receiver: mpsc::Receiver<Vec<u8>>) -> Self {
+        ChunkReader {
+            receiver,
+            chunk: vec![],
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let read = buf.len().min(self.chunk.len() - self.position);
+        buf[..read].copy_from_slice(&self.chunk[self.position..self.position + read]);
+        self.position += read;
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        fs::{create_dir, read_dir, remove_dir_all},
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use crypto::crypt::generate_random_secure_key;
+    use database::{
+        create_in_memory,
+        models::{self, EntryKind},
+        traits::FetchAll,
+        EncryptedDatabase,
+    };
+    use fs::{encode_path, normalize_path, PathFinder};
+    use rand::{prelude::SmallRng, SeedableRng};
+    use tmp::{RandomFill, Tmp};
+
+    use super::{add_paths, record_file, PipelineOptions};
+    use crate::utils::agent::Keyring;
+
+    /// Add 100 random files, with a catalog that cannot be persisted when `persist_fails`
+    async fn add_random_files(persist_fails: bool) -> (Tmp, EncryptedDatabase, bool) {
+        let mut rng = SmallRng::seed_from_u64(45);
+        let source = Tmp::random();
+        source.random_fill(100, &mut rng).unwrap();
+
+        let tmp = Tmp::random();
+        let locked_path = tmp.base_path().join("locked");
+        let catalog_path = tmp.base_path().join("catalog");
+        create_dir(&locked_path).unwrap();
+        create_dir(&catalog_path).unwrap();
+
+        let master_key = Keyring::Local(generate_random_secure_key());
+        let mut db =
+            EncryptedDatabase::open_or_create(catalog_path.join("db.sqlite"), &master_key).unwrap();
+
+        if persist_fails {
+            remove_dir_all(&catalog_path).unwrap();
+        }
+
+        let walk = PathFinder::builder(source.base_path())
+            .walk_parallel()
+            .unwrap();
+        let options = PipelineOptions {
+            io_concurrency: 8,
+            cpu_concurrency: 2,
+            queue_size: 4,
+            commit_every: 10,
+            persist_every: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let result = add_paths(
+            &mut db,
+            &master_key,
+            &locked_path,
+            vec![(walk, PathBuf::from("docs"))],
+            &options,
+        )
+        .await;
+
+        (tmp, db, result.is_ok())
+    }
+
+    /// The names in the locked path, and the locked names of the files in `db`
+    fn locked_names(tmp: &Tmp, db: &EncryptedDatabase) -> (HashSet<String>, HashSet<String>) {
+        let found = read_dir(tmp.base_path().join("locked"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+
+        let recorded = models::File::fetch_all(db)
+            .unwrap()
+            .into_iter()
+            .filter(|file| file.kind == EntryKind::File)
+            .map(|file| file.locked_hash)
+            .collect();
+
+        (found, recorded)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_add_paths() {
+        let (tmp, db, succeeded) = add_random_files(false).await;
+        assert!(succeeded);
+
+        let (found, recorded) = locked_names(&tmp, &db);
+        assert_eq!(recorded.len(), 100);
+        assert_eq!(found, recorded);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_record_failure_leaves_no_orphans() {
+        let (tmp, db, succeeded) = add_random_files(true).await;
+        assert!(!succeeded);
+
+        // The first commit made it, then persisting failed and everything else stopped
+        let (found, recorded) = locked_names(&tmp, &db);
+        assert_eq!(recorded.len(), 10);
+        assert_eq!(found, recorded);
+    }
+
+    #[test]
+    fn test_record_normalization_conflict() {
+        let db = create_in_memory().unwrap();
+        let master_key = generate_random_secure_key();
+
+        // "é" composed, as Linux usually writes it, and decomposed, as macOS does
+        let composed = Path::new("caf\u{e9}.txt");
+        let decomposed = Path::new("cafe\u{301}.txt");
+
+        let new_file = |raw_path: &Path| {
+            let path = normalize_path(raw_path);
+            models::File::new(encode_path(&path), path, String::new(), 0, &master_key).unwrap()
+        };
+
+        record_file(&db, new_file(composed), composed).unwrap();
+
+        let error = record_file(&db, new_file(decomposed), decomposed).unwrap_err();
+        assert!(error.to_string().contains("normalized"));
+        assert!(error.to_string().contains(&composed.display().to_string()));
+
+        assert!(record_file(&db, new_file(composed), composed).is_err());
+        assert!(models::File::find_by_path(&db, decomposed)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    io::{self, stderr},
+    process::exit,
+};
+
+use cli::ProgressMode;
+use crypto::progress::{
+    BarProgress, CancellationToken, JsonLinesProgress, ProgressSink, QuietProgress,
+};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Where to report the progress of the bulk operations in `mode`
+pub fn progress_sink(mode: ProgressMode) -> Box<dyn ProgressSink> {
+    match mode {
+        ProgressMode::Bar => Box::new(BarProgress::new()),
+        ProgressMode::Quiet => Box::new(QuietProgress),
+        ProgressMode::Json => Box::new(JsonLinesProgress::new(stderr())),
+    }
+}
+
+/// A token cancelled by the first Ctrl-C, which lets the files in progress complete. The
+/// second Ctrl-C exits right away
+pub fn cancel_on_ctrl_c() -> io::Result<CancellationToken> {
+    let token = CancellationToken::new();
+
+    // Registered now, before any long operation starts
+    let mut interrupts = signal(SignalKind::interrupt())?;
+
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        interrupts.recv().await;
+        eprintln!("Cancelling, waiting for the files in progress. Press Ctrl-C again to abort");
+        cancel.cancel();
+
+        interrupts.recv().await;
+        exit(130);
+    });
+
+    Ok(token)
+}
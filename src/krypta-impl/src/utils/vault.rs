@@ -0,0 +1,85 @@
+use std::fs::read_to_string;
+
+use crypto::crypt::Identity;
+use database::vault_file;
+use utils::{ask_line, ask_passphrase};
+use vault::{MasterKey, Vault};
+
+use super::{
+    agent::{AgentClient, Keyring},
+    config::Config,
+};
+use crate::errors::{KryptaError, KryptaResult};
+
+/// Open the vault and get the master key using the configured identity file, if any.
+/// Otherwise ask the user for the passphrase, or for the key shares when the vault has
+/// no passphrase
+pub fn unlock_vault() -> KryptaResult<(Vault, MasterKey)> {
+    let vault = Vault::open(vault_file()?)?;
+
+    let master_key = if let Some(identity_path) = Config::get()?.identity_path {
+        let identity = Identity::from_identity_file(read_to_string(identity_path)?)?;
+        vault.unlock_with_identity(&identity)?
+    } else if vault.has_passphrase() {
+        let passphrase = ask_passphrase("Vault passphrase:").map_err(KryptaError::Prompt)?;
+        vault.unlock_with_passphrase(passphrase)?
+    } else {
+        let shares = ask_shares(vault.shares_threshold()?)?;
+        vault.unlock_with_shares(&shares)?
+    };
+
+    if vault.previous_master_key(&master_key)?.is_some() {
+        eprintln!(
+            "Warning: a master key rotation is in progress, run `krypta rekey` to complete it"
+        );
+    }
+
+    Ok((vault, master_key))
+}
+
+/// Use the master key held by `krypta agent`, if it is running. Otherwise unlock the vault
+pub fn unlock_keyring() -> KryptaResult<Keyring> {
+    if let Some(client) = AgentClient::connect() {
+        return Ok(Keyring::Agent(client));
+    }
+
+    let (_, master_key) = unlock_vault()?;
+
+    Ok(Keyring::Local(master_key))
+}
+
+/// Ask for `threshold` key shares
+pub fn ask_shares(threshold: u8) -> KryptaResult<Vec<String>> {
+    eprintln!("Vault is protected by key shares, {threshold} are needed");
+
+    (1..=threshold)
+        .map(|i| ask_line(format!("Share {i}/{threshold}:")).map_err(KryptaError::Prompt))
+        .collect()
+}
+
+/// Print a recovery key in a way that is easy to write down, on stderr so that it is shown
+/// to the user and not captured with the output of the command
+pub fn print_recovery_key(mnemonic: &str) {
+    eprintln!("This is your recovery key, write it down and store it in a safe place:\n");
+
+    for (i, word) in mnemonic.split_whitespace().enumerate() {
+        eprint!("{:>2}. {word:<10}", i + 1);
+
+        if (i + 1) % 4 == 0 {
+            eprintln!();
+        }
+    }
+
+    eprintln!("\nAnyone with this key can access the vault. It will not be shown again.");
+}
+
+/// Print the key shares, one per line, on stderr like the recovery key
+pub fn print_shares(shares: &[String]) {
+    eprintln!("These are the key shares, hand each one to a different person:\n");
+
+    for (i, share) in shares.iter().enumerate() {
+        eprintln!("{:>3}. {share}", i + 1);
+    }
+
+    eprintln!("\nThey will not be shown again.");
+}
@@ -1,5 +1,11 @@
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use std::io::Write;
+use std::{
+    ffi::OsString,
+    fs::{rename, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
 
 pub struct RandomString;
 
@@ -31,19 +37,71 @@ impl RandomString {
     }
 }
 
-pub fn ask_yes_or_no(question: impl AsRef<str>) {
-    let question = question.as_ref();
-    print!("{question} y/N ");
+/// Replace `path` with `contents`, readable only by the current user. A copy is written to
+/// `<path>.tmp` and swapped in, so that a crash leaves either the old or the new contents
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_path = OsString::from(path);
+    tmp_path.push(".tmp");
 
-    std::io::stdout().lock().flush().unwrap();
+    let mut tmp_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp_path)?;
+    tmp_file.write_all(contents)?;
+    tmp_file.sync_all()?;
 
-    let mut in_buf = String::new();
-    std::io::stdin().read_line(&mut in_buf).unwrap();
+    rename(&tmp_path, path)?;
+
+    // Make the rename itself durable
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Ask a yes or no question, anything but an answer starting with `y` means no, like the
+/// input ending
+pub fn ask_yes_or_no(question: impl AsRef<str>) -> io::Result<bool> {
+    match ask_line(format!("{} y/N", question.as_ref())) {
+        Ok(answer) => Ok(answer.to_lowercase().starts_with('y')),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
 
-    in_buf = in_buf.trim().to_lowercase();
+/// Ask for a passphrase without echoing it back to the terminal
+pub fn ask_passphrase(prompt: impl AsRef<str>) -> io::Result<String> {
+    rpassword::prompt_password(format!("{} ", prompt.as_ref()))
+}
+
+/// Ask for a new passphrase twice, making sure that both match
+pub fn ask_new_passphrase() -> io::Result<String> {
+    loop {
+        let passphrase = ask_passphrase("New passphrase:")?;
+        let confirmation = ask_passphrase("Confirm passphrase:")?;
+
+        if passphrase.is_empty() {
+            eprintln!("Passphrase cannot be empty.");
+        } else if passphrase != confirmation {
+            eprintln!("Passphrases do not match.");
+        } else {
+            return Ok(passphrase);
+        }
+    }
+}
+
+/// Ask for a line of input. The input ending before the answer is an error
+pub fn ask_line(prompt: impl AsRef<str>) -> io::Result<String> {
+    eprint!("{} ", prompt.as_ref());
+    io::stderr().lock().flush()?;
 
-    if in_buf.is_empty() || !in_buf.starts_with('y') {
-        println!("Stopped.");
-        std::process::exit(0);
+    let mut in_buf = String::new();
+    if io::stdin().read_line(&mut in_buf)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
     }
+
+    Ok(in_buf.trim().to_string())
 }
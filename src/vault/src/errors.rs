@@ -0,0 +1,32 @@
+use crypto::errors::CryptoError;
+use thiserror::Error;
+
+pub type VaultResult<T> = Result<T, VaultError>;
+
+#[derive(Debug, Error)]
+pub enum VaultError {
+    #[error("Crypto error: {0}")]
+    Crypto(#[from] CryptoError),
+    #[error("Input/Output error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Cannot parse vault file: {0}")]
+    Deserialize(#[from] toml::de::Error),
+    #[error("Cannot serialize vault file: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("Invalid hex encoding in vault file: {0}")]
+    Hex(#[from] hex::FromHexError),
+    #[error("Vault has not been initialized, please run `krypta init`")]
+    NotInitialized,
+    #[error("Vault has already been initialized")]
+    AlreadyInitialized,
+    #[error("Vault has no {0} key")]
+    MissingEntry(&'static str),
+    #[error("Wrong secret, cannot unlock the vault")]
+    WrongSecret,
+    #[error("Recipient {0} cannot unlock the vault")]
+    UnknownRecipient(String),
+    #[error("Recipient {0} has already been added")]
+    DuplicateRecipient(String),
+    #[error("A master key rotation is already in progress, please run `krypta rekey --master`")]
+    RotationInProgress,
+}
@@ -0,0 +1,712 @@
+//! The vault file holds the master key wrapped under one or more user secrets, such as a
+//! passphrase, a recovery key, a set of key shares or the public keys of its recipients.
+//! The master key itself is never written to disk and is used for wrapping each per-file key.
+
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crypto::{
+    crypt::{
+        combine_shares, derive_key_from_passphrase, generate_random_salt,
+        generate_random_secure_key, split_key, unwrap_key, unwrap_key_with_identity, wrap_key,
+        wrap_key_for_recipient, Identity, Recipient, RecoveryKey, SecretKey,
+    },
+    errors::CryptoError,
+};
+use serde::{Deserialize, Serialize};
+use utils::write_atomically;
+
+pub mod errors;
+use errors::{VaultError, VaultResult};
+
+/// The key that wraps every per-file key
+pub type MasterKey = SecretKey;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Vault {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default, rename = "entry")]
+    entries: Vec<VaultEntry>,
+}
+
+/// A copy of the master key, wrapped under some user secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum VaultEntry {
+    Passphrase {
+        salt: String,
+        wrapped_key: String,
+    },
+    Recovery {
+        wrapped_key: String,
+    },
+    Shares {
+        threshold: u8,
+        wrapped_key: String,
+    },
+    Recipient {
+        public_key: String,
+        wrapped_key: String,
+    },
+    /// The old master key, wrapped under the new one while a rotation is in progress
+    Previous {
+        wrapped_key: String,
+    },
+}
+
+impl VaultEntry {
+    fn kind(&self) -> &'static str {
+        match self {
+            VaultEntry::Passphrase { .. } => "passphrase",
+            VaultEntry::Recovery { .. } => "recovery",
+            VaultEntry::Shares { .. } => "shares",
+            VaultEntry::Recipient { .. } => "recipient",
+            VaultEntry::Previous { .. } => "previous",
+        }
+    }
+}
+
+impl Vault {
+    /// Create a new vault at `path` with a random master key, wrapped under both `passphrase`
+    /// and a freshly generated recovery key
+    pub fn create(
+        path: impl AsRef<Path>,
+        passphrase: impl AsRef<str>,
+    ) -> VaultResult<(Vault, MasterKey, RecoveryKey)> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            return Err(VaultError::AlreadyInitialized);
+        }
+
+        let master_key = generate_random_secure_key();
+        let (recovery_entry, recovery_key) = recovery_entry(&master_key)?;
+
+        let vault = Vault {
+            path: path.to_path_buf(),
+            entries: vec![passphrase_entry(&master_key, passphrase)?, recovery_entry],
+        };
+        vault.save()?;
+
+        log::trace!("Created new vault in {path:?}");
+
+        Ok((vault, master_key, recovery_key))
+    }
+
+    /// Open an existing vault file
+    pub fn open(path: impl AsRef<Path>) -> VaultResult<Vault> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Err(VaultError::NotInitialized);
+        }
+
+        let mut f = File::open(path)?;
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+
+        let mut vault: Vault = toml::from_str(&s)?;
+        vault.path = path.to_path_buf();
+
+        Ok(vault)
+    }
+
+    /// Persist the vault to disk
+    fn save(&self) -> VaultResult<()> {
+        let s = toml::to_string_pretty(self)?;
+
+        // The old vault is intact until the new one is on disk: losing the vault loses the
+        // master key
+        write_atomically(&self.path, s.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Get the master key using the passphrase
+    pub fn unlock_with_passphrase(&self, passphrase: impl AsRef<str>) -> VaultResult<MasterKey> {
+        let (salt, wrapped_key) = self
+            .entries
+            .iter()
+            .find_map(|entry| match entry {
+                VaultEntry::Passphrase { salt, wrapped_key } => Some((salt, wrapped_key)),
+                _ => None,
+            })
+            .ok_or(VaultError::MissingEntry("passphrase"))?;
+
+        let salt = hex::decode(salt)?;
+        let passphrase_key = derive_key_from_passphrase(passphrase.as_ref(), &salt)?;
+
+        unwrap_master_key(&passphrase_key, wrapped_key)
+    }
+
+    /// Get the master key using the recovery key
+    pub fn unlock_with_recovery_key(&self, recovery_key: &RecoveryKey) -> VaultResult<MasterKey> {
+        let wrapped_key = self
+            .entries
+            .iter()
+            .find_map(|entry| match entry {
+                VaultEntry::Recovery { wrapped_key } => Some(wrapped_key),
+                _ => None,
+            })
+            .ok_or(VaultError::MissingEntry("recovery"))?;
+
+        unwrap_master_key(recovery_key.key(), wrapped_key)
+    }
+
+    /// Get the master key using at least `threshold` shares
+    pub fn unlock_with_shares(&self, shares: &[impl AsRef<str>]) -> VaultResult<MasterKey> {
+        let threshold = self.shares_threshold()?;
+        let wrapped_key = self
+            .entries
+            .iter()
+            .find_map(|entry| match entry {
+                VaultEntry::Shares { wrapped_key, .. } => Some(wrapped_key),
+                _ => None,
+            })
+            .ok_or(VaultError::MissingEntry("shares"))?;
+
+        let shares_key = combine_shares(threshold, shares).map_err(|error| match error {
+            CryptoError::InvalidShare | CryptoError::InvalidKeyLength(_) => VaultError::WrongSecret,
+            error => VaultError::Crypto(error),
+        })?;
+
+        unwrap_master_key(&shares_key, wrapped_key)
+    }
+
+    /// Whether the vault can be unlocked with a passphrase
+    pub fn has_passphrase(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| matches!(entry, VaultEntry::Passphrase { .. }))
+    }
+
+    /// The number of shares needed for unlocking the vault
+    pub fn shares_threshold(&self) -> VaultResult<u8> {
+        self.entries
+            .iter()
+            .find_map(|entry| match entry {
+                VaultEntry::Shares { threshold, .. } => Some(*threshold),
+                _ => None,
+            })
+            .ok_or(VaultError::MissingEntry("shares"))
+    }
+
+    /// Wrap the master key under a random key which is then split into `count` shares, any
+    /// `threshold` of which can unlock the vault. Previously generated shares stop working
+    pub fn split_into_shares(
+        &mut self,
+        master_key: &MasterKey,
+        threshold: u8,
+        count: u8,
+    ) -> VaultResult<Vec<String>> {
+        let shares_key = generate_random_secure_key();
+        let shares = split_key(&shares_key, threshold, count)?;
+        let wrapped_key = wrap_key(&shares_key, master_key)?;
+
+        self.replace_entry(VaultEntry::Shares {
+            threshold,
+            wrapped_key: hex::encode(wrapped_key),
+        })?;
+
+        Ok(shares)
+    }
+
+    /// Remove the passphrase, so that the vault can only be unlocked with the shares or the
+    /// recovery key
+    pub fn remove_passphrase(&mut self) -> VaultResult<()> {
+        if !self
+            .entries
+            .iter()
+            .any(|entry| matches!(entry, VaultEntry::Shares { .. }))
+        {
+            return Err(VaultError::MissingEntry("shares"));
+        }
+
+        self.entries
+            .retain(|entry| !matches!(entry, VaultEntry::Passphrase { .. }));
+
+        self.save()
+    }
+
+    /// Get the master key using the identity of one of the recipients
+    pub fn unlock_with_identity(&self, identity: &Identity) -> VaultResult<MasterKey> {
+        let public_key = identity.to_recipient().to_string();
+
+        let wrapped_key = self
+            .entries
+            .iter()
+            .find_map(|entry| match entry {
+                VaultEntry::Recipient {
+                    public_key: p,
+                    wrapped_key,
+                } if p == &public_key => Some(wrapped_key),
+                _ => None,
+            })
+            .ok_or(VaultError::UnknownRecipient(public_key))?;
+
+        let wrapped_key = hex::decode(wrapped_key)?;
+        unwrap_key_with_identity(identity, &wrapped_key).map_err(|error| match error {
+            CryptoError::KeyUnwrap => VaultError::WrongSecret,
+            error => VaultError::Crypto(error),
+        })
+    }
+
+    /// Get the recipients that can unlock the vault
+    pub fn recipients(&self) -> VaultResult<Vec<Recipient>> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry {
+                VaultEntry::Recipient { public_key, .. } => Some(public_key.parse()),
+                _ => None,
+            })
+            .collect::<Result<Vec<_>, CryptoError>>()
+            .map_err(VaultError::Crypto)
+    }
+
+    /// Wrap the master key for a new recipient
+    pub fn add_recipient(
+        &mut self,
+        master_key: &MasterKey,
+        recipient: &Recipient,
+    ) -> VaultResult<()> {
+        let public_key = recipient.to_string();
+
+        if self.recipients()?.contains(recipient) {
+            return Err(VaultError::DuplicateRecipient(public_key));
+        }
+
+        let wrapped_key = wrap_key_for_recipient(recipient, master_key)?;
+
+        self.entries.push(VaultEntry::Recipient {
+            public_key,
+            wrapped_key: hex::encode(wrapped_key),
+        });
+
+        self.save()
+    }
+
+    /// Remove a recipient, which won't be able to unlock the vault anymore
+    pub fn remove_recipient(&mut self, recipient: &Recipient) -> VaultResult<()> {
+        let public_key = recipient.to_string();
+        let len = self.entries.len();
+
+        self.entries.retain(|entry| {
+            !matches!(entry, VaultEntry::Recipient { public_key: p, .. } if p == &public_key)
+        });
+
+        if self.entries.len() == len {
+            return Err(VaultError::UnknownRecipient(public_key));
+        }
+
+        self.save()
+    }
+
+    /// Replace the master key with a new random one. The passphrase is replaced with
+    /// `passphrase`, or removed if it is `None`, a new recovery key is generated, the
+    /// recipients get the new key and the shares are dropped, as they need to be handed
+    /// out again. The old master key is kept wrapped under the new one until
+    /// `forget_previous_master_key` is called, so that per-file keys can be rotated.
+    ///
+    /// The new recovery key is handed to `show_recovery_key` before the vault is written,
+    /// once: until then the old secrets keep working, as the vault may hold nothing else
+    pub fn rotate_master_key(
+        &mut self,
+        master_key: &MasterKey,
+        passphrase: Option<&str>,
+        show_recovery_key: impl FnOnce(&RecoveryKey),
+    ) -> VaultResult<MasterKey> {
+        if self.previous_master_key(master_key)?.is_some() {
+            return Err(VaultError::RotationInProgress);
+        }
+
+        let recipients = self.recipients()?;
+        let new_master_key = generate_random_secure_key();
+
+        let mut entries = vec![VaultEntry::Previous {
+            wrapped_key: hex::encode(wrap_key(&new_master_key, master_key)?),
+        }];
+
+        for recipient in recipients {
+            entries.push(VaultEntry::Recipient {
+                public_key: recipient.to_string(),
+                wrapped_key: hex::encode(wrap_key_for_recipient(&recipient, &new_master_key)?),
+            });
+        }
+
+        if let Some(passphrase) = passphrase {
+            entries.push(passphrase_entry(&new_master_key, passphrase)?);
+        }
+
+        let (recovery_entry, recovery_key) = recovery_entry(&new_master_key)?;
+        entries.push(recovery_entry);
+
+        let rotated = Vault {
+            path: self.path.clone(),
+            entries,
+        };
+
+        show_recovery_key(&recovery_key);
+        rotated.save()?;
+        *self = rotated;
+
+        Ok(new_master_key)
+    }
+
+    /// Get the old master key, if a rotation is in progress
+    pub fn previous_master_key(&self, master_key: &MasterKey) -> VaultResult<Option<MasterKey>> {
+        self.entries
+            .iter()
+            .find_map(|entry| match entry {
+                VaultEntry::Previous { wrapped_key } => Some(wrapped_key),
+                _ => None,
+            })
+            .map(|wrapped_key| unwrap_master_key(master_key, wrapped_key))
+            .transpose()
+    }
+
+    /// Drop the old master key, once no per-file key is wrapped with it anymore
+    pub fn forget_previous_master_key(&mut self) -> VaultResult<()> {
+        self.entries
+            .retain(|entry| !matches!(entry, VaultEntry::Previous { .. }));
+
+        self.save()
+    }
+
+    /// Wrap the master key under a new passphrase, replacing the old one
+    pub fn set_passphrase(
+        &mut self,
+        master_key: &MasterKey,
+        passphrase: impl AsRef<str>,
+    ) -> VaultResult<()> {
+        self.replace_entry(passphrase_entry(master_key, passphrase)?)
+    }
+
+    /// Generate a new recovery key, replacing the old one which stops working
+    pub fn rotate_recovery_key(&mut self, master_key: &MasterKey) -> VaultResult<RecoveryKey> {
+        let (entry, recovery_key) = recovery_entry(master_key)?;
+        self.replace_entry(entry)?;
+
+        Ok(recovery_key)
+    }
+
+    /// Replace the entry of the same kind, if any, and save the vault. Only meant for
+    /// entries that can appear once
+    fn replace_entry(&mut self, entry: VaultEntry) -> VaultResult<()> {
+        self.entries.retain(|e| e.kind() != entry.kind());
+        self.entries.push(entry);
+
+        self.save()
+    }
+}
+
+/// Wrap the master key under a key derived from `passphrase` with a new salt
+fn passphrase_entry(
+    master_key: &MasterKey,
+    passphrase: impl AsRef<str>,
+) -> VaultResult<VaultEntry> {
+    let salt = generate_random_salt();
+    let passphrase_key = derive_key_from_passphrase(passphrase.as_ref(), &salt)?;
+    let wrapped_key = wrap_key(&passphrase_key, master_key)?;
+
+    Ok(VaultEntry::Passphrase {
+        salt: hex::encode(salt),
+        wrapped_key: hex::encode(wrapped_key),
+    })
+}
+
+/// Wrap the master key under a new recovery key
+fn recovery_entry(master_key: &MasterKey) -> VaultResult<(VaultEntry, RecoveryKey)> {
+    let recovery_key = RecoveryKey::generate();
+    let wrapped_key = wrap_key(recovery_key.key(), master_key)?;
+
+    Ok((
+        VaultEntry::Recovery {
+            wrapped_key: hex::encode(wrapped_key),
+        },
+        recovery_key,
+    ))
+}
+
+/// Unwrap an hex encoded master key
+fn unwrap_master_key(wrapping_key: &SecretKey, wrapped_key: &str) -> VaultResult<MasterKey> {
+    let wrapped_key = hex::decode(wrapped_key)?;
+
+    unwrap_key(wrapping_key, &wrapped_key).map_err(|error| match error {
+        CryptoError::KeyUnwrap => VaultError::WrongSecret,
+        error => VaultError::Crypto(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir, metadata},
+        os::unix::fs::PermissionsExt,
+    };
+
+    use crypto::crypt::{Identity, RecoveryKey};
+    use tmp::Tmp;
+
+    use crate::{errors::VaultError, Vault};
+
+    #[test]
+    fn test_create_and_unlock() {
+        let tmp = Tmp::random();
+        let mut path = tmp.base_path();
+        path.push("krypta.vault");
+
+        let (_, master_key, recovery_key) = Vault::create(&path, "passphrase").unwrap();
+        assert!(path.is_file());
+
+        let vault = Vault::open(&path).unwrap();
+        assert_eq!(
+            vault.unlock_with_passphrase("passphrase").unwrap(),
+            master_key
+        );
+
+        let recovery_key = RecoveryKey::from_mnemonic(recovery_key.to_mnemonic()).unwrap();
+        assert_eq!(
+            vault.unlock_with_recovery_key(&recovery_key).unwrap(),
+            master_key
+        );
+
+        assert!(matches!(
+            vault.unlock_with_passphrase("wrong passphrase"),
+            Err(VaultError::WrongSecret)
+        ));
+        assert!(matches!(
+            vault.unlock_with_recovery_key(&RecoveryKey::generate()),
+            Err(VaultError::WrongSecret)
+        ));
+
+        assert!(matches!(
+            Vault::create(&path, "passphrase"),
+            Err(VaultError::AlreadyInitialized)
+        ));
+    }
+
+    #[test]
+    fn test_save_replaces_the_vault() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("krypta.vault");
+
+        let (mut vault, master_key, _) = Vault::create(&path, "passphrase").unwrap();
+        vault.set_passphrase(&master_key, "new passphrase").unwrap();
+
+        assert!(!tmp.base_path().join("krypta.vault.tmp").exists());
+        assert_eq!(metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let vault = Vault::open(&path).unwrap();
+        assert_eq!(
+            vault.unlock_with_passphrase("new passphrase").unwrap(),
+            master_key
+        );
+    }
+
+    #[test]
+    fn test_open_not_initialized() {
+        let tmp = Tmp::random();
+        let mut path = tmp.base_path();
+        path.push("krypta.vault");
+
+        assert!(matches!(
+            Vault::open(&path),
+            Err(VaultError::NotInitialized)
+        ));
+    }
+
+    #[test]
+    fn test_recover_and_rotate() {
+        let tmp = Tmp::random();
+        let mut path = tmp.base_path();
+        path.push("krypta.vault");
+
+        let (_, master_key, old_recovery_key) = Vault::create(&path, "forgotten").unwrap();
+
+        // Recover access and set a new passphrase
+        let mut vault = Vault::open(&path).unwrap();
+        let recovered = vault.unlock_with_recovery_key(&old_recovery_key).unwrap();
+        vault.set_passphrase(&recovered, "new passphrase").unwrap();
+
+        // Display a fresh recovery key
+        let new_recovery_key = vault.rotate_recovery_key(&recovered).unwrap();
+
+        let vault = Vault::open(&path).unwrap();
+        assert!(vault.unlock_with_passphrase("forgotten").is_err());
+        assert_eq!(
+            vault.unlock_with_passphrase("new passphrase").unwrap(),
+            master_key
+        );
+        assert!(vault.unlock_with_recovery_key(&old_recovery_key).is_err());
+        assert_eq!(
+            vault.unlock_with_recovery_key(&new_recovery_key).unwrap(),
+            master_key
+        );
+    }
+
+    #[test]
+    fn test_split_and_unlock_with_shares() {
+        let tmp = Tmp::random();
+        let mut path = tmp.base_path();
+        path.push("krypta.vault");
+
+        let (mut vault, master_key, _) = Vault::create(&path, "passphrase").unwrap();
+
+        assert!(matches!(
+            vault.remove_passphrase(),
+            Err(VaultError::MissingEntry("shares"))
+        ));
+
+        let shares = vault.split_into_shares(&master_key, 3, 5).unwrap();
+        vault.remove_passphrase().unwrap();
+
+        let vault = Vault::open(&path).unwrap();
+        assert!(!vault.has_passphrase());
+        assert_eq!(vault.shares_threshold().unwrap(), 3);
+        assert!(vault.unlock_with_passphrase("passphrase").is_err());
+
+        assert_eq!(vault.unlock_with_shares(&shares[1..4]).unwrap(), master_key);
+        assert!(matches!(
+            vault.unlock_with_shares(&shares[0..2]),
+            Err(VaultError::WrongSecret)
+        ));
+    }
+
+    #[test]
+    fn test_recipients() {
+        let tmp = Tmp::random();
+        let mut path = tmp.base_path();
+        path.push("krypta.vault");
+
+        let (mut vault, master_key, _) = Vault::create(&path, "passphrase").unwrap();
+
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+
+        vault
+            .add_recipient(&master_key, &alice.to_recipient())
+            .unwrap();
+        vault
+            .add_recipient(&master_key, &bob.to_recipient())
+            .unwrap();
+        assert!(matches!(
+            vault.add_recipient(&master_key, &bob.to_recipient()),
+            Err(VaultError::DuplicateRecipient(_))
+        ));
+
+        let mut vault = Vault::open(&path).unwrap();
+        assert_eq!(
+            vault.recipients().unwrap(),
+            vec![alice.to_recipient(), bob.to_recipient()]
+        );
+
+        assert_eq!(vault.unlock_with_identity(&alice).unwrap(), master_key);
+        assert_eq!(vault.unlock_with_identity(&bob).unwrap(), master_key);
+        assert!(matches!(
+            vault.unlock_with_identity(&eve),
+            Err(VaultError::UnknownRecipient(_))
+        ));
+
+        vault.remove_recipient(&alice.to_recipient()).unwrap();
+        assert!(vault.remove_recipient(&alice.to_recipient()).is_err());
+
+        let vault = Vault::open(&path).unwrap();
+        assert_eq!(vault.recipients().unwrap(), vec![bob.to_recipient()]);
+        assert!(vault.unlock_with_identity(&alice).is_err());
+        assert_eq!(vault.unlock_with_identity(&bob).unwrap(), master_key);
+
+        // Other secrets still work
+        assert_eq!(
+            vault.unlock_with_passphrase("passphrase").unwrap(),
+            master_key
+        );
+    }
+
+    #[test]
+    fn test_rotate_master_key() {
+        let tmp = Tmp::random();
+        let mut path = tmp.base_path();
+        path.push("krypta.vault");
+
+        let (mut vault, master_key, old_recovery_key) = Vault::create(&path, "old").unwrap();
+        let alice = Identity::generate();
+        vault
+            .add_recipient(&master_key, &alice.to_recipient())
+            .unwrap();
+        vault.split_into_shares(&master_key, 2, 3).unwrap();
+
+        assert!(vault.previous_master_key(&master_key).unwrap().is_none());
+
+        let mut new_recovery_key = None;
+        let new_master_key = vault
+            .rotate_master_key(&master_key, Some("new"), |recovery_key| {
+                new_recovery_key = Some(recovery_key.to_mnemonic())
+            })
+            .unwrap();
+        let new_recovery_key = RecoveryKey::from_mnemonic(new_recovery_key.unwrap()).unwrap();
+        assert_ne!(new_master_key, master_key);
+
+        assert!(matches!(
+            vault.rotate_master_key(&new_master_key, None, |_| ()),
+            Err(VaultError::RotationInProgress)
+        ));
+
+        let mut vault = Vault::open(&path).unwrap();
+        assert_eq!(
+            vault.previous_master_key(&new_master_key).unwrap(),
+            Some(master_key)
+        );
+
+        assert_eq!(vault.unlock_with_passphrase("new").unwrap(), new_master_key);
+        assert!(vault.unlock_with_passphrase("old").is_err());
+        assert_eq!(vault.unlock_with_identity(&alice).unwrap(), new_master_key);
+        assert_eq!(
+            vault.unlock_with_recovery_key(&new_recovery_key).unwrap(),
+            new_master_key
+        );
+        assert!(vault.unlock_with_recovery_key(&old_recovery_key).is_err());
+        assert!(vault.shares_threshold().is_err());
+
+        vault.forget_previous_master_key().unwrap();
+
+        let vault = Vault::open(&path).unwrap();
+        assert!(vault
+            .previous_master_key(&new_master_key)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_failed_rotation_keeps_the_old_secrets() {
+        let tmp = Tmp::random();
+        let path = tmp.base_path().join("krypta.vault");
+
+        let (mut vault, master_key, recovery_key) = Vault::create(&path, "old").unwrap();
+        let shares = vault.split_into_shares(&master_key, 2, 3).unwrap();
+        vault.remove_passphrase().unwrap();
+
+        // The copy of the vault cannot be written
+        create_dir(tmp.base_path().join("krypta.vault.tmp")).unwrap();
+
+        let mut shown = false;
+        assert!(vault
+            .rotate_master_key(&master_key, Some("new"), |_| shown = true)
+            .is_err());
+        assert!(shown);
+
+        for vault in [vault, Vault::open(&path).unwrap()] {
+            assert!(vault.previous_master_key(&master_key).unwrap().is_none());
+            assert!(vault.unlock_with_passphrase("new").is_err());
+            assert_eq!(
+                vault.unlock_with_recovery_key(&recovery_key).unwrap(),
+                master_key
+            );
+            assert_eq!(vault.unlock_with_shares(&shares[..2]).unwrap(), master_key);
+        }
+    }
+}
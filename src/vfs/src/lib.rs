@@ -2,19 +2,31 @@
 
 use std::{
     ffi::OsStr,
+    os::unix::ffi::OsStrExt,
     path::Path,
-    time::{Duration, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use database::{models, traits::FetchAll, Database};
-use fuse::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyDirectory, ReplyEntry, Request};
-use libc::ENOENT;
+use crypto::crypt::KeyWrapper;
+use database::{
+    models::{self, EntryKind},
+    traits::FetchAll,
+    EncryptedDatabase,
+};
+use fuse::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+use libc::{EINVAL, EIO, ENOENT};
+
+const TTL: Duration = Duration::from_secs(1);
 
-pub struct KryptaFS {
-    db: Database,
+/// `master_key` unwraps the per-file keys, which seal the targets of symlinks
+pub struct KryptaFS<K> {
+    db: EncryptedDatabase,
+    master_key: K,
 }
 
-impl Filesystem for KryptaFS {
+impl<K: KeyWrapper> Filesystem for KryptaFS<K> {
     //fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
     //let attr = FileAttr {
     //ino: 2,
@@ -36,30 +48,50 @@ impl Filesystem for KryptaFS {
     //reply.entry(&Duration::from_secs(1), &attr, 0);
     //}
 
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-        let root_dir: FileAttr = FileAttr {
-            ino: 1,
-            size: 0,
-            blocks: 0,
-            atime: UNIX_EPOCH,
-            mtime: UNIX_EPOCH,
-            ctime: UNIX_EPOCH,
-            crtime: UNIX_EPOCH,
-            kind: FileType::Directory,
-            perm: 0o755,
-            nlink: 2,
-            uid: 501,
-            gid: 20,
-            rdev: 0,
-            flags: 0,
+    fn getattr(&mut self, req: &Request, ino: u64, reply: ReplyAttr) {
+        if ino == 1 {
+            let root_dir: FileAttr = FileAttr {
+                ino: 1,
+                size: 0,
+                blocks: 0,
+                atime: UNIX_EPOCH,
+                mtime: UNIX_EPOCH,
+                ctime: UNIX_EPOCH,
+                crtime: UNIX_EPOCH,
+                kind: FileType::Directory,
+                perm: 0o755,
+                nlink: 2,
+                uid: req.uid(),
+                gid: req.gid(),
+                rdev: 0,
+                flags: 0,
+            };
+
+            reply.attr(&TTL, &root_dir);
+            return;
+        }
+
+        match self.entry(ino) {
+            Some(file) => reply.attr(&TTL, &self.file_attr(req, ino, &file)),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        let file = match self.entry(ino) {
+            Some(file) => file,
+            None => return reply.error(ENOENT),
         };
 
-        reply.attr(&Duration::from_secs(1), &root_dir);
+        if file.kind != EntryKind::Symlink {
+            return reply.error(EINVAL);
+        }
 
-        //match ino {
-        //1 => reply.attr(&Duration::from_secs(1), &root_dir),
-        //_ => reply.error(ENOENT),
-        //}
+        match file.symlink_target(&self.master_key) {
+            Ok(Some(target)) => reply.data(target.as_os_str().as_bytes()),
+            // A symlink without a target or whose target cannot be unsealed
+            Ok(None) | Err(_) => reply.error(EIO),
+        }
     }
 
     fn readdir(
@@ -83,11 +115,13 @@ impl Filesystem for KryptaFS {
         ];
 
         for (i, file) in files.into_iter().enumerate() {
-            entries.push((
-                (i + 2) as u64,
-                FileType::RegularFile,
-                file.title.to_string(),
-            ));
+            let kind = match file.kind {
+                EntryKind::File => FileType::RegularFile,
+                EntryKind::Symlink => FileType::Symlink,
+                EntryKind::Directory => FileType::Directory,
+            };
+
+            entries.push(((i + 2) as u64, kind, file.title.to_string()));
         }
 
         for (i, entry) in entries.into_iter().enumerate().skip(offset as usize) {
@@ -99,14 +133,61 @@ impl Filesystem for KryptaFS {
     }
 }
 
-impl KryptaFS {
-    pub fn mount(mountpoint: impl AsRef<Path>) {
+impl<K: KeyWrapper> KryptaFS<K> {
+    pub fn mount(mountpoint: impl AsRef<Path>, db: EncryptedDatabase, master_key: K) {
         let options = ["-o", "ro", "-o", "local"]
             .iter()
             .map(|o| o.as_ref())
             .collect::<Vec<&OsStr>>();
 
-        let db = database::connect_or_create().unwrap();
-        fuse::mount(KryptaFS { db }, mountpoint, &options).unwrap();
+        fuse::mount(KryptaFS { db, master_key }, mountpoint, &options).unwrap();
+    }
+
+    /// The entry with inode `ino`, numbered like in `readdir`
+    fn entry(&self, ino: u64) -> Option<models::File> {
+        let index = ino.checked_sub(2)? as usize;
+
+        models::File::fetch_all(&self.db)
+            .ok()?
+            .into_iter()
+            .nth(index)
+    }
+
+    /// The attributes of `file` as they were in the source, the requesting user owns what
+    /// was added without an owner
+    fn file_attr(&self, req: &Request, ino: u64, file: &models::File) -> FileAttr {
+        let (kind, perm, nlink, size) = match file.kind {
+            EntryKind::File => (FileType::RegularFile, 0o644, 1, file.size),
+            EntryKind::Symlink => {
+                // The size of a symlink is the length of its target
+                let size = file
+                    .symlink_target(&self.master_key)
+                    .ok()
+                    .flatten()
+                    .map_or(0, |target| target.as_os_str().len() as u64);
+
+                (FileType::Symlink, 0o777, 1, size)
+            }
+            EntryKind::Directory => (FileType::Directory, 0o755, 2, 0),
+        };
+
+        let modified_at = file.modified_at.map_or(UNIX_EPOCH, SystemTime::from);
+
+        FileAttr {
+            ino,
+            size,
+            blocks: (size + 511) / 512,
+            atime: file.accessed_at.map_or(modified_at, SystemTime::from),
+            mtime: modified_at,
+            ctime: SystemTime::from(file.updated_at),
+            crtime: SystemTime::from(file.created_at),
+            kind,
+            perm: file.mode.map_or(perm, |mode| (mode & 0o7777) as u16),
+            nlink,
+            uid: file.uid.unwrap_or_else(|| req.uid()),
+            gid: file.gid.unwrap_or_else(|| req.gid()),
+            rdev: 0,
+            flags: 0,
+        }
     }
 }
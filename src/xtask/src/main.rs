@@ -1,5 +1,6 @@
-use std::{env, mem::forget, process::Command};
+use std::{env, mem::forget, process::Command, time::Instant};
 
+use fs::PathFinder;
 use rand::{prelude::SmallRng, SeedableRng};
 use tmp::{RandomFill, Tmp};
 
@@ -10,6 +11,7 @@ fn main() {
     match args.next().unwrap().as_str() {
         "populate-unlocked" => populate_unlocked(),
         "coverage" => coverage(),
+        "bench-walk" => bench_walk(args.next()),
         _ => panic!("xtask: invalid argument"),
     };
 }
@@ -48,3 +50,41 @@ fn populate_unlocked() {
 
     forget(tmp);
 }
+
+/// Time `PathFinder` with one thread and with all of them, on `path` if given, otherwise
+/// on random files
+fn bench_walk(path: Option<String>) {
+    let tmp = Tmp::random();
+
+    let source_path = match path {
+        Some(path) => path.into(),
+        None => {
+            println!("Generating random files at {:?}", tmp.base_path());
+
+            let mut rng = SmallRng::seed_from_u64(0);
+            tmp.random_fill(100_000, &mut rng).unwrap();
+
+            tmp.base_path()
+        }
+    };
+
+    for threads in [1, 0] {
+        let start = Instant::now();
+
+        let path_finder = PathFinder::builder(&source_path)
+            .threads(threads)
+            .build()
+            .unwrap();
+
+        let threads = match threads {
+            0 => "all".to_string(),
+            threads => threads.to_string(),
+        };
+
+        println!(
+            "{threads} threads: found {} files in {:?}",
+            path_finder.metadatas.len(),
+            start.elapsed()
+        );
+    }
+}